@@ -10,6 +10,41 @@ use crate::{
 
 pub const APU_SAMPLE_RATE: usize = 1048576;
 
+/// A snapshot of a single sound channel, for inspection.
+#[cfg(feature = "debug")]
+#[derive(Clone, Copy, Debug)]
+pub struct ChannelState {
+    /// Whether the channel is playing, as reported in `NR52`.
+    pub active: bool,
+    pub dac_enabled: bool,
+    /// The current volume, from 0 to 15.
+    pub volume: u8,
+    /// The frequency of the waveform in Hz, or of the LFSR clock for the noise channel.
+    pub frequency: f32,
+    /// Whether the channel stops when its length timer runs out.
+    pub length_enabled: bool,
+    /// Whether the channel is sent to the left output.
+    pub left: bool,
+    /// Whether the channel is sent to the right output.
+    pub right: bool,
+}
+
+/// A snapshot of the APU, for inspection.
+#[cfg(feature = "debug")]
+#[derive(Clone, Copy, Debug)]
+pub struct ApuState {
+    pub enabled: bool,
+    pub nr50: u8,
+    pub nr51: u8,
+    pub nr52: u8,
+    /// The master volume of the left output, from 1 to 8.
+    pub left_volume: u8,
+    /// The master volume of the right output, from 1 to 8.
+    pub right_volume: u8,
+    pub channels: [ChannelState; 4],
+}
+
+#[derive(Clone)]
 pub struct Apu<S>
 where
     S: Speaker,
@@ -68,6 +103,31 @@ where
         self.speaker.push_sample(left_sample, right_sample);
     }
 
+    /// Returns a snapshot of the APU registers and of every channel.
+    #[cfg(feature = "debug")]
+    pub fn state(&self) -> ApuState {
+        let channels = [
+            self.ch1.state(),
+            self.ch2.state(),
+            self.ch3.state(),
+            self.ch4.state(),
+        ];
+        ApuState {
+            enabled: self.master.apu_enabled(),
+            nr50: self.master.volume,
+            nr51: self.master.panning,
+            nr52: self.read(0xff26),
+            left_volume: self.master.left_volume(),
+            right_volume: self.master.right_volume(),
+            channels: std::array::from_fn(|idx| ChannelState {
+                active: (self.master.control >> idx) & 1 != 0,
+                left: self.master.channel_left(idx),
+                right: self.master.channel_right(idx),
+                ..channels[idx]
+            }),
+        }
+    }
+
     pub fn read(&self, addr: u16) -> u8 {
         match addr {
             0xff10 => self.ch1.nrx0 | 0x80,
@@ -99,6 +159,18 @@ where
         }
     }
 
+    /// Writes `value` to the register at `addr` like [`Apu::write`], except that setting the
+    /// trigger bit of `NRx4` doesn't restart the channel.
+    pub fn poke(&mut self, addr: u16, value: u8) {
+        match addr {
+            0xff14 => self.ch1.nrx4 = value & 0xc7,
+            0xff19 => self.ch2.nrx4 = value & 0xc7,
+            0xff1e => self.ch3.nrx4 = value & 0xc7,
+            0xff23 => self.ch4.nrx4 = value & 0xc0,
+            _ => self.write(addr, value),
+        }
+    }
+
     pub fn write(&mut self, addr: u16, value: u8) {
         match addr {
             0xff10 => self.ch1.nrx0 = value & 0x7f,
@@ -155,14 +227,14 @@ where
     }
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub enum SweepDir {
     #[default]
     Decrease,
     Increase,
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct LengthTimer {
     enabled: bool,
     length: u8,
@@ -194,7 +266,7 @@ impl LengthTimer {
     }
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct VolumeEnvelope {
     volume: u8,
     direction: SweepDir,
@@ -254,7 +326,7 @@ impl SweepControl for () {
     }
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct PeriodSweep {
     period: u16,
     direction: SweepDir,
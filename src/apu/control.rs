@@ -1,3 +1,4 @@
+#[derive(Clone)]
 pub struct MasterControl {
     pub control: u8,
     pub panning: u8,
@@ -1,10 +1,14 @@
+#[cfg(feature = "debug")]
+use crate::apu::{ChannelState, APU_SAMPLE_RATE};
 use crate::apu::{LengthTimer, SweepDir, VolumeEnvelope};
 
+#[derive(Clone)]
 pub enum LfsrWidth {
     B15 = 0,
     B7 = 1,
 }
 
+#[derive(Clone)]
 pub struct Noise {
     volume_envelope: VolumeEnvelope,
     length_timer: LengthTimer,
@@ -110,4 +114,18 @@ impl Noise {
         }
         Some(volume * (self.lfsr & 1) as f32)
     }
+
+    /// Returns a snapshot of the channel, without the fields kept by the master control.
+    #[cfg(feature = "debug")]
+    pub fn state(&self) -> ChannelState {
+        ChannelState {
+            active: false,
+            dac_enabled: self.nrx2 & 0xf8 != 0,
+            volume: self.volume_envelope.volume,
+            frequency: APU_SAMPLE_RATE as f32 / self.period() as f32,
+            length_enabled: self.length_enabled(),
+            left: false,
+            right: false,
+        }
+    }
 }
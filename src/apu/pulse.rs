@@ -1,7 +1,10 @@
+#[cfg(feature = "debug")]
+use crate::apu::{ChannelState, APU_SAMPLE_RATE};
 use crate::apu::{
     LengthTimer, PeriodSweep, PeriodSweepResult, SweepControl, SweepDir, VolumeEnvelope,
 };
 
+#[derive(Clone)]
 pub struct Pulse<const FS: bool>
 where
     Self: SweepRegs,
@@ -112,11 +115,26 @@ where
         }
         Some(volume * self.waveform()[self.waveform_idx] as f32)
     }
+
+    /// Returns a snapshot of the channel, without the fields kept by the master control.
+    #[cfg(feature = "debug")]
+    pub fn state(&self) -> ChannelState {
+        ChannelState {
+            active: false,
+            dac_enabled: self.nrx2 & 0xf8 != 0,
+            volume: self.volume_envelope.volume,
+            frequency: APU_SAMPLE_RATE as f32
+                / (self.period() as usize * Self::WAVEFORM_SIZE) as f32,
+            length_enabled: self.length_enabled(),
+            left: false,
+            right: false,
+        }
+    }
 }
 
 pub trait SweepRegs {
-    type SweepType: Default + SweepControl;
-    type SweepRegType: Default;
+    type SweepType: Default + SweepControl + Clone;
+    type SweepRegType: Default + Clone;
 
     fn sweep_step(&self) -> u8;
     fn sweep_direction(&self) -> SweepDir;
@@ -1,5 +1,8 @@
 use crate::apu::LengthTimer;
+#[cfg(feature = "debug")]
+use crate::apu::{ChannelState, APU_SAMPLE_RATE};
 
+#[derive(Clone)]
 pub struct Wave {
     length_timer: LengthTimer,
     waveform_idx: usize,
@@ -58,7 +61,7 @@ impl Wave {
 
     fn waveform_sample(&self, idx: usize) -> u8 {
         let byte = self.waveform[idx / 2];
-        if idx % 2 == 0 {
+        if idx.is_multiple_of(2) {
             (byte >> 4) & 0x0f
         } else {
             byte & 0x0f
@@ -85,4 +88,19 @@ impl Wave {
         }
         Some(volume * (self.waveform_sample(self.waveform_idx) as f32 / 15.))
     }
+
+    /// Returns a snapshot of the channel, without the fields kept by the master control.
+    #[cfg(feature = "debug")]
+    pub fn state(&self) -> ChannelState {
+        ChannelState {
+            active: false,
+            dac_enabled: self.dac_enabled(),
+            volume: (self.volume() * 15.) as u8,
+            frequency: APU_SAMPLE_RATE as f32
+                / (self.period().max(1) as usize * Self::WAVEFORM_SIZE) as f32,
+            length_enabled: self.length_enabled(),
+            left: false,
+            right: false,
+        }
+    }
 }
@@ -0,0 +1,583 @@
+use crate::{
+    expr::{Context, Expr, IO_REGISTERS},
+    peripherals::SharedFrame,
+    source::SourceMap,
+    DebugGameboy,
+};
+use gabbro::{ButtonState, Event, Mnemonic, SymbolTable};
+use serde_json::{json, Value};
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    mem,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::mpsc::{self, Receiver, Sender, TryRecvError},
+    thread,
+};
+
+/// The ID of the only thread, the CPU.
+const THREAD: u64 = 1;
+
+/// The number of steps the program runs at a time, before checking for new requests.
+const STEPS_PER_SLICE: usize = 4096;
+
+/// The variables references of the scopes.
+const REGISTERS: u64 = 1;
+const IO: u64 = 2;
+
+/// Describes whether a running program should stop.
+type StopCondition = Box<dyn FnMut(&Session) -> bool>;
+
+/// A program running until a condition is met, a breakpoint is hit, or it is paused.
+struct Run {
+    done: StopCondition,
+    /// The reason reported when `done` stops the program.
+    reason: &'static str,
+}
+
+/// A launched program, with the symbols and sources it was built from.
+struct Session {
+    gameboy: DebugGameboy,
+    symbols: SymbolTable,
+    sources: SourceMap,
+    rom: Vec<u8>,
+    /// The ROM bank and address of the breakpoints, by source file.
+    breakpoints: HashMap<PathBuf, Vec<(usize, u16)>>,
+    /// The locations of all breakpoints, for quick lookup.
+    locations: HashSet<(usize, u16)>,
+    stop_on_entry: bool,
+}
+
+impl Session {
+    /// Returns the ROM bank and address of `addr`, as they appear in the symbol file.
+    fn location(&self, addr: u16) -> (usize, u16) {
+        match addr {
+            0x4000..=0x7fff => (self.gameboy.rom_bank(), addr),
+            _ => (0, addr),
+        }
+    }
+
+    /// Returns the source of the instruction at `addr` in `bank` as a DAP `Source`, and its line.
+    fn source(&self, bank: usize, addr: u16) -> Option<(Value, usize)> {
+        let (path, line) = self.sources.line_of(bank, addr)?;
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        Some((json!({ "name": name, "path": path }), line))
+    }
+
+    /// Returns a DAP `StackFrame` for the instruction at `addr` in `bank`.
+    fn frame(&self, id: usize, bank: usize, addr: u16) -> Value {
+        let name = match self.symbols.function(bank, addr) {
+            Some(label) => label.to_string(),
+            None => format!("{:#06x}", addr),
+        };
+        let mut frame = json!({
+            "id": id,
+            "name": name,
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": format!("{:#06x}", addr),
+        });
+        if let Some((source, line)) = self.source(bank, addr) {
+            frame["source"] = source;
+            frame["line"] = json!(line);
+            frame["column"] = json!(1);
+        }
+        frame
+    }
+}
+
+/// A Debug Adapter Protocol server, which lets editors debug a ROM on the source level.
+pub struct DapServer {
+    requests: Receiver<Value>,
+    writer: Box<dyn Write>,
+    /// The sequence number of the next message sent.
+    seq: u64,
+    /// Events to send after the response to the current request.
+    events: Vec<Value>,
+    session: Option<Session>,
+    running: Option<Run>,
+}
+
+impl DapServer {
+    /// Creates a server that reads requests from `reader` and writes responses to `writer`.
+    pub fn new<R, W>(reader: R, writer: W) -> Self
+    where
+        R: Read + Send + 'static,
+        W: Write + 'static,
+    {
+        let (sender, requests) = mpsc::channel();
+        // Requests are read on another thread, so that a running program can be paused.
+        thread::spawn(move || read_requests(BufReader::new(reader), sender));
+        Self {
+            requests,
+            writer: Box::new(writer),
+            seq: 1,
+            events: Vec::new(),
+            session: None,
+            running: None,
+        }
+    }
+
+    /// Serves requests until the client disconnects.
+    pub fn serve(mut self) -> io::Result<()> {
+        loop {
+            let request = if self.running.is_some() {
+                match self.requests.try_recv() {
+                    Ok(request) => Some(request),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            } else {
+                match self.requests.recv() {
+                    Ok(request) => Some(request),
+                    Err(_) => return Ok(()),
+                }
+            };
+            if let Some(request) = request {
+                if !self.handle(&request)? {
+                    return Ok(());
+                }
+            }
+            self.run_slice();
+            for event in mem::take(&mut self.events) {
+                self.send(event)?;
+            }
+        }
+    }
+
+    /// Handles `request` and responds to it. Returns whether to keep serving.
+    fn handle(&mut self, request: &Value) -> io::Result<bool> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let args = &request["arguments"];
+        let result = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsEvaluateForHovers": true,
+                "supportsSteppingGranularity": true,
+            })),
+            "launch" => self.launch(args),
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "configurationDone" => self.configuration_done(),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD, "name": "SM83" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS, "expensive": false },
+                { "name": "IO", "variablesReference": IO, "expensive": false },
+            ]})),
+            "variables" => self.variables(args),
+            "evaluate" => self.evaluate(args),
+            "continue" => self.resume(Box::new(|_| false), "breakpoint"),
+            "next" | "stepIn" | "stepOut" => self.step(command, args),
+            "pause" => {
+                if self.running.is_some() {
+                    self.stop("pause", None);
+                }
+                Ok(json!({}))
+            }
+            "disconnect" | "terminate" => Ok(json!({})),
+            _ => Err(format!("Unsupported request: {}", command)),
+        };
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": command,
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)?;
+        Ok(!matches!(command, "disconnect" | "terminate"))
+    }
+
+    fn session(&self) -> Result<&Session, String> {
+        self.session
+            .as_ref()
+            .ok_or_else(|| "No program launched".to_string())
+    }
+
+    fn session_mut(&mut self) -> Result<&mut Session, String> {
+        self.session
+            .as_mut()
+            .ok_or_else(|| "No program launched".to_string())
+    }
+
+    /// Loads the ROM at `program`, with the symbol file next to it and the sources under
+    /// `sourceRoot`, or the directory of the ROM.
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let program = args["program"].as_str().ok_or("Missing program")?;
+        let program = Path::new(program);
+        let rom = fs::read(program)
+            .map_err(|e| format!("Failed to read {}: {}", program.display(), e))?;
+
+        // RGBDS names the symbol file after the ROM.
+        let symbols_path = match args["symbols"].as_str() {
+            Some(path) => PathBuf::from(path),
+            None => program.with_extension("sym"),
+        };
+        let symbols = fs::read_to_string(&symbols_path)
+            .map(|src| SymbolTable::parse(&src))
+            .unwrap_or_default();
+
+        let root = match args["sourceRoot"].as_str() {
+            Some(path) => PathBuf::from(path),
+            None => program.parent().unwrap_or(Path::new(".")).to_path_buf(),
+        };
+        let mut sources = SourceMap::default();
+        sources
+            .load_dir(&root, &rom, &symbols)
+            .map_err(|e| format!("Failed to read sources in {}: {}", root.display(), e))?;
+
+        let mut gameboy = crate::build_gameboy(
+            rom.clone(),
+            SharedFrame::default(),
+            Rc::new(Cell::new(ButtonState::new())),
+        );
+        gameboy.track_calls(true);
+        gameboy.record_events(true);
+        self.session = Some(Session {
+            gameboy,
+            symbols,
+            sources,
+            rom,
+            breakpoints: HashMap::new(),
+            locations: HashSet::new(),
+            stop_on_entry: args["stopOnEntry"].as_bool().unwrap_or(false),
+        });
+        self.event("initialized", json!({}));
+        Ok(json!({}))
+    }
+
+    /// Replaces the breakpoints in a source file. Each breakpoint moves to the first line
+    /// at or after it with an instruction.
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let session = self.session_mut()?;
+        let path = args["source"]["path"]
+            .as_str()
+            .ok_or("Missing source path")?;
+        let path = PathBuf::from(path);
+        // Sources outside the source root are mapped once they get breakpoints.
+        let _ = session
+            .sources
+            .load_file(&path, &session.rom, &session.symbols);
+
+        let lines = args["breakpoints"].as_array().cloned().unwrap_or_default();
+        let mut locations = Vec::new();
+        let mut breakpoints = Vec::new();
+        for line in lines.iter().filter_map(|bp| bp["line"].as_u64()) {
+            match session.sources.resolve_line(&path, line as usize) {
+                Some((line, location)) => {
+                    locations.push(location);
+                    breakpoints.push(json!({ "verified": true, "line": line }));
+                }
+                None => breakpoints.push(json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "No instruction found at or after this line",
+                })),
+            }
+        }
+        session.breakpoints.insert(path, locations);
+        session.locations = session.breakpoints.values().flatten().copied().collect();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    /// Starts the program, or stops it at the entry point if the launch asked for it.
+    /// A breakpoint at the entry point stops the program before its first instruction.
+    fn configuration_done(&mut self) -> Result<Value, String> {
+        let session = self.session()?;
+        let entry = session.location(session.gameboy.regs().pc());
+        if session.stop_on_entry {
+            self.stop("entry", None);
+        } else if session.locations.contains(&entry) {
+            self.stop("breakpoint", None);
+        } else {
+            self.running = Some(Run {
+                done: Box::new(|_| false),
+                reason: "breakpoint",
+            });
+        }
+        Ok(json!({}))
+    }
+
+    /// Returns the current instruction and the call sites of the calls on the shadow call stack.
+    fn stack_trace(&self) -> Result<Value, String> {
+        let session = self.session()?;
+        let gb = &session.gameboy;
+        let (bank, pc) = session.location(gb.regs().pc());
+        let mut frames = vec![session.frame(0, bank, pc)];
+
+        let calls = gb.call_stack();
+        for (i, call) in calls.iter().enumerate().rev() {
+            // The caller runs in the bank of the call outside it, if any.
+            let bank = match call.return_addr {
+                0x4000..=0x7fff => i
+                    .checked_sub(1)
+                    .map_or(gb.rom_bank(), |outer| calls[outer].bank),
+                _ => 0,
+            };
+            // Show the call instruction rather than the return address, if it is in the sources.
+            let ret = call.return_addr;
+            let addr = match call.interrupt {
+                true => ret,
+                false => [ret.wrapping_sub(3), ret.wrapping_sub(1)]
+                    .into_iter()
+                    .find(|addr| session.sources.line_of(bank, *addr).is_some())
+                    .unwrap_or(ret),
+            };
+            frames.push(session.frame(frames.len(), bank, addr));
+        }
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    /// Returns the registers, or the IO registers.
+    fn variables(&self, args: &Value) -> Result<Value, String> {
+        let gb = &self.session()?.gameboy;
+        let var = |name: &str, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+        let variables = match args["variablesReference"].as_u64() {
+            Some(REGISTERS) => {
+                let regs = gb.regs();
+                let flags = regs.flags();
+                let mut vars = [
+                    ("A", regs.a()),
+                    ("B", regs.b()),
+                    ("C", regs.c()),
+                    ("D", regs.d()),
+                    ("E", regs.e()),
+                    ("H", regs.h()),
+                    ("L", regs.l()),
+                ]
+                .map(|(name, val)| var(name, format!("{:#04x}", val)))
+                .to_vec();
+                vars.extend(
+                    [
+                        ("AF", regs.af()),
+                        ("BC", regs.bc()),
+                        ("DE", regs.de()),
+                        ("HL", regs.hl()),
+                        ("SP", regs.sp()),
+                        ("PC", regs.pc()),
+                    ]
+                    .map(|(name, val)| var(name, format!("{:#06x}", val))),
+                );
+                let flags = [
+                    (flags.z(), 'Z'),
+                    (flags.n(), 'N'),
+                    (flags.h(), 'H'),
+                    (flags.c(), 'C'),
+                ]
+                .map(|(set, flag)| if set { flag } else { '-' });
+                vars.push(var("Flags", flags.iter().collect()));
+                vars.push(var("IME", gb.ime().to_string()));
+                vars.push(var("Halted", gb.halted().to_string()));
+                vars
+            }
+            Some(IO) => {
+                let mut seen = HashSet::new();
+                IO_REGISTERS
+                    .iter()
+                    .filter(|(_, addr)| seen.insert(*addr))
+                    .map(|(name, addr)| var(name, format!("{:#04x}", gb.read_mem(*addr))))
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+        Ok(json!({ "variables": variables }))
+    }
+
+    /// Evaluates an expression of the `print` command.
+    fn evaluate(&self, args: &Value) -> Result<Value, String> {
+        let session = self.session()?;
+        let src = args["expression"].as_str().ok_or("Missing expression")?;
+        let ctx = Context {
+            gameboy: &session.gameboy,
+            hits: 0,
+        };
+        let val = Expr::parse(src, &session.symbols).and_then(|expr| expr.eval(&ctx))?;
+        let result = match val {
+            val if val < 0 => val.to_string(),
+            val => format!("{} ({:#x})", val, val),
+        };
+        Ok(json!({ "result": result, "variablesReference": 0 }))
+    }
+
+    /// Steps to another source line. Steps a single instruction if asked to, or if the
+    /// current instruction is not in the sources, in which case `next` steps over calls.
+    fn step(&mut self, command: &str, args: &Value) -> Result<Value, String> {
+        let session = self.session()?;
+        let gb = &session.gameboy;
+        let depth = gb.call_stack().len();
+        if command == "stepOut" {
+            return self.resume(
+                Box::new(move |session| session.gameboy.call_stack().len() < depth),
+                "step",
+            );
+        }
+
+        let (bank, pc) = session.location(gb.regs().pc());
+        let line = session
+            .sources
+            .line_of(bank, pc)
+            .map(|(path, line)| (path.to_path_buf(), line));
+        let line = match line {
+            Some(line) if args["granularity"] != "instruction" => line,
+            _ => {
+                let pc = gb.regs().pc();
+                let (bytes, mnemonic) = gb.disasm_at(pc);
+                let call = matches!(
+                    mnemonic,
+                    Mnemonic::Call(_) | Mnemonic::CallCond(..) | Mnemonic::Rst(_)
+                );
+                if command == "next" && call {
+                    // Stop at the return address, unless it is reached by a recursive call.
+                    let ret = pc.wrapping_add(bytes.len() as u16);
+                    return self.resume(
+                        Box::new(move |session| {
+                            let gb = &session.gameboy;
+                            gb.regs().pc() == ret && gb.call_stack().len() <= depth
+                        }),
+                        "step",
+                    );
+                }
+                self.session_mut()?.gameboy.step();
+                self.stop("step", None);
+                return Ok(json!({}));
+            }
+        };
+
+        let over = command == "next";
+        self.resume(
+            Box::new(move |session| {
+                let calls = session.gameboy.call_stack().len();
+                if calls < depth {
+                    return true;
+                }
+                if over && calls > depth {
+                    return false;
+                }
+                let (bank, pc) = session.location(session.gameboy.regs().pc());
+                session
+                    .sources
+                    .line_of(bank, pc)
+                    .is_some_and(|(path, l)| (path, l) != (line.0.as_path(), line.1))
+            }),
+            "step",
+        )
+    }
+
+    /// Runs the program until `done` stops it.
+    fn resume(&mut self, done: StopCondition, reason: &'static str) -> Result<Value, String> {
+        self.session()?;
+        self.running = Some(Run { done, reason });
+        Ok(json!({ "allThreadsContinued": true }))
+    }
+
+    /// Runs the program for a while, and stops it on a breakpoint, an illegal opcode,
+    /// or when its run is done.
+    fn run_slice(&mut self) {
+        let (Some(run), Some(session)) = (&mut self.running, &mut self.session) else {
+            return;
+        };
+        for _ in 0..STEPS_PER_SLICE {
+            session.gameboy.step();
+            let illegal = session
+                .gameboy
+                .take_events()
+                .into_iter()
+                .find(|event| matches!(event, Event::IllegalOpcode { .. }));
+            let pc = session.gameboy.regs().pc();
+            let stop = if let Some(event) = illegal {
+                Some(("exception", Some(event.to_string())))
+            } else if session.locations.contains(&session.location(pc)) {
+                Some(("breakpoint", None))
+            } else if (run.done)(session) {
+                Some((run.reason, None))
+            } else {
+                None
+            };
+            if let Some((reason, text)) = stop {
+                self.stop(reason, text);
+                return;
+            }
+        }
+    }
+
+    /// Stops the program, and tells the client why.
+    fn stop(&mut self, reason: &str, text: Option<String>) {
+        self.running = None;
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD,
+            "allThreadsStopped": true,
+        });
+        if let Some(text) = text {
+            body["description"] = json!(text);
+            body["text"] = json!(text);
+        }
+        self.event("stopped", body);
+    }
+
+    /// Queues an event, to be sent after the response to the current request.
+    fn event(&mut self, event: &str, body: Value) {
+        self.events
+            .push(json!({ "type": "event", "event": event, "body": body }));
+    }
+
+    /// Sends `message` with the next sequence number.
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        message["seq"] = json!(self.seq);
+        self.seq += 1;
+        let body = message.to_string();
+        write!(
+            self.writer,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.writer.flush()
+    }
+}
+
+/// Reads requests from `reader` and sends them to the server, until either side is gone.
+fn read_requests<R>(mut reader: R, sender: Sender<Value>)
+where
+    R: BufRead,
+{
+    while let Ok(Some(request)) = read_message(&mut reader) {
+        if sender.send(request).is_err() {
+            return;
+        }
+    }
+}
+
+/// Reads a message, which is a JSON body after a `Content-Length` header.
+/// Returns `None` at the end of the input.
+fn read_message<R>(reader: &mut R) -> io::Result<Option<Value>>
+where
+    R: BufRead,
+{
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(n) = line.strip_prefix("Content-Length:") {
+            len = n.trim().parse::<usize>().ok();
+        }
+    }
+    let len =
+        len.ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "Missing Content-Length"))?;
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
@@ -1,49 +1,493 @@
-use gabbro::{Gameboy, Mnemonic, Regs};
-use std::num::ParseIntError;
+use crate::{
+    expr::{Condition, Context, Expr, IO_REGISTERS},
+    input::InputLine,
+    peripherals::{Button, CellJoypad, FrameLcd, Keys, SharedFrame},
+    DebugGameboy,
+};
+use gabbro::{
+    ApuState, CodeDataLog, Event, InterruptState, LcdColor, Mnemonic, PpuState, Profiler, Regs,
+    Snapshot, SymbolTable, TimerState, WatchHit, WatchKind, Watchpoint, LCD_HEIGHT,
+};
+use std::{
+    cell::Ref,
+    collections::{BTreeMap, VecDeque},
+    fmt::{self, Display, Formatter},
+    fs, mem,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// The number of machine cycles in a frame.
+const FRAME_CYCLES: u64 = 17556;
+
+/// The number of machine cycles the hardware runs per second.
+const CYCLES_PER_SECOND: u64 = 1048576;
+
+/// The handler addresses of the interrupts, in order of priority.
+const INTERRUPT_HANDLERS: [u16; 5] = [0x40, 0x48, 0x50, 0x58, 0x60];
+
+/// The number of instructions between the snapshots used to go back in time.
+const SNAPSHOT_INTERVAL: u64 = 100_000;
+
+/// The maximum number of snapshots kept, which limits how far back in time the program can go.
+const MAX_SNAPSHOTS: usize = 64;
+
+/// The names of all commands, as completed and listed by `help`.
+const COMMANDS: [&str; 34] = [
+    "quit",
+    "continue",
+    "rcontinue",
+    "play",
+    "step",
+    "rstep",
+    "next",
+    "rnext",
+    "finish",
+    "until",
+    "frame",
+    "scanline",
+    "backtrace",
+    "read",
+    "memory",
+    "write",
+    "fill",
+    "set",
+    "flag",
+    "ime",
+    "halted",
+    "print",
+    "break",
+    "watch",
+    "hardware",
+    "asm",
+    "symbols",
+    "profile",
+    "cdl",
+    "alias",
+    "unalias",
+    "source",
+    "save",
+    "help",
+];
+
+/// The short forms of commands, which can't be redefined by aliases either.
+const SHORT_FORMS: [&str; 17] = [
+    "b", "bt", "c", "f", "h", "hw", "m", "n", "p", "q", "r", "rc", "rn", "rs", "s", "u", "w",
+];
+
+/// The maximum number of scripts sourced from each other at once.
+const MAX_SOURCE_DEPTH: usize = 8;
+
+/// The subcommands completed after commands that have them.
+const SUBCOMMANDS: [(&str, &[&str]); 5] = [
+    ("break", &["add", "on", "remove"]),
+    ("watch", &["add", "remove"]),
+    ("profile", &["start", "stop", "report", "flame"]),
+    ("cdl", &["start", "stop", "save"]),
+    ("hardware", &["ppu", "timer", "int", "apu"]),
+];
+
+/// The maximum number of completions listed when there are several.
+const MAX_COMPLETIONS: usize = 20;
+
+/// The start addresses of memory regions that the memory panel can jump to.
+const REGIONS: [(&str, u16); 7] = [
+    ("rom", 0x0000),
+    ("vram", 0x8000),
+    ("sram", 0xa000),
+    ("wram", 0xc000),
+    ("oam", 0xfe00),
+    ("io", 0xff00),
+    ("hram", 0xff80),
+];
+
+/// How long the program runs at a time, before the UI is redrawn and input is handled.
+const SLICE: Duration = Duration::from_millis(20);
+
+/// The number of steps between checks whether the current slice has ended.
+const STEPS_PER_CHECK: u64 = 1024;
+
+/// Describes why a running program should stop, if it should.
+type StopCondition = Box<dyn FnMut(&DebugGameboy) -> Option<String>>;
+
+/// The state of the Game Boy being debugged at some point.
+type DebugSnapshot = Snapshot<FrameLcd, (), CellJoypad, ()>;
+
+/// A program running until a condition is met or it is interrupted.
+struct Run {
+    /// Describes why the program should stop, if it should.
+    done: StopCondition,
+    /// The start of the current measurement of the instructions per second.
+    measured_since: Instant,
+    /// The number of instructions executed since `measured_since`.
+    instructions: u64,
+    /// The instructions per second in the last measurement.
+    ips: u64,
+    /// Whether the program runs at the speed of the hardware, with the joypad played from the keyboard.
+    playing: bool,
+    /// When the program started running, and the number of machine cycles emulated by then.
+    started: (Instant, u64),
+    /// The number of machine cycles emulated at which the program is stopped, if limited.
+    limit: Option<u64>,
+}
+
+/// Why a running program stopped.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stop {
+    /// The command that ran it is done, like a step that finished or a call that returned.
+    Done,
+    Breakpoint,
+    Watchpoint,
+    /// The condition of a breakpoint or watchpoint could not be evaluated.
+    ConditionFailed,
+    Interrupted,
+    /// It ran for the maximum number of cycles per command.
+    Limit,
+}
+
+impl Stop {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stop::Done => "done",
+            Stop::Breakpoint => "breakpoint",
+            Stop::Watchpoint => "watchpoint",
+            Stop::ConditionFailed => "condition failed",
+            Stop::Interrupted => "interrupted",
+            Stop::Limit => "limit",
+        }
+    }
+}
+
+/// The part of the hardware shown in the hardware panel.
+#[derive(Clone, Copy)]
+pub enum HardwareView {
+    Ppu,
+    Timer,
+    Interrupts,
+    Apu,
+}
 
 pub struct GameboyDebugger<'a> {
-    gameboy: &'a mut Gameboy,
-    input: String,
+    gameboy: &'a mut DebugGameboy,
+    input: InputLine,
     output: String,
-    breakpoints: Vec<u16>,
-    watchpoints: Vec<u16>,
+    breakpoints: Vec<(Breakpoint, Trigger)>,
+    /// The triggers of the watchpoints of `gameboy`, by index.
+    watch_triggers: Vec<Trigger>,
+    symbols: SymbolTable,
+    /// The file symbols were last loaded from with the `symbols` command, if any.
+    symbols_path: Option<String>,
+    /// Commands that expand to other commands, by name.
+    aliases: BTreeMap<String, String>,
+    /// The file that `save` writes to by default.
+    init_path: Option<PathBuf>,
+    /// The results of the last stopped profiling run.
+    profile: Option<Profiler>,
+    /// The last stopped Code/Data Log.
+    cdl: Option<CodeDataLog>,
+    running: Option<Run>,
+    /// Why the program last stopped, if it ran since the last command.
+    stop: Option<Stop>,
+    /// Whether the last command failed, like when its arguments are invalid.
+    failed: bool,
+    /// The maximum number of machine cycles a command may run the program for, if limited.
+    max_cycles: Option<u64>,
+    /// The first address shown in the memory panel.
+    memory_addr: u16,
+    /// The number of bytes shown in the memory panel when it was last drawn.
+    memory_len: usize,
+    /// The memory shown in the panel as it was before the program last ran, to highlight changes.
+    prev_memory: Vec<u8>,
+    /// The address of the first byte in `prev_memory`.
+    prev_memory_addr: u16,
+    hardware_view: HardwareView,
+    /// The last frame drawn by the game.
+    screen: SharedFrame,
+    /// The buttons held down in play mode.
+    keys: Keys,
+    /// The number of instructions executed since the program started.
+    /// Goes down when going back in time.
+    position: u64,
+    /// Snapshots with their positions, oldest first. Going back in time restores one,
+    /// and executes the instructions after it again.
+    history: VecDeque<(u64, DebugSnapshot)>,
+    /// Whether the program was played since the history was last reset. Execution then
+    /// depends on the keyboard, so that it can't be executed again.
+    played: bool,
+    /// The scripts being sourced, outermost first, to stop scripts from sourcing themselves.
+    sourcing: Vec<PathBuf>,
 }
 
 impl<'a> GameboyDebugger<'a> {
-    pub fn new(gameboy: &'a mut Gameboy) -> Self {
+    pub fn new(
+        gameboy: &'a mut DebugGameboy,
+        symbols: SymbolTable,
+        screen: SharedFrame,
+        keys: Keys,
+    ) -> Self {
+        gameboy.track_calls(true);
+        let history = VecDeque::from([(0, gameboy.snapshot())]);
         Self {
             gameboy,
-            input: String::new(),
+            input: InputLine::default(),
             output: String::new(),
             breakpoints: Vec::new(),
-            watchpoints: Vec::new(),
+            watch_triggers: Vec::new(),
+            symbols,
+            symbols_path: None,
+            aliases: BTreeMap::new(),
+            init_path: None,
+            profile: None,
+            cdl: None,
+            running: None,
+            stop: None,
+            failed: false,
+            max_cycles: None,
+            memory_addr: 0xc000,
+            memory_len: 0,
+            prev_memory: Vec::new(),
+            prev_memory_addr: 0,
+            hardware_view: HardwareView::Ppu,
+            screen,
+            keys,
+            position: 0,
+            history,
+            played: false,
+            sourcing: Vec::new(),
         }
     }
 
-    pub fn push_input(&mut self, c: char) {
-        self.input.push(c)
+    /// Replaces the command line with `input`.
+    pub fn set_input(&mut self, input: &str) {
+        self.input.set(input);
+    }
+
+    pub fn input(&self) -> &InputLine {
+        &self.input
     }
 
-    pub fn pop_input(&mut self) {
-        self.input.pop();
+    pub fn input_mut(&mut self) -> &mut InputLine {
+        &mut self.input
     }
 
-    pub fn input(&self) -> &str {
-        self.input.as_str()
+    /// Completes the word before the cursor as a command, subcommand, label or IO register.
+    /// Lists the candidates if there are several.
+    pub fn complete(&mut self) {
+        let before = self.input.before_cursor();
+        let words = before.split(' ').collect::<Vec<&str>>();
+        let (word, prev) = match words[..] {
+            [.., prev, word] => (word, Some(prev)),
+            [word] => (word, None),
+            [] => return,
+        };
+        let mut candidates = match prev {
+            None => COMMANDS
+                .iter()
+                .map(|cmd| cmd.to_string())
+                .chain(self.aliases.keys().cloned())
+                .collect::<Vec<String>>(),
+            Some(prev) => match SUBCOMMANDS.iter().find(|(cmd, _)| *cmd == prev) {
+                Some((_, subcommands)) if words.len() == 2 => {
+                    subcommands.iter().map(|sub| sub.to_string()).collect()
+                }
+                _ => self
+                    .symbols
+                    .iter()
+                    .map(|(_, _, label)| label.to_string())
+                    .chain(IO_REGISTERS.iter().map(|(name, _)| name.to_string()))
+                    .collect(),
+            },
+        };
+        candidates.retain(|candidate| candidate.starts_with(word));
+        candidates.sort();
+        candidates.dedup();
+
+        let completed = match &candidates[..] {
+            [] => return,
+            [candidate] => format!("{} ", candidate),
+            [first, rest @ ..] => {
+                let common = rest.iter().fold(first.len(), |len, candidate| {
+                    first
+                        .bytes()
+                        .zip(candidate.bytes())
+                        .take(len)
+                        .take_while(|(a, b)| a == b)
+                        .count()
+                });
+                let mut list = candidates[..candidates.len().min(MAX_COMPLETIONS)].join(" ");
+                if candidates.len() > MAX_COMPLETIONS {
+                    list += &format!(" and {} more", candidates.len() - MAX_COMPLETIONS);
+                }
+                self.output = list;
+                first[..common].to_string()
+            }
+        };
+        let rest = completed[word.len()..].to_string();
+        self.input.insert_str(&rest);
     }
 
     pub fn output(&self) -> &str {
         self.output.as_str()
     }
 
-    pub fn disasm(&self, amt: usize) -> Vec<(bool, u16, Vec<u8>, Mnemonic)> {
+    /// Returns the first address shown in the memory panel.
+    pub fn memory_addr(&self) -> u16 {
+        self.memory_addr
+    }
+
+    /// Returns `len` bytes of memory starting at `addr`, and whether each changed
+    /// since the program last ran. Only the bytes last returned are compared on the next run.
+    pub fn memory(&mut self, addr: u16, len: usize) -> Vec<(u8, bool)> {
+        self.memory_len = len;
+        (0..len)
+            .map_while(|i| addr.checked_add(i as u16))
+            .map(|addr| {
+                let val = self.gameboy.read_mem(addr);
+                let prev = addr
+                    .checked_sub(self.prev_memory_addr)
+                    .and_then(|offset| self.prev_memory.get(offset as usize));
+                (val, prev.is_some_and(|prev| *prev != val))
+            })
+            .collect()
+    }
+
+    /// Scrolls the memory panel by `bytes`, which is negative to scroll up.
+    pub fn scroll_memory(&mut self, bytes: i32) {
+        self.memory_addr = (self.memory_addr as i32 + bytes).clamp(0, 0xfff0) as u16;
+    }
+
+    /// Returns whether the program is running, rather than stopped at a prompt.
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// Describes the speed of the running program, if it is running.
+    pub fn running_status(&self) -> Option<String> {
+        self.running.as_ref().map(|run| match run.playing {
+            true => "Playing. Arrows, X (A), Z (B), Enter (Start) and Backspace (Select) \
+                control the joypad. Press Esc or Ctrl-C to break"
+                .into(),
+            false => format!(
+                "Running, {} instructions/s. Press Esc or Ctrl-C to break",
+                run.ips
+            ),
+        })
+    }
+
+    /// Runs the program for a slice of time, or until it stops.
+    pub fn run_slice(&mut self) {
+        let Some(mut run) = self.running.take() else {
+            return;
+        };
+        let start = Instant::now();
+        // While playing, only emulate the cycles the hardware would have run by now.
+        let budget = run.playing.then(|| {
+            self.keys.update();
+            let (started, start_cycles) = run.started;
+            start_cycles + (started.elapsed().as_secs_f64() * CYCLES_PER_SECOND as f64) as u64
+        });
+        loop {
+            if budget.is_some_and(|budget| self.gameboy.cycles() >= budget) {
+                break;
+            }
+            self.step();
+            run.instructions += 1;
+            let stop = (run.done)(self.gameboy)
+                .map(|reason| (Stop::Done, reason))
+                .or_else(|| self.stop_reason())
+                .or_else(|| {
+                    let limit = run.limit.filter(|limit| self.gameboy.cycles() >= *limit)?;
+                    let cycles = limit - run.started.1;
+                    let pc = self.gameboy.regs().pc();
+                    let reason = format!("Stopped after {} cycles at {:#06x}", cycles, pc);
+                    Some((Stop::Limit, reason))
+                });
+            if let Some((stop, reason)) = stop {
+                self.output = reason;
+                self.stop = Some(stop);
+                // Running into the limit or a broken condition fails the command that ran it.
+                self.failed = matches!(stop, Stop::Limit | Stop::ConditionFailed);
+                self.keys.release_all();
+                return;
+            }
+            if run.instructions.is_multiple_of(STEPS_PER_CHECK) && start.elapsed() >= SLICE {
+                break;
+            }
+        }
+        let elapsed = run.measured_since.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            run.ips = (run.instructions as f64 / elapsed.as_secs_f64()) as u64;
+            run.measured_since = Instant::now();
+            run.instructions = 0;
+        }
+        self.running = Some(run);
+    }
+
+    /// Returns why the program stopped, if the last command ran it.
+    pub fn stop(&self) -> Option<Stop> {
+        self.stop
+    }
+
+    /// Returns whether the last command failed, including when it ran into the cycle limit
+    /// or a condition that could not be evaluated.
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+
+    /// Limits the number of machine cycles every following command may run the program for.
+    pub fn set_max_cycles(&mut self, max_cycles: Option<u64>) {
+        self.max_cycles = max_cycles;
+    }
+
+    /// Runs the program until it stops, without returning between slices.
+    pub fn run_to_stop(&mut self) {
+        while self.is_running() {
+            self.run_slice();
+        }
+    }
+
+    /// Stops the running program.
+    pub fn interrupt(&mut self) {
+        if self.running.take().is_some() {
+            self.output = format!("Interrupted at {:#06x}", self.gameboy.regs().pc());
+            self.stop = Some(Stop::Interrupted);
+            self.keys.release_all();
+        }
+    }
+
+    /// Returns whether the program is running in play mode.
+    pub fn is_playing(&self) -> bool {
+        self.running.as_ref().is_some_and(|run| run.playing)
+    }
+
+    /// Holds `button` down while playing.
+    pub fn press(&mut self, button: Button) {
+        if self.is_playing() {
+            self.keys.press(button);
+        }
+    }
+
+    /// Returns the last frame drawn by the game, row by row.
+    pub fn screen(&self) -> Ref<'_, Vec<LcdColor>> {
+        self.screen.borrow()
+    }
+
+    /// Returns the label at `addr` in the currently mapped memory, if any.
+    pub fn label(&self, addr: u16) -> Option<&str> {
+        self.symbols.label(self.gameboy.rom_bank(), addr)
+    }
+
+    pub fn disasm(&self, amt: usize) -> Vec<(bool, u16, Vec<u8>, String)> {
         let mut disassembly = Vec::new();
         let mut addr = self.gameboy.regs().pc();
         let mut at_pc = true;
         for _ in 0..amt {
             let (bytes, mnemonic) = self.gameboy.disasm_at(addr);
             let size = bytes.len() as u16;
-            disassembly.push((at_pc, addr, bytes, mnemonic));
+            let text =
+                mnemonic.to_string_with_labels(addr, |addr| self.label(addr).map(String::from));
+            disassembly.push((at_pc, addr, bytes, text));
             at_pc = false;
             if let Some(next_addr) = addr.checked_add(size) {
                 addr = next_addr;
@@ -75,95 +519,1206 @@ impl<'a> GameboyDebugger<'a> {
         self.gameboy.regs()
     }
 
-    pub fn breakpoints(&self) -> &Vec<u16> {
+    pub fn ime(&self) -> bool {
+        self.gameboy.ime()
+    }
+
+    pub fn halted(&self) -> bool {
+        self.gameboy.halted()
+    }
+
+    /// Returns the part of the hardware shown in the hardware panel.
+    pub fn hardware_view(&self) -> HardwareView {
+        self.hardware_view
+    }
+
+    pub fn ppu_state(&self) -> PpuState {
+        self.gameboy.ppu_state()
+    }
+
+    pub fn timer_state(&self) -> TimerState {
+        self.gameboy.timer_state()
+    }
+
+    pub fn interrupt_state(&self) -> InterruptState {
+        self.gameboy.interrupt_state()
+    }
+
+    pub fn apu_state(&self) -> ApuState {
+        self.gameboy.apu_state()
+    }
+
+    /// Describes the frames on the call stack, starting with the innermost one.
+    pub fn backtrace(&self) -> Vec<String> {
+        self.gameboy
+            .call_stack()
+            .iter()
+            .rev()
+            .map(|frame| {
+                format!(
+                    "{:02x}:{:04x} {}{}",
+                    frame.bank,
+                    frame.target,
+                    self.symbols
+                        .label(frame.bank, frame.target)
+                        .map_or(String::new(), |label| format!("{} ", label)),
+                    if frame.interrupt { "(interrupt)" } else { "" },
+                )
+                .trim_end()
+                .to_string()
+            })
+            .collect()
+    }
+
+    pub fn breakpoints(&self) -> &[(Breakpoint, Trigger)] {
         &self.breakpoints
     }
 
-    pub fn watchpoints(&self) -> Vec<(u16, u8)> {
-        self.watchpoints
+    /// Returns all watchpoints, with the current value at the start of their range.
+    pub fn watchpoints(&self) -> Vec<(&Watchpoint, u8)> {
+        self.gameboy
+            .watchpoints()
             .iter()
-            .map(|&addr| (addr, self.gameboy.read_mem(addr)))
-            .collect::<Vec<(u16, u8)>>()
+            .map(|wp| (wp, self.gameboy.read_mem(*wp.range.start())))
+            .collect()
     }
 
     pub fn run_command(&mut self) -> bool {
-        self.output = match self.input.split(' ').collect::<Vec<&str>>()[..] {
+        let line = self.input.submit();
+        let line = self.expand_alias(line);
+        self.stop = None;
+        self.failed = false;
+        if mem::take(&mut self.played) {
+            self.reset_history();
+        }
+        let words = line.split(' ').collect::<Vec<&str>>();
+        // Changing the state by hand can't be repeated when executing instructions again.
+        let edits = matches!(
+            words[0],
+            "write" | "fill" | "set" | "flag" | "ime" | "halted" | "asm"
+        );
+        self.output = match words[..] {
             ["quit" | "q"] => return true,
-            ["continue" | "c"] => loop {
-                self.gameboy.step();
-                if let Some(breakpoint) = self
-                    .breakpoints
-                    .iter()
-                    .position(|bp| *bp == self.gameboy.regs().pc())
-                {
-                    break format!("Breakpoint {:02} hit", breakpoint);
+            ["continue" | "c"] => self.run_until(move |_| None),
+            ["rcontinue" | "rc"] => self.reverse_continue(),
+            ["rstep" | "rs"] => self.reverse_step(1),
+            ["rstep" | "rs", n] => match n.parse::<u64>() {
+                Ok(n) => self.reverse_step(n),
+                Err(_) => self.fail(format!("Invalid number of steps: {}", n)),
+            },
+            ["rnext" | "rn"] => self.reverse_next(),
+            ["play"] => self.play(),
+            ["finish" | "f"] => match self.gameboy.call_stack().len() {
+                0 => self.fail("Not inside a call".into()),
+                depth => self.run_until(move |gb| {
+                    (gb.call_stack().len() < depth)
+                        .then(|| format!("Returned to {:#06x}", gb.regs().pc()))
+                }),
+            },
+            ["next" | "n"] => {
+                let pc = self.gameboy.regs().pc();
+                let (bytes, mnemonic) = self.gameboy.disasm_at(pc);
+                match mnemonic {
+                    Mnemonic::Call(_) | Mnemonic::CallCond(..) | Mnemonic::Rst(_) => {
+                        // Break at the return address, unless it is reached by a recursive call.
+                        let ret = pc.wrapping_add(bytes.len() as u16);
+                        let depth = self.gameboy.call_stack().len();
+                        self.run_until(move |gb| {
+                            (gb.regs().pc() == ret && gb.call_stack().len() <= depth)
+                                .then(|| format!("Stepped over to {:#06x}", ret))
+                        })
+                    }
+                    _ => {
+                        self.step_once();
+                        "Executed 1 instruction".into()
+                    }
                 }
+            }
+            ["until" | "u", addr] => match self.parse_addr(addr) {
+                Some(addr) => self.run_until(move |gb| {
+                    (gb.regs().pc() == addr).then(|| format!("Reached {:#06x}", addr))
+                }),
+                None => self.fail(format!("Invalid address: {}", addr)),
+            },
+            ["frame"] => {
+                let limit = self.gameboy.cycles() + 2 * FRAME_CYCLES;
+                let mut ly = current_ly(self.gameboy);
+                self.run_until(move |gb| {
+                    let prev = mem::replace(&mut ly, current_ly(gb));
+                    if prev != ly && ly as usize == LCD_HEIGHT {
+                        Some("Reached VBlank".into())
+                    } else if gb.cycles() > limit {
+                        Some("No VBlank within two frames, the LCD may be off".into())
+                    } else {
+                        None
+                    }
+                })
+            }
+            ["scanline"] => {
+                let limit = self.gameboy.cycles() + 2 * FRAME_CYCLES;
+                let ly = current_ly(self.gameboy);
+                self.run_until(move |gb| {
+                    if current_ly(gb) != ly {
+                        Some(format!("Reached scanline {}", current_ly(gb)))
+                    } else if gb.cycles() > limit {
+                        Some("LY did not change within two frames, the LCD may be off".into())
+                    } else {
+                        None
+                    }
+                })
+            }
+            ["backtrace" | "bt"] => match self.backtrace() {
+                frames if frames.is_empty() => self.fail("Not inside a call".into()),
+                frames => frames.join(" <- "),
             },
             ["step" | "s", steps] => match steps.parse::<usize>() {
+                Ok(0) => "Executed 0 instructions".into(),
                 Ok(n) => {
-                    self.step_n(n);
-                    format!("Executed {} instructions", n)
+                    // Run in slices like other motions, so that long steps can be interrupted.
+                    let mut left = n;
+                    self.run_until(move |_| {
+                        left -= 1;
+                        (left == 0).then(|| format!("Executed {} instructions", n))
+                    })
                 }
-                Err(_) => format!("Invalid number: {}", steps),
+                Err(_) => self.fail(format!("Invalid number: {}", steps)),
             },
             ["step" | "s"] => {
-                self.step_n(1);
+                self.step_once();
                 "Executed 1 instruction".into()
             }
+            ["memory" | "m", addr] => {
+                let region = REGIONS.iter().find(|(name, _)| *name == addr);
+                match region.map(|(_, addr)| *addr).or(self.parse_addr(addr)) {
+                    Some(addr) => {
+                        self.memory_addr = addr & 0xfff0;
+                        format!("Showing memory at {:#06x}", addr)
+                    }
+                    None => self.fail(format!("Invalid address: {}", addr)),
+                }
+            }
+            ["write", addr, ref vals @ ..] if !vals.is_empty() => {
+                let vals = vals.iter().map(|val| self.parse_byte(val));
+                match (self.parse_addr(addr), vals.collect::<Option<Vec<u8>>>()) {
+                    (Some(addr), Some(vals)) => {
+                        for (i, val) in vals.iter().enumerate() {
+                            self.gameboy.write_mem(addr.wrapping_add(i as u16), *val);
+                        }
+                        format!("Wrote {} bytes at {:#06x}", vals.len(), addr)
+                    }
+                    (None, _) => self.fail(format!("Invalid address: {}", addr)),
+                    (_, None) => self.fail("Invalid value, expected a byte".into()),
+                }
+            }
+            ["fill", range, val] => match (self.parse_range(range), self.parse_byte(val)) {
+                (Some(range), Some(val)) => {
+                    let (start, end) = (*range.start(), *range.end());
+                    range.for_each(|addr| self.gameboy.write_mem(addr, val));
+                    format!("Filled {:#06x}-{:#06x} with {:#04x}", start, end, val)
+                }
+                (None, _) => self.fail(format!("Invalid address range: {}", range)),
+                (_, None) => self.fail(format!("Invalid value, expected a byte: {}", val)),
+            },
+            ["set", reg, ref val @ ..] if !val.is_empty() => {
+                let val = val.join(" ");
+                match self.parse_value(&val) {
+                    Some(val) => match Self::set_register(self.gameboy.regs_mut(), reg, val) {
+                        Ok(()) => format!("Set {} to {:#x}", reg, val),
+                        Err(e) => self.fail(e),
+                    },
+                    None => self.fail(format!("Invalid value: {}", val)),
+                }
+            }
+            ["flag", flag, val] => match self.parse_bool(val) {
+                Some(val) => match Self::set_flag(self.gameboy.regs_mut(), flag, val) {
+                    Ok(()) => format!("Set flag {} to {}", flag, val as u8),
+                    Err(e) => self.fail(e),
+                },
+                None => self.fail(format!("Invalid value, expected 0 or 1: {}", val)),
+            },
+            ["ime", val] => match self.parse_bool(val) {
+                Some(val) => {
+                    self.gameboy.set_ime(val);
+                    format!("Set IME to {}", val as u8)
+                }
+                None => self.fail(format!("Invalid value, expected 0 or 1: {}", val)),
+            },
+            ["halted", val] => match self.parse_bool(val) {
+                Some(val) => {
+                    self.gameboy.set_halted(val);
+                    format!("Set halted to {}", val as u8)
+                }
+                None => self.fail(format!("Invalid value, expected 0 or 1: {}", val)),
+            },
             ["read" | "r", addr] => match self.parse_addr(addr) {
-                Ok(addr) => {
+                Some(addr) => {
                     let val = self.gameboy.read_mem(addr);
                     format!("Value at address {:#06x}: {:#04x}", addr, val)
                 }
-                Err(_) => format!("Invalid address: {}", addr),
+                None => self.fail(format!("Invalid address: {}", addr)),
             },
-            ["break" | "b", "add" | "a", addr] => match self.parse_addr(addr) {
-                Ok(addr) => {
-                    self.breakpoints.push(addr);
-                    format!("Inserted breakpoint at {:#06x}", addr)
+            ["break" | "b", "add" | "a", addr, ref args @ ..] => {
+                match (self.parse_location(addr), self.parse_condition(args)) {
+                    (Some(breakpoint), Ok(condition)) => self.add_breakpoint(breakpoint, condition),
+                    (None, _) => self.fail(format!("Invalid address: {}", addr)),
+                    (_, Err(e)) => self.fail(format!("Invalid condition: {}", e)),
                 }
-                Err(_) => format!("Invalid address: {}", addr),
-            },
+            }
+            ["break" | "b", "on", ref args @ ..] => {
+                let (event, args) = match args {
+                    ["interrupt" | "int", name, args @ ..] => (
+                        INTERRUPT_HANDLERS
+                            .into_iter()
+                            .find(|handler| {
+                                Event::interrupt_name(*handler).eq_ignore_ascii_case(name)
+                            })
+                            .map(EventBreak::Interrupt),
+                        args,
+                    ),
+                    ["write", reg, args @ ..] => {
+                        (self.parse_io_register(reg).map(EventBreak::IoWrite), args)
+                    }
+                    ["bank", args @ ..] => (Some(EventBreak::BankSwitch), args),
+                    ["dma", args @ ..] => (Some(EventBreak::DmaStart), args),
+                    ["lcd", args @ ..] => (Some(EventBreak::Lcd), args),
+                    ["illegal", args @ ..] => (Some(EventBreak::IllegalOpcode), args),
+                    _ => (None, args),
+                };
+                match (event, self.parse_condition(args)) {
+                    (Some(event), Ok(condition)) => {
+                        self.add_breakpoint(Breakpoint::Event(event), condition)
+                    }
+                    (None, _) => self.fail(format!("Invalid event: {}", args.join(" "))),
+                    (_, Err(e)) => self.fail(format!("Invalid condition: {}", e)),
+                }
+            }
             ["break" | "b", "remove" | "r", idx] => match idx.parse::<usize>() {
                 Ok(idx) if idx < self.breakpoints.len() => {
-                    let addr = self.breakpoints[idx];
-                    self.breakpoints.remove(idx);
-                    format!("Removed breakpoint {:02} at {:#06x}", idx, addr)
+                    let (breakpoint, _) = self.breakpoints.remove(idx);
+                    self.record_events();
+                    format!("Removed breakpoint {:02} {}", idx, breakpoint)
                 }
-                _ => format!("Invalid index: {}", idx),
+                _ => self.fail(format!("Invalid index: {}", idx)),
             },
-            ["watch" | "w", "add" | "a", addr] => match self.parse_addr(addr) {
-                Ok(addr) => {
-                    self.watchpoints.push(addr);
-                    format!("Inserted watchpoint at {:#06x}", addr)
+            ["watch" | "w", "add" | "a", range, ref args @ ..] => {
+                let (kind, args) = match args {
+                    [] | ["if", ..] => (Some(WatchKind::Write), args),
+                    ["write" | "w", args @ ..] => (Some(WatchKind::Write), args),
+                    ["read" | "r", args @ ..] => (Some(WatchKind::Read), args),
+                    ["change" | "c", args @ ..] => (Some(WatchKind::Change), args),
+                    [_, args @ ..] => (None, args),
+                };
+                match (self.parse_range(range), kind, self.parse_condition(args)) {
+                    (Some(range), Some(kind), Ok(condition)) => {
+                        let watchpoint = Watchpoint { range, kind };
+                        let idx = self.gameboy.add_watchpoint(watchpoint.clone());
+                        self.watch_triggers.push(Trigger::new(condition));
+                        format!(
+                            "Inserted watchpoint {:02} on {}",
+                            idx,
+                            describe(&watchpoint)
+                        )
+                    }
+                    (None, _, _) => self.fail(format!("Invalid address range: {}", range)),
+                    (_, None, _) => {
+                        self.fail(format!("Invalid watchpoint kind: {}", args.join(" ")))
+                    }
+                    (_, _, Err(e)) => self.fail(format!("Invalid condition: {}", e)),
                 }
-                Err(_) => format!("Invalid address: {}", addr),
-            },
+            }
             ["watch" | "w", "remove" | "r", idx] => match idx.parse::<usize>() {
-                Ok(idx) if idx < self.watchpoints.len() => {
-                    let addr = self.watchpoints[idx];
-                    self.watchpoints.remove(idx);
-                    format!("Removed watchpoint {:02} at {:#06x}", idx, addr)
+                Ok(idx) if idx < self.watch_triggers.len() => {
+                    let watchpoint = self.gameboy.remove_watchpoint(idx).unwrap();
+                    self.watch_triggers.remove(idx);
+                    format!("Removed watchpoint {:02} on {}", idx, describe(&watchpoint))
+                }
+                _ => self.fail(format!("Invalid index: {}", idx)),
+            },
+            ["print", ref expr @ ..] if !expr.is_empty() => {
+                let src = expr.join(" ");
+                match Expr::parse(&src, &self.symbols).and_then(|expr| expr.eval(&self.context(0)))
+                {
+                    Ok(val) if val < 0 => format!("{} = {}", src, val),
+                    Ok(val) => format!("{} = {} ({:#x})", src, val, val),
+                    Err(e) => self.fail(format!("Failed to evaluate {}: {}", src, e)),
+                }
+            }
+            ["asm", addr, ref instr @ ..] if !instr.is_empty() => match self.parse_addr(addr) {
+                Some(addr) => match self.gameboy.patch_asm(addr, &instr.join(" ")) {
+                    Ok(len) => format!("Assembled {} bytes at {:#06x}", len, addr),
+                    Err(e) => self.fail(format!("Failed to assemble {}", e)),
+                },
+                None => self.fail(format!("Invalid address: {}", addr)),
+            },
+            ["profile" | "p", "start"] => {
+                self.gameboy.start_profiling();
+                "Profiling started".into()
+            }
+            ["profile" | "p", "stop"] => match self.gameboy.stop_profiling() {
+                Some(profile) => {
+                    self.profile = Some(profile);
+                    format!("Profiling stopped. {}", self.profile_summary())
+                }
+                None => self.fail("Profiling is not running".into()),
+            },
+            ["profile" | "p"] => self.profile_summary(),
+            ["profile" | "p", "report", path] => match self.current_profile() {
+                Some(profile) => match fs::write(path, profile.report(&self.symbols)) {
+                    Ok(()) => format!("Wrote profile report to {}", path),
+                    Err(e) => self.fail(format!("Failed to write {}: {}", path, e)),
+                },
+                None => self.fail("No profile recorded".into()),
+            },
+            ["profile" | "p", "flame", path] => match self.current_profile() {
+                Some(profile) => match fs::write(path, profile.collapsed(&self.symbols)) {
+                    Ok(()) => format!("Wrote collapsed stacks to {}", path),
+                    Err(e) => self.fail(format!("Failed to write {}: {}", path, e)),
+                },
+                None => self.fail("No profile recorded".into()),
+            },
+            ["cdl", "start"] => {
+                self.gameboy.start_cdl();
+                "Code/data logging started".into()
+            }
+            ["cdl", "stop"] => match self.gameboy.stop_cdl() {
+                Some(cdl) => {
+                    self.cdl = Some(cdl);
+                    format!("Code/data logging stopped. {}", self.cdl_summary())
+                }
+                None => self.fail("Code/data logging is not running".into()),
+            },
+            ["cdl"] => self.cdl_summary(),
+            ["cdl", "save", path] => match self.current_cdl() {
+                Some(cdl) => match fs::write(path, cdl.bytes()) {
+                    Ok(()) => format!("Wrote code/data log to {}", path),
+                    Err(e) => self.fail(format!("Failed to write {}: {}", path, e)),
+                },
+                None => self.fail("No code/data log recorded".into()),
+            },
+            ["hardware" | "hw", name] => {
+                let view = match name {
+                    "ppu" => Some(HardwareView::Ppu),
+                    "timer" => Some(HardwareView::Timer),
+                    "int" | "interrupts" => Some(HardwareView::Interrupts),
+                    "apu" => Some(HardwareView::Apu),
+                    _ => None,
+                };
+                match view {
+                    Some(view) => {
+                        self.hardware_view = view;
+                        format!("Showing {} state", name)
+                    }
+                    None => self.fail(format!(
+                        "Invalid hardware: {}, expected ppu, timer, int or apu",
+                        name
+                    )),
+                }
+            }
+            ["symbols", path] => match fs::read_to_string(path) {
+                Ok(src) => {
+                    self.symbols = SymbolTable::parse(&src);
+                    self.symbols_path = Some(path.to_string());
+                    format!("Loaded {} symbols from {}", self.symbols.len(), path)
+                }
+                Err(e) => self.fail(format!("Failed to read {}: {}", path, e)),
+            },
+            ["symbols"] => format!("{} symbols loaded", self.symbols.len()),
+            ["alias"] if self.aliases.is_empty() => "No aliases defined".into(),
+            ["alias"] => self
+                .aliases
+                .iter()
+                .map(|(name, cmd)| format!("{} = {}", name, cmd))
+                .collect::<Vec<String>>()
+                .join(", "),
+            ["alias", name, ref cmd @ ..] if !cmd.is_empty() => {
+                if COMMANDS.contains(&name) || SHORT_FORMS.contains(&name) {
+                    self.fail(format!("Cannot redefine the command {}", name))
+                } else {
+                    self.aliases.insert(name.to_string(), cmd.join(" "));
+                    format!("Defined alias {} = {}", name, cmd.join(" "))
                 }
-                _ => format!("Invalid index: {}", idx),
+            }
+            ["unalias", name] => match self.aliases.remove(name) {
+                Some(_) => format!("Removed alias {}", name),
+                None => self.fail(format!("No alias named {}", name)),
+            },
+            ["source", path] => self.source(Path::new(path)),
+            ["save"] => match self.init_path.clone() {
+                Some(path) => self.save_session(&path),
+                None => self.fail("No init file to save to".into()),
             },
-            ["help" | "h"] => "Commands: quit, continue, step, read, break, watch, help".into(),
-            _ => format!("Unknown command: {}", self.input),
+            ["save", path] => self.save_session(Path::new(path)),
+            ["help" | "h"] => format!("Commands: {}", COMMANDS.join(", ")),
+            _ => self.fail(format!("Unknown command: {}", line)),
         };
-        self.input.clear();
+        if edits {
+            self.reset_history();
+        }
         false
     }
 
-    fn step_n(&mut self, n: usize) {
-        for _ in 0..n {
-            self.gameboy.step()
+    /// Runs the init file at `path` if it exists, and makes it the file `save` writes to.
+    pub fn load_init(&mut self, path: &Path) {
+        self.init_path = Some(path.to_path_buf());
+        if path.exists() {
+            self.output = self.source(path);
+        }
+    }
+
+    /// Runs the script at `path`, and describes how many of its commands ran and failed.
+    /// Fails if the script can't be run, or if any of its commands failed.
+    fn source(&mut self, path: &Path) -> String {
+        let (ran, total, failed) = match self.run_script(path) {
+            Ok(counts) => counts,
+            Err(e) => return self.fail(e),
+        };
+        let mut message = if ran < total {
+            // The output is still why the program stopped.
+            format!(
+                "Ran {} of {} commands from {}, stopped: {}",
+                ran,
+                total,
+                path.display(),
+                self.output
+            )
+        } else {
+            format!("Ran {} commands from {}", ran, path.display())
+        };
+        if failed > 0 {
+            message = self.fail(format!("{}, {} failed", message, failed));
+        }
+        message
+    }
+
+    /// Runs the commands in the file at `path`, one per line, skipping empty lines and
+    /// `#` comments. Runs of the program complete before the next command, and the script
+    /// stops early when one is stopped by anything but its own end, like a breakpoint.
+    /// Returns the number of commands run, the number of commands in the script, and how many
+    /// of them failed. Scripts can source other scripts, but not one that is already being sourced.
+    fn run_script(&mut self, path: &Path) -> Result<(usize, usize, usize), String> {
+        let read = |e| format!("Failed to read {}: {}", path.display(), e);
+        let src = fs::read_to_string(path).map_err(read)?;
+        let canonical = fs::canonicalize(path).map_err(read)?;
+        if self.sourcing.contains(&canonical) {
+            return Err(format!("{} is already being sourced", path.display()));
+        }
+        if self.sourcing.len() >= MAX_SOURCE_DEPTH {
+            return Err(format!(
+                "Cannot source {}, scripts are nested too deeply",
+                path.display()
+            ));
+        }
+        let commands = src
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect::<Vec<&str>>();
+        self.sourcing.push(canonical);
+        let (mut ran, mut failed) = (0, 0);
+        for command in &commands {
+            self.input.set(command);
+            self.run_command();
+            self.run_to_stop();
+            ran += 1;
+            failed += self.failed as usize;
+            if self.stop.is_some_and(|stop| stop != Stop::Done) {
+                break;
+            }
+        }
+        self.sourcing.pop();
+        Ok((ran, commands.len(), failed))
+    }
+
+    /// Writes the commands that restore the loaded symbols, aliases, breakpoints and
+    /// watchpoints to the file at `path`.
+    fn save_session(&mut self, path: &Path) -> String {
+        let if_condition = |trigger: &Trigger| match trigger.condition() {
+            Some(condition) => format!(" if {}", condition),
+            None => String::new(),
+        };
+        let symbols = self
+            .symbols_path
+            .iter()
+            .map(|path| format!("symbols {}", path));
+        let aliases = self
+            .aliases
+            .iter()
+            .map(|(name, cmd)| format!("alias {} {}", name, cmd));
+        let breakpoints = self
+            .breakpoints
+            .iter()
+            .map(|(bp, trigger)| format!("{}{}", bp.command(), if_condition(trigger)));
+        let watchpoints = self
+            .gameboy
+            .watchpoints()
+            .iter()
+            .zip(&self.watch_triggers)
+            .map(|(wp, trigger)| {
+                format!(
+                    "watch add {:#06x}-{:#06x} {}{}",
+                    wp.range.start(),
+                    wp.range.end(),
+                    wp.kind,
+                    if_condition(trigger)
+                )
+            });
+        let commands = symbols
+            .chain(aliases)
+            .chain(breakpoints)
+            .chain(watchpoints)
+            .collect::<Vec<String>>();
+        let src = commands
+            .iter()
+            .map(|cmd| format!("{}\n", cmd))
+            .collect::<String>();
+        match fs::write(path, src) {
+            Ok(()) => format!("Saved {} commands to {}", commands.len(), path.display()),
+            Err(e) => self.fail(format!("Failed to write {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Marks the current command as failed, and returns `message` describing why.
+    fn fail(&mut self, message: String) -> String {
+        self.failed = true;
+        message
+    }
+
+    /// Replaces an alias at the start of `line` with the command it stands for.
+    fn expand_alias(&self, line: String) -> String {
+        let (name, args) = line.split_once(' ').unwrap_or((&line, ""));
+        match self.aliases.get(name) {
+            Some(cmd) if args.is_empty() => cmd.clone(),
+            Some(cmd) => format!("{} {}", cmd, args),
+            None => line,
         }
     }
 
-    fn parse_addr(&self, input: &str) -> Result<u16, ParseIntError> {
-        match input.strip_prefix("0x") {
-            Some(hex) => u16::from_str_radix(hex, 16),
-            None => input.parse::<u16>(),
+    /// Returns the running profile, or else the last stopped one.
+    fn current_profile(&self) -> Option<&Profiler> {
+        self.gameboy.profiler().or(self.profile.as_ref())
+    }
+
+    /// Summarizes the current profile in a single line.
+    fn profile_summary(&self) -> String {
+        let Some(profile) = self.current_profile() else {
+            return "No profile recorded".into();
+        };
+        let total = profile.total();
+        let top = profile.functions(&self.symbols).into_iter().next();
+        format!(
+            "{} instructions, {} cycles{}",
+            total.instructions,
+            total.cycles,
+            top.map_or(String::new(), |(name, counts)| format!(
+                ", most in {} ({} cycles)",
+                name, counts.cycles
+            ))
+        )
+    }
+
+    /// Returns the running Code/Data Log, or else the last stopped one.
+    fn current_cdl(&self) -> Option<&CodeDataLog> {
+        self.gameboy.cdl().or(self.cdl.as_ref())
+    }
+
+    /// Summarizes the coverage of the current Code/Data Log in a single line.
+    fn cdl_summary(&self) -> String {
+        let Some(cdl) = self.current_cdl() else {
+            return "No code/data log recorded".into();
+        };
+        let (code, data, unused) = cdl.coverage();
+        format!(
+            "{} bytes of code, {} bytes of data, {} of {} bytes unused",
+            code,
+            data,
+            unused,
+            cdl.bytes().len()
+        )
+    }
+
+    /// Starts running until `done` describes why it should stop, or a breakpoint or
+    /// watchpoint is hit. The program runs in slices through [`GameboyDebugger::run_slice`].
+    fn run_until<F>(&mut self, done: F) -> String
+    where
+        F: FnMut(&DebugGameboy) -> Option<String> + 'static,
+    {
+        // Discard hits and events from before, e.g. while single stepping.
+        self.gameboy.take_watch_hits();
+        self.gameboy.take_events();
+        self.snapshot_memory();
+        self.running = Some(Run {
+            done: Box::new(done),
+            measured_since: Instant::now(),
+            instructions: 0,
+            ips: 0,
+            playing: false,
+            started: (Instant::now(), self.gameboy.cycles()),
+            limit: self.max_cycles.map(|max| self.gameboy.cycles() + max),
+        });
+        "Running".into()
+    }
+
+    /// Starts running at the speed of the hardware, with the joypad played from the keyboard,
+    /// until interrupted or a breakpoint or watchpoint is hit.
+    fn play(&mut self) -> String {
+        self.run_until(|_| None);
+        if let Some(run) = &mut self.running {
+            run.playing = true;
+        }
+        self.played = true;
+        "Playing, press Esc to stop".into()
+    }
+
+    /// Describes why execution should stop after the last step, if it should.
+    /// Counts a hit for every breakpoint and watchpoint that was reached.
+    fn stop_reason(&mut self) -> Option<(Stop, String)> {
+        for hit in self.gameboy.take_watch_hits() {
+            match self.watch_triggers[hit.index].hit(self.gameboy) {
+                Ok(true) => {
+                    let reason = format!(
+                        "Watchpoint {:02} hit: {}",
+                        hit.index,
+                        describe_watch_hit(&hit)
+                    );
+                    return Some((Stop::Watchpoint, reason));
+                }
+                Ok(false) => (),
+                Err(e) => {
+                    let reason = format!("Watchpoint {:02} condition failed: {}", hit.index, e);
+                    return Some((Stop::ConditionFailed, reason));
+                }
+            }
+        }
+        for event in self.gameboy.take_events() {
+            for (idx, (_, trigger)) in self.breakpoints.iter_mut().enumerate().filter(
+                |(_, (bp, _))| matches!(bp, Breakpoint::Event(kind) if kind.matches(&event)),
+            ) {
+                match trigger.hit(self.gameboy) {
+                    Ok(true) => {
+                        let reason = format!("Breakpoint {:02} hit: {}", idx, event);
+                        return Some((Stop::Breakpoint, reason));
+                    }
+                    Ok(false) => (),
+                    Err(e) => {
+                        let reason = format!("Breakpoint {:02} condition failed: {}", idx, e);
+                        return Some((Stop::ConditionFailed, reason));
+                    }
+                }
+            }
+        }
+        let (pc, bank) = (self.gameboy.regs().pc(), self.gameboy.rom_bank());
+        for (idx, (_, trigger)) in self
+            .breakpoints
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, (bp, _))| bp.stops_at(pc, bank))
+        {
+            match trigger.hit(self.gameboy) {
+                Ok(true) => return Some((Stop::Breakpoint, format!("Breakpoint {:02} hit", idx))),
+                Ok(false) => (),
+                Err(e) => {
+                    let reason = format!("Breakpoint {:02} condition failed: {}", idx, e);
+                    return Some((Stop::ConditionFailed, reason));
+                }
+            }
+        }
+        None
+    }
+
+    /// Adds a breakpoint that stops execution when `condition` holds, if any.
+    fn add_breakpoint(&mut self, breakpoint: Breakpoint, condition: Option<Condition>) -> String {
+        self.breakpoints.push((breakpoint, Trigger::new(condition)));
+        self.record_events();
+        format!("Inserted breakpoint {}", breakpoint)
+    }
+
+    /// Only records hardware events while there are breakpoints on them.
+    fn record_events(&mut self) {
+        let enabled = self
+            .breakpoints
+            .iter()
+            .any(|(bp, _)| matches!(bp, Breakpoint::Event(_)));
+        self.gameboy.record_events(enabled);
+    }
+
+    /// Returns the context to evaluate expressions in.
+    fn context(&self, hits: u64) -> Context<'_> {
+        Context {
+            gameboy: self.gameboy,
+            hits,
+        }
+    }
+
+    /// Parses the optional `if <expr>` at the end of a command.
+    fn parse_condition(&self, args: &[&str]) -> Result<Option<Condition>, String> {
+        match args {
+            [] => Ok(None),
+            ["if", expr @ ..] if !expr.is_empty() => {
+                Condition::parse(&expr.join(" "), &self.symbols).map(Some)
+            }
+            _ => Err(format!("Expected 'if <expr>', found '{}'", args.join(" "))),
+        }
+    }
+
+    /// Executes one instruction, and takes a snapshot every [`SNAPSHOT_INTERVAL`] instructions
+    /// unless the program is played.
+    fn step(&mut self) {
+        self.gameboy.step();
+        self.position += 1;
+        let last = self.history.back().map_or(0, |(pos, _)| *pos);
+        if !self.played && self.position >= last + SNAPSHOT_INTERVAL {
+            self.history
+                .push_back((self.position, self.gameboy.snapshot()));
+            if self.history.len() > MAX_SNAPSHOTS {
+                self.history.pop_front();
+            }
+        }
+    }
+
+    /// Discards the history, so that the program can't go back before the current instruction.
+    fn reset_history(&mut self) {
+        self.history.clear();
+        self.history
+            .push_back((self.position, self.gameboy.snapshot()));
+    }
+
+    /// Goes back or forward in time to `target`, by restoring the last snapshot before it
+    /// and executing the instructions after it again. `target` has to be in the history.
+    fn goto(&mut self, target: u64) {
+        self.without_profiling(|db| db.replay_to(target));
+    }
+
+    /// Runs `replay` with profiling paused, since it executes instructions that ran before.
+    fn without_profiling<T>(&mut self, replay: impl FnOnce(&mut Self) -> T) -> T {
+        let profiler = self.gameboy.stop_profiling();
+        let result = replay(self);
+        if let Some(profiler) = profiler {
+            self.gameboy.resume_profiling(profiler);
+        }
+        result
+    }
+
+    fn replay_to(&mut self, target: u64) {
+        let Some((pos, snapshot)) = self.history.iter().rev().find(|(pos, _)| *pos <= target)
+        else {
+            return;
+        };
+        self.gameboy.restore(snapshot);
+        self.position = *pos;
+        while self.position < target {
+            self.gameboy.step();
+            self.position += 1;
+        }
+        self.gameboy.take_watch_hits();
+        self.gameboy.take_events();
+    }
+
+    /// Finds the last instruction before the current one at which `hit` describes a reason to stop,
+    /// by executing the history again. `hit` is checked after every instruction, and may take
+    /// watchpoint hits and events. Goes to the found instruction, or stays at the current one.
+    fn find_back<F>(&mut self, hit: F) -> Option<String>
+    where
+        F: FnMut(&mut Self) -> Option<String>,
+    {
+        self.without_profiling(|db| db.search_back(hit))
+    }
+
+    fn search_back<F>(&mut self, mut hit: F) -> Option<String>
+    where
+        F: FnMut(&mut Self) -> Option<String>,
+    {
+        let now = self.position;
+        let starts = self
+            .history
+            .iter()
+            .map(|(pos, _)| *pos)
+            .filter(|pos| *pos < now)
+            .collect::<Vec<u64>>();
+        for (i, start) in starts.iter().enumerate().rev() {
+            let end = starts.get(i + 1).copied().unwrap_or(now);
+            self.replay_to(*start);
+            let mut found = None;
+            while self.position < end {
+                self.gameboy.step();
+                self.position += 1;
+                if self.position < now {
+                    if let Some(reason) = hit(self) {
+                        found = Some((self.position, reason));
+                    }
+                }
+            }
+            if let Some((pos, reason)) = found {
+                self.replay_to(pos);
+                return Some(reason);
+            }
+        }
+        self.replay_to(now);
+        None
+    }
+
+    /// Goes back `n` instructions, or as far back as the history goes.
+    fn reverse_step(&mut self, n: u64) -> String {
+        let oldest = self.history.front().map_or(self.position, |(pos, _)| *pos);
+        let target = self.position.saturating_sub(n).max(oldest);
+        if target == self.position {
+            return self.fail("No history before this instruction".into());
+        }
+        self.snapshot_memory();
+        let steps = self.position - target;
+        self.goto(target);
+        format!(
+            "Stepped back {} instructions to {:#06x}",
+            steps,
+            self.gameboy.regs().pc()
+        )
+    }
+
+    /// Goes back to the previous instruction, stepping over calls.
+    fn reverse_next(&mut self) -> String {
+        self.snapshot_memory();
+        let depth = self.gameboy.call_stack().len();
+        let reason = self.find_back(|db| {
+            (db.gameboy.call_stack().len() <= depth)
+                .then(|| format!("Stepped back to {:#06x}", db.gameboy.regs().pc()))
+        });
+        reason.unwrap_or_else(|| self.fail("No history before this instruction".into()))
+    }
+
+    /// Goes back to the last time a breakpoint or watchpoint was hit.
+    fn reverse_continue(&mut self) -> String {
+        self.snapshot_memory();
+        let steps = self.position;
+        let reason = self.find_back(|db| {
+            let holds = |trigger: &Trigger, gb: &DebugGameboy| trigger.holds(gb).unwrap_or(true);
+            for hit in db.gameboy.take_watch_hits() {
+                if holds(&db.watch_triggers[hit.index], db.gameboy) {
+                    return Some(format!(
+                        "Watchpoint {:02} last hit: {}",
+                        hit.index,
+                        describe_watch_hit(&hit)
+                    ));
+                }
+            }
+            let (pc, bank) = (db.gameboy.regs().pc(), db.gameboy.rom_bank());
+            let events = db.gameboy.take_events();
+            db.breakpoints
+                .iter()
+                .position(|(bp, trigger)| {
+                    let reached = match bp {
+                        Breakpoint::Addr { .. } => bp.stops_at(pc, bank),
+                        Breakpoint::Event(kind) => events.iter().any(|e| kind.matches(e)),
+                    };
+                    reached && holds(trigger, db.gameboy)
+                })
+                .map(|idx| format!("Breakpoint {:02} last hit at {:#06x}", idx, pc))
+        });
+        match reason {
+            Some(reason) => format!("{}, {} instructions back", reason, steps - self.position),
+            None => self.fail("No breakpoint or watchpoint hit in the history".into()),
         }
     }
+
+    /// Executes a single instruction, without checking breakpoints.
+    fn step_once(&mut self) {
+        self.snapshot_memory();
+        self.step();
+    }
+
+    /// Parses an inclusive range of addresses given as `start-end`, or a single address.
+    fn parse_range(&self, input: &str) -> Option<RangeInclusive<u16>> {
+        match input.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (self.parse_addr(start)?, self.parse_addr(end)?);
+                (start <= end).then_some(start..=end)
+            }
+            None => self.parse_addr(input).map(|addr| addr..=addr),
+        }
+    }
+
+    /// Sets the register named `reg` to `val`, which has to fit in the register.
+    fn set_register(regs: &mut Regs, reg: &str, val: i64) -> Result<(), String> {
+        let too_large = || format!("Value {:#x} does not fit in {}", val, reg);
+        let byte = || u8::try_from(val).map_err(|_| too_large());
+        let word = || u16::try_from(val).map_err(|_| too_large());
+        match reg.to_ascii_lowercase().as_str() {
+            "a" => regs.set_a(byte()?),
+            "b" => regs.set_b(byte()?),
+            "c" => regs.set_c(byte()?),
+            "d" => regs.set_d(byte()?),
+            "e" => regs.set_e(byte()?),
+            "h" => regs.set_h(byte()?),
+            "l" => regs.set_l(byte()?),
+            "f" => regs.set_af(regs.af() & 0xff00 | byte()? as u16),
+            "af" => regs.set_af(word()?),
+            "bc" => regs.set_bc(word()?),
+            "de" => regs.set_de(word()?),
+            "hl" => regs.set_hl(word()?),
+            "sp" => regs.set_sp(word()?),
+            "pc" => regs.set_pc(word()?),
+            _ => return Err(format!("Unknown register: {}", reg)),
+        }
+        Ok(())
+    }
+
+    /// Sets the flag named `flag` to `val`.
+    fn set_flag(regs: &mut Regs, flag: &str, val: bool) -> Result<(), String> {
+        let flags = regs.flags_mut();
+        match flag.to_ascii_lowercase().as_str() {
+            "z" => flags.set_z(val),
+            "n" => flags.set_n(val),
+            "h" => flags.set_h(val),
+            "c" => flags.set_c(val),
+            _ => return Err(format!("Unknown flag: {}", flag)),
+        }
+        Ok(())
+    }
+
+    /// Remembers all memory, so that changes can be highlighted after the program runs.
+    fn snapshot_memory(&mut self) {
+        let addr = self.memory_addr;
+        let memory = (0..self.memory_len)
+            .map_while(|i| addr.checked_add(i as u16))
+            .map(|addr| self.gameboy.read_mem(addr));
+        self.prev_memory.clear();
+        self.prev_memory.extend(memory);
+        self.prev_memory_addr = addr;
+    }
+
+    /// Parses and evaluates an expression.
+    fn parse_value(&self, input: &str) -> Option<i64> {
+        let expr = Expr::parse(input, &self.symbols).ok()?;
+        expr.eval(&self.context(0)).ok()
+    }
+
+    /// Parses a byte, given as an expression.
+    fn parse_byte(&self, input: &str) -> Option<u8> {
+        self.parse_value(input)
+            .and_then(|val| u8::try_from(val).ok())
+    }
+
+    /// Parses `0` or `1`.
+    fn parse_bool(&self, input: &str) -> Option<bool> {
+        match input {
+            "0" => Some(false),
+            "1" => Some(true),
+            _ => None,
+        }
+    }
+
+    /// Parses an address, given as an expression such as `$c000`, a label, or `hl + 2`.
+    fn parse_addr(&self, input: &str) -> Option<u16> {
+        self.parse_value(input)
+            .and_then(|addr| u16::try_from(addr).ok())
+    }
+
+    /// Parses where an address breakpoint stops, given as `bank:addr` with the bank in hex,
+    /// as a label, or as an address. Banked addresses without a bank stop in the current bank.
+    fn parse_location(&self, input: &str) -> Option<Breakpoint> {
+        let (bank, addr) = match input.split_once(':') {
+            Some((bank, addr)) => (
+                Some(usize::from_str_radix(bank, 16).ok()?),
+                self.parse_addr(addr)?,
+            ),
+            None => match self.symbols.resolve(input) {
+                Some((bank, addr)) => (Some(bank as usize), addr),
+                None => (None, self.parse_addr(input)?),
+            },
+        };
+        let bank = match addr {
+            0x4000..=0x7fff => Some(bank.unwrap_or(self.gameboy.rom_bank())),
+            _ => None,
+        };
+        Some(Breakpoint::Addr { bank, addr })
+    }
+
+    /// Parses an IO register, given by name or by address.
+    fn parse_io_register(&self, input: &str) -> Option<u16> {
+        let named = IO_REGISTERS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(input));
+        match named.map(|(_, addr)| *addr).or(self.parse_addr(input))? {
+            addr @ (0xff00..=0xff7f | 0xffff) => Some(addr),
+            _ => None,
+        }
+    }
+}
+
+/// Decides whether reaching a breakpoint or watchpoint stops execution.
+pub struct Trigger {
+    condition: Option<Condition>,
+    /// The number of times the breakpoint or watchpoint was reached while running.
+    hits: u64,
+}
+
+impl Trigger {
+    fn new(condition: Option<Condition>) -> Self {
+        Self { condition, hits: 0 }
+    }
+
+    /// Returns whether the condition holds without counting a hit, if there is one.
+    fn holds(&self, gameboy: &DebugGameboy) -> Result<bool, String> {
+        match &self.condition {
+            Some(condition) => condition.holds(&Context {
+                gameboy,
+                hits: self.hits,
+            }),
+            None => Ok(true),
+        }
+    }
+
+    /// Counts a hit, and returns whether the condition holds, if there is one.
+    fn hit(&mut self, gameboy: &DebugGameboy) -> Result<bool, String> {
+        self.hits += 1;
+        self.holds(gameboy)
+    }
+
+    pub fn condition(&self) -> Option<&Condition> {
+        self.condition.as_ref()
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+}
+
+/// Where a breakpoint stops execution.
+#[derive(Clone, Copy)]
+pub enum Breakpoint {
+    /// Before executing the instruction at `addr`, only in `bank` if the address is banked.
+    Addr { bank: Option<usize>, addr: u16 },
+    /// After an instruction causes a hardware event.
+    Event(EventBreak),
+}
+
+impl Breakpoint {
+    /// Returns whether the breakpoint stops before executing the instruction at `pc`,
+    /// while `bank` is mapped to `0x4000 - 0x7fff`.
+    fn stops_at(&self, pc: u16, bank: usize) -> bool {
+        match self {
+            Breakpoint::Addr {
+                bank: bp_bank,
+                addr,
+            } => *addr == pc && bp_bank.is_none_or(|bp_bank| bp_bank == bank),
+            Breakpoint::Event(_) => false,
+        }
+    }
+
+    /// Returns the command that inserts this breakpoint, without its condition.
+    fn command(&self) -> String {
+        match self {
+            Breakpoint::Addr {
+                bank: Some(bank),
+                addr,
+            } => format!("break add {:02x}:{:#06x}", bank, addr),
+            Breakpoint::Addr { bank: None, addr } => format!("break add {:#06x}", addr),
+            Breakpoint::Event(event) => match event {
+                EventBreak::Interrupt(handler) => {
+                    format!("break on int {}", Event::interrupt_name(*handler))
+                }
+                EventBreak::IoWrite(addr) => format!("break on write {:#06x}", addr),
+                EventBreak::BankSwitch => "break on bank".into(),
+                EventBreak::DmaStart => "break on dma".into(),
+                EventBreak::Lcd => "break on lcd".into(),
+                EventBreak::IllegalOpcode => "break on illegal".into(),
+            },
+        }
+    }
+}
+
+impl Display for Breakpoint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Addr {
+                bank: Some(bank),
+                addr,
+            } => write!(f, "at {:02x}:{:04x}", bank, addr),
+            Breakpoint::Addr { bank: None, addr } => write!(f, "at {:#06x}", addr),
+            Breakpoint::Event(event) => write!(f, "on {}", event),
+        }
+    }
+}
+
+/// The hardware events that breakpoints can stop on.
+#[derive(Clone, Copy)]
+pub enum EventBreak {
+    /// Dispatching the interrupt whose handler is at the given address.
+    Interrupt(u16),
+    /// Writing to the IO register at the given address.
+    IoWrite(u16),
+    BankSwitch,
+    DmaStart,
+    /// Turning the LCD on or off.
+    Lcd,
+    IllegalOpcode,
+}
+
+impl EventBreak {
+    /// Returns whether `event` is one this breakpoint stops on.
+    fn matches(&self, event: &Event) -> bool {
+        match (self, event) {
+            (
+                EventBreak::Interrupt(handler),
+                Event::Interrupt {
+                    handler: event_handler,
+                },
+            ) => handler == event_handler,
+            (
+                EventBreak::IoWrite(addr),
+                Event::IoWrite {
+                    addr: event_addr, ..
+                },
+            ) => addr == event_addr,
+            (EventBreak::BankSwitch, Event::BankSwitch { .. })
+            | (EventBreak::DmaStart, Event::DmaStart { .. })
+            | (EventBreak::Lcd, Event::Lcd { .. })
+            | (EventBreak::IllegalOpcode, Event::IllegalOpcode { .. }) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Display for EventBreak {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            EventBreak::Interrupt(handler) => {
+                write!(f, "{} interrupt", Event::interrupt_name(*handler))
+            }
+            EventBreak::IoWrite(addr) => match IO_REGISTERS.iter().find(|(_, a)| a == addr) {
+                Some((name, _)) => write!(f, "write to {}", name),
+                None => write!(f, "write to {:#06x}", addr),
+            },
+            EventBreak::BankSwitch => write!(f, "bank switch"),
+            EventBreak::DmaStart => write!(f, "DMA start"),
+            EventBreak::Lcd => write!(f, "LCD on/off"),
+            EventBreak::IllegalOpcode => write!(f, "illegal opcode"),
+        }
+    }
+}
+
+/// Describes the range and kind of a watchpoint.
+pub fn describe(watchpoint: &Watchpoint) -> String {
+    let (start, end) = (watchpoint.range.start(), watchpoint.range.end());
+    if start == end {
+        format!("{:#06x} ({})", start, watchpoint.kind)
+    } else {
+        format!("{:#06x}-{:#06x} ({})", start, end, watchpoint.kind)
+    }
+}
+
+/// Describes the access that triggered a watchpoint.
+fn describe_watch_hit(hit: &WatchHit) -> String {
+    let by = if hit.interrupt {
+        format!("interrupt dispatch after {:#06x}", hit.pc)
+    } else {
+        format!("{:#06x}", hit.pc)
+    };
+    format!(
+        "{} at {:#06x} by {}, {:#04x} -> {:#04x}",
+        hit.kind, hit.addr, by, hit.old, hit.new
+    )
+}
+
+/// Returns the scanline the PPU is on. Unlike reading `LY`, this ignores `--stub-ly`.
+fn current_ly(gameboy: &DebugGameboy) -> u8 {
+    gameboy.ppu_state().ly
 }
@@ -0,0 +1,118 @@
+use crate::DebugGameboy;
+use flate2::read::GzDecoder;
+use gabbro::Mnemonic;
+use std::{
+    collections::VecDeque,
+    fmt::{self, Display, Formatter},
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+/// The first two bytes of every gzip-compressed file.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Describes the first line at which the emulator diverged from a reference trace log.
+pub struct Divergence {
+    line: usize,
+    expected: String,
+    actual: String,
+    context: Vec<String>,
+    instruction: Option<(u16, Vec<u8>, Mnemonic)>,
+}
+
+/// Opens the reference trace log at `path`, decompressing it if it is gzip-compressed.
+pub fn open_log(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(BufReader::new(GzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Runs `gameboy` one instruction per line of the reference trace `log`, comparing the
+/// emulator state against each line before executing the instruction.
+/// Returns the first line that does not match, together with the `context` lines before it,
+/// or `None` if the whole log matched.
+pub fn run(
+    gameboy: &mut DebugGameboy,
+    log: impl BufRead,
+    context: usize,
+) -> io::Result<Option<Divergence>> {
+    let mut preceding = VecDeque::with_capacity(context);
+    let mut prev_pc = None;
+    for (idx, line) in log.lines().enumerate() {
+        let expected = line?.trim().to_string();
+        if expected.is_empty() {
+            continue;
+        }
+        let actual = gameboy.trace();
+        if actual != expected {
+            return Ok(Some(Divergence {
+                line: idx + 1,
+                expected,
+                actual,
+                context: preceding.into(),
+                instruction: prev_pc.map(|pc| {
+                    let (bytes, mnemonic) = gameboy.disasm_at(pc);
+                    (pc, bytes, mnemonic)
+                }),
+            }));
+        }
+        if context > 0 {
+            if preceding.len() == context {
+                preceding.pop_front();
+            }
+            preceding.push_back(expected);
+        }
+        prev_pc = Some(gameboy.regs().pc());
+        gameboy.step();
+    }
+    Ok(None)
+}
+
+impl Divergence {
+    /// Returns the names of the fields that differ between the expected and actual line,
+    /// together with both values.
+    fn differing_fields(&self) -> Vec<(&str, &str, &str)> {
+        self.expected
+            .split_whitespace()
+            .zip(self.actual.split_whitespace())
+            .filter(|(expected, actual)| expected != actual)
+            .filter_map(|(expected, actual)| {
+                let (name, expected) = expected.split_once(':')?;
+                let (_, actual) = actual.split_once(':')?;
+                Some((name, expected, actual))
+            })
+            .collect()
+    }
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Divergence at line {}", self.line)?;
+        for line in &self.context {
+            writeln!(f, "           {}", line)?;
+        }
+        if let Some((addr, bytes, mnemonic)) = &self.instruction {
+            writeln!(
+                f,
+                "After:     {:#06x} {:08} {}",
+                addr,
+                bytes
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect::<Vec<String>>()
+                    .join(" "),
+                mnemonic
+            )?;
+        }
+        writeln!(f, "Expected:  {}", self.expected)?;
+        writeln!(f, "Actual:    {}", self.actual)?;
+        for (name, expected, actual) in self.differing_fields() {
+            writeln!(f, "  {}: expected {}, got {}", name, expected, actual)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,456 @@
+use crate::DebugGameboy;
+use gabbro::SymbolTable;
+use std::fmt::{self, Display, Formatter};
+
+/// IO registers that can be referred to by name, and their addresses.
+pub const IO_REGISTERS: [(&str, u16); 43] = [
+    ("P1", 0xff00),
+    ("JOYP", 0xff00),
+    ("SB", 0xff01),
+    ("SC", 0xff02),
+    ("DIV", 0xff04),
+    ("TIMA", 0xff05),
+    ("TMA", 0xff06),
+    ("TAC", 0xff07),
+    ("IF", 0xff0f),
+    ("NR10", 0xff10),
+    ("NR11", 0xff11),
+    ("NR12", 0xff12),
+    ("NR13", 0xff13),
+    ("NR14", 0xff14),
+    ("NR21", 0xff16),
+    ("NR22", 0xff17),
+    ("NR23", 0xff18),
+    ("NR24", 0xff19),
+    ("NR30", 0xff1a),
+    ("NR31", 0xff1b),
+    ("NR32", 0xff1c),
+    ("NR33", 0xff1d),
+    ("NR34", 0xff1e),
+    ("NR41", 0xff20),
+    ("NR42", 0xff21),
+    ("NR43", 0xff22),
+    ("NR44", 0xff23),
+    ("NR50", 0xff24),
+    ("NR51", 0xff25),
+    ("NR52", 0xff26),
+    ("LCDC", 0xff40),
+    ("STAT", 0xff41),
+    ("SCY", 0xff42),
+    ("SCX", 0xff43),
+    ("LY", 0xff44),
+    ("LYC", 0xff45),
+    ("DMA", 0xff46),
+    ("BGP", 0xff47),
+    ("OBP0", 0xff48),
+    ("OBP1", 0xff49),
+    ("WY", 0xff4a),
+    ("WX", 0xff4b),
+    ("IE", 0xffff),
+];
+
+/// Operators, ordered so that longer operators are matched before their prefixes.
+const OPERATORS: [&str; 24] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "<", ">", "|", "^", "&", "+", "-", "*", "/",
+    "%", "!", "~", "(", ")", "[", "]",
+];
+
+/// The binary operators, from the lowest to the highest precedence.
+const PRECEDENCE: [&[(&str, BinaryOp)]; 10] = [
+    &[("||", BinaryOp::Or)],
+    &[("&&", BinaryOp::And)],
+    &[("==", BinaryOp::Eq), ("!=", BinaryOp::Ne)],
+    &[
+        ("<", BinaryOp::Lt),
+        ("<=", BinaryOp::Le),
+        (">", BinaryOp::Gt),
+        (">=", BinaryOp::Ge),
+    ],
+    &[("|", BinaryOp::BitOr)],
+    &[("^", BinaryOp::BitXor)],
+    &[("&", BinaryOp::BitAnd)],
+    &[("<<", BinaryOp::Shl), (">>", BinaryOp::Shr)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[
+        ("*", BinaryOp::Mul),
+        ("/", BinaryOp::Div),
+        ("%", BinaryOp::Rem),
+    ],
+];
+
+#[derive(Clone, Copy)]
+pub enum Register {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    Af,
+    Bc,
+    De,
+    Hl,
+    Sp,
+    Pc,
+}
+
+#[derive(Clone, Copy)]
+pub enum Flag {
+    Z,
+    N,
+    H,
+    C,
+}
+
+#[derive(Clone, Copy)]
+pub enum UnaryOp {
+    Not,
+    Neg,
+    Complement,
+}
+
+#[derive(Clone, Copy)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+/// An expression over the state of the Game Boy, such as `a == 3 && [ff44] > 100`.
+///
+/// Operands are numbers, registers (`a`, `hl`, `pc`, ...), flags (`zf`, `nf`, `hf`, `cf`),
+/// IO registers by name (`LY`, `LCDC`, ...), labels, the ROM bank (`bank`), the hit count of
+/// a breakpoint (`hits`), and memory (`[addr]` for a byte, `word[addr]` for a word).
+/// Numbers are decimal, unless prefixed with `$` or `0x` for hexadecimal or `0b` for binary.
+/// Inside brackets, unprefixed numbers are hexadecimal. Names are resolved before that,
+/// so that `[c]` or `[de]` always mean the registers.
+/// The operators and their precedence are the same as in Rust. Comparisons result in 0 or 1.
+pub enum Expr {
+    Num(i64),
+    Register(Register),
+    Flag(Flag),
+    Bank,
+    Hits,
+    Byte(Box<Expr>),
+    Word(Box<Expr>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+/// The state an expression is evaluated against.
+pub struct Context<'a> {
+    pub gameboy: &'a DebugGameboy,
+    /// The number of times the breakpoint or watchpoint being checked was hit, if any.
+    pub hits: u64,
+}
+
+impl Expr {
+    /// Parses `src`, resolving labels with `symbols`.
+    pub fn parse(src: &str, symbols: &SymbolTable) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(src)?,
+            pos: 0,
+            depth: 0,
+            symbols,
+        };
+        let expr = parser.binary(0)?;
+        match parser.tokens.get(parser.pos) {
+            Some(token) => Err(format!("Unexpected {}", token)),
+            None => Ok(expr),
+        }
+    }
+
+    /// Evaluates the expression against `ctx`.
+    pub fn eval(&self, ctx: &Context) -> Result<i64, String> {
+        let regs = ctx.gameboy.regs();
+        let read = |addr: i64| ctx.gameboy.read_mem(addr as u16) as i64;
+        Ok(match self {
+            Expr::Num(n) => *n,
+            Expr::Register(reg) => match reg {
+                Register::A => regs.a() as i64,
+                Register::F => (regs.af() & 0xff) as i64,
+                Register::B => regs.b() as i64,
+                Register::C => regs.c() as i64,
+                Register::D => regs.d() as i64,
+                Register::E => regs.e() as i64,
+                Register::H => regs.h() as i64,
+                Register::L => regs.l() as i64,
+                Register::Af => regs.af() as i64,
+                Register::Bc => regs.bc() as i64,
+                Register::De => regs.de() as i64,
+                Register::Hl => regs.hl() as i64,
+                Register::Sp => regs.sp() as i64,
+                Register::Pc => regs.pc() as i64,
+            },
+            Expr::Flag(flag) => match flag {
+                Flag::Z => regs.flags().z() as i64,
+                Flag::N => regs.flags().n() as i64,
+                Flag::H => regs.flags().h() as i64,
+                Flag::C => regs.flags().c() as i64,
+            },
+            Expr::Bank => ctx.gameboy.rom_bank() as i64,
+            Expr::Hits => ctx.hits as i64,
+            Expr::Byte(addr) => read(addr.eval(ctx)?),
+            Expr::Word(addr) => {
+                let addr = addr.eval(ctx)?;
+                read(addr) | read(addr.wrapping_add(1)) << 8
+            }
+            Expr::Unary(op, expr) => {
+                let val = expr.eval(ctx)?;
+                match op {
+                    UnaryOp::Not => (val == 0) as i64,
+                    UnaryOp::Neg => val.wrapping_neg(),
+                    UnaryOp::Complement => !val,
+                }
+            }
+            // Short-circuit, so that e.g. `hl != 0 && [hl] == 1` does not read through `hl` needlessly.
+            Expr::Binary(BinaryOp::And, lhs, rhs) => {
+                (lhs.eval(ctx)? != 0 && rhs.eval(ctx)? != 0) as i64
+            }
+            Expr::Binary(BinaryOp::Or, lhs, rhs) => {
+                (lhs.eval(ctx)? != 0 || rhs.eval(ctx)? != 0) as i64
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(ctx)?, rhs.eval(ctx)?);
+                match op {
+                    BinaryOp::Or | BinaryOp::And => unreachable!(),
+                    BinaryOp::Eq => (lhs == rhs) as i64,
+                    BinaryOp::Ne => (lhs != rhs) as i64,
+                    BinaryOp::Lt => (lhs < rhs) as i64,
+                    BinaryOp::Le => (lhs <= rhs) as i64,
+                    BinaryOp::Gt => (lhs > rhs) as i64,
+                    BinaryOp::Ge => (lhs >= rhs) as i64,
+                    BinaryOp::BitOr => lhs | rhs,
+                    BinaryOp::BitXor => lhs ^ rhs,
+                    BinaryOp::BitAnd => lhs & rhs,
+                    BinaryOp::Shl => lhs.checked_shl(rhs as u32).unwrap_or(0),
+                    BinaryOp::Shr => lhs.checked_shr(rhs as u32).unwrap_or(0),
+                    BinaryOp::Add => lhs.wrapping_add(rhs),
+                    BinaryOp::Sub => lhs.wrapping_sub(rhs),
+                    BinaryOp::Mul => lhs.wrapping_mul(rhs),
+                    BinaryOp::Div => lhs.checked_div(rhs).ok_or("Division by zero")?,
+                    BinaryOp::Rem => lhs.checked_rem(rhs).ok_or("Division by zero")?,
+                }
+            }
+        })
+    }
+}
+
+/// A condition of a breakpoint or watchpoint, which keeps its source for display.
+pub struct Condition {
+    src: String,
+    expr: Expr,
+}
+
+impl Condition {
+    /// Parses `src` as an expression, resolving labels with `symbols`.
+    pub fn parse(src: &str, symbols: &SymbolTable) -> Result<Self, String> {
+        Ok(Self {
+            src: src.to_string(),
+            expr: Expr::parse(src, symbols)?,
+        })
+    }
+
+    /// Returns whether the condition holds, i.e. evaluates to a non-zero value.
+    pub fn holds(&self, ctx: &Context) -> Result<bool, String> {
+        self.expr.eval(ctx).map(|val| val != 0)
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.src)
+    }
+}
+
+enum Token {
+    Op(&'static str),
+    Word(String),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Token::Op(op) => write!(f, "'{}'", op),
+            Token::Word(word) => write!(f, "'{}'", word),
+        }
+    }
+}
+
+/// Splits `src` into operators and words, which are numbers or names.
+fn tokenize(src: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = src.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$')))
+                .unwrap_or(rest.len());
+            if len == 0 {
+                return Err(format!("Unexpected character '{}'", c));
+            }
+            tokens.push(Token::Word(rest[..len].to_string()));
+            rest = &rest[len..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// The number of brackets the parser is inside of.
+    depth: usize,
+    symbols: &'a SymbolTable,
+}
+
+impl Parser<'_> {
+    /// Parses binary operators with at least the precedence at `level`.
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        let Some(ops) = PRECEDENCE.get(level) else {
+            return self.unary();
+        };
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(Token::Op(token)) = self.tokens.get(self.pos) {
+            let Some((_, op)) = ops.iter().find(|(op, _)| op == token) else {
+                break;
+            };
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+            lhs = Expr::Binary(*op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let op = match self.tokens.get(self.pos) {
+            Some(Token::Op("!")) => UnaryOp::Not,
+            Some(Token::Op("-")) => UnaryOp::Neg,
+            Some(Token::Op("~")) => UnaryOp::Complement,
+            _ => return self.primary(),
+        };
+        self.pos += 1;
+        Ok(Expr::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .ok_or("Unexpected end of expression")?;
+        self.pos += 1;
+        match token {
+            Token::Op("(") => {
+                let expr = self.binary(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Op("[") => Ok(Expr::Byte(Box::new(self.memory()?))),
+            Token::Word(word) if word == "word" => {
+                self.expect("[")?;
+                Ok(Expr::Word(Box::new(self.memory()?)))
+            }
+            Token::Word(word) => {
+                let word = word.clone();
+                self.word(&word)
+            }
+            Token::Op(_) => Err(format!("Unexpected {}", token)),
+        }
+    }
+
+    /// Parses the address between brackets, after the opening bracket.
+    fn memory(&mut self) -> Result<Expr, String> {
+        self.depth += 1;
+        let addr = self.binary(0)?;
+        self.depth -= 1;
+        self.expect("]")?;
+        Ok(addr)
+    }
+
+    /// Parses a number or a name.
+    fn word(&self, word: &str) -> Result<Expr, String> {
+        let lower = word.to_ascii_lowercase();
+        let register = match lower.as_str() {
+            "a" => Some(Register::A),
+            "f" => Some(Register::F),
+            "b" => Some(Register::B),
+            "c" => Some(Register::C),
+            "d" => Some(Register::D),
+            "e" => Some(Register::E),
+            "h" => Some(Register::H),
+            "l" => Some(Register::L),
+            "af" => Some(Register::Af),
+            "bc" => Some(Register::Bc),
+            "de" => Some(Register::De),
+            "hl" => Some(Register::Hl),
+            "sp" => Some(Register::Sp),
+            "pc" => Some(Register::Pc),
+            _ => None,
+        };
+        if let Some(register) = register {
+            return Ok(Expr::Register(register));
+        }
+        match lower.as_str() {
+            "zf" => return Ok(Expr::Flag(Flag::Z)),
+            "nf" => return Ok(Expr::Flag(Flag::N)),
+            "hf" => return Ok(Expr::Flag(Flag::H)),
+            "cf" => return Ok(Expr::Flag(Flag::C)),
+            "bank" => return Ok(Expr::Bank),
+            "hits" => return Ok(Expr::Hits),
+            _ => (),
+        }
+        if let Some((_, addr)) = IO_REGISTERS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(word))
+        {
+            return Ok(Expr::Byte(Box::new(Expr::Num(*addr as i64))));
+        }
+        if let Some((_, addr)) = self.symbols.resolve(word) {
+            return Ok(Expr::Num(addr as i64));
+        }
+
+        let num = if let Some(hex) = lower.strip_prefix('$').or(lower.strip_prefix("0x")) {
+            i64::from_str_radix(hex, 16)
+        } else if let Some(bin) = lower.strip_prefix("0b") {
+            i64::from_str_radix(bin, 2)
+        } else if self.depth > 0 {
+            i64::from_str_radix(&lower, 16)
+        } else {
+            lower.parse()
+        };
+        num.map(Expr::Num)
+            .map_err(|_| format!("Unknown name or invalid number '{}'", word))
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(token)) if *token == op => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(token) => Err(format!("Expected '{}', found {}", op, token)),
+            None => Err(format!("Expected '{}'", op)),
+        }
+    }
+}
@@ -0,0 +1,413 @@
+use crate::DebugGameboy;
+use gabbro::{Event, Regs, WatchKind, Watchpoint};
+use std::{
+    io::{self, BufReader, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+/// The byte a client sends to interrupt a running program.
+const INTERRUPT: u8 = 0x03;
+
+/// The number of steps between checks whether the client interrupted a running program.
+const STEPS_PER_POLL: u64 = 4096;
+
+/// The names of the registers, in the order the client reads and writes them.
+/// Every register is 16 bits wide and sent in little-endian, like the first registers of GDB's Z80 target.
+const REGISTERS: [&str; 6] = ["af", "bc", "de", "hl", "sp", "pc"];
+
+/// Why the program stopped, reported to the client as a signal.
+enum Stop {
+    /// A step finished or a breakpoint was hit.
+    Trap,
+    /// The watchpoint of the given type was hit while accessing the address.
+    Watch(u8, u16),
+    /// The client interrupted the program.
+    Interrupted,
+    /// The CPU fetched an illegal opcode.
+    Illegal,
+    /// The client closed the connection while the program was running.
+    Disconnected,
+}
+
+/// A GDB stub, serving a single client over the remote serial protocol.
+pub struct GdbServer<'a> {
+    gameboy: &'a mut DebugGameboy,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    breakpoints: Vec<u16>,
+    /// The type, address and length requested by the client for every watchpoint of `gameboy`,
+    /// by index. Access watchpoints are added as a read and a write watchpoint.
+    watchpoints: Vec<(u8, u16, u16)>,
+    /// Whether packets are acknowledged, until the client asks to stop.
+    ack: bool,
+}
+
+impl<'a> GdbServer<'a> {
+    /// Waits for a client to connect to `listener`.
+    pub fn accept(gameboy: &'a mut DebugGameboy, listener: &TcpListener) -> io::Result<Self> {
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        gameboy.record_events(true);
+        Ok(Self {
+            gameboy,
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            ack: true,
+        })
+    }
+
+    /// Serves the client until it detaches, kills the program or disconnects.
+    pub fn serve(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            let reply = match packet.as_bytes() {
+                [b'D', ..] | [b'k', ..] => {
+                    self.send("OK")?;
+                    return Ok(());
+                }
+                [b's', ..] => {
+                    self.gameboy.take_events();
+                    self.gameboy.step();
+                    let stop = self.stop_reason().unwrap_or(Stop::Trap);
+                    self.stop_reply(stop)
+                }
+                [b'c', ..] => {
+                    let stop = self.resume()?;
+                    if let Stop::Disconnected = stop {
+                        return Ok(());
+                    }
+                    self.stop_reply(stop)
+                }
+                _ => self.handle(&packet),
+            };
+            self.send(&reply)?;
+        }
+        Ok(())
+    }
+
+    /// Handles a packet that does not run the program, and returns the reply.
+    /// Unsupported packets get an empty reply.
+    fn handle(&mut self, packet: &str) -> String {
+        let Some(cmd) = packet.get(..1) else {
+            return String::new();
+        };
+        let args = &packet[1..];
+        let result = match cmd {
+            "?" => Some(self.stop_reply(Stop::Trap)),
+            "g" => Some(self.read_registers()),
+            "G" => self.write_registers(args),
+            "p" => self.read_register(args),
+            "P" => self.write_register(args),
+            "m" => self.read_memory(args),
+            "M" => self.write_memory(args),
+            "Z" => self.insert_point(args),
+            "z" => self.remove_point(args),
+            "H" => Some("OK".into()),
+            "q" | "Q" => Some(self.query(packet)),
+            _ => Some(String::new()),
+        };
+        result.unwrap_or_else(|| "E01".into())
+    }
+
+    /// Answers a general query.
+    fn query(&mut self, packet: &str) -> String {
+        match packet.split(':').next().unwrap_or_default() {
+            "qSupported" => "PacketSize=4000;QStartNoAckMode+".into(),
+            "QStartNoAckMode" => {
+                // The client still acknowledges this reply, but no packet after it.
+                self.ack = false;
+                "OK".into()
+            }
+            "qAttached" => "1".into(),
+            "qC" => "QC1".into(),
+            "qfThreadInfo" => "m1".into(),
+            "qsThreadInfo" => "l".into(),
+            _ => String::new(),
+        }
+    }
+
+    /// Runs the program until it stops, checking for an interrupt from the client now and then.
+    fn resume(&mut self) -> io::Result<Stop> {
+        self.gameboy.take_watch_hits();
+        self.gameboy.take_events();
+        self.writer.set_nonblocking(true)?;
+        let stop = self.run_polling();
+        // Restore blocking reads even if polling failed, since the stream is shared with `reader`.
+        self.writer.set_nonblocking(false)?;
+        stop
+    }
+
+    /// Steps until the program stops, polling the non-blocking stream for an interrupt.
+    fn run_polling(&mut self) -> io::Result<Stop> {
+        let mut steps = 0u64;
+        loop {
+            self.gameboy.step();
+            if let Some(stop) = self.stop_reason() {
+                return Ok(stop);
+            }
+            steps += 1;
+            if steps.is_multiple_of(STEPS_PER_POLL) {
+                let mut byte = [0];
+                match self.reader.read(&mut byte) {
+                    Ok(0) => return Ok(Stop::Disconnected),
+                    Ok(_) if byte[0] == INTERRUPT => return Ok(Stop::Interrupted),
+                    Ok(_) => (),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => (),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+
+    /// Describes why the program should stop after the last step, if it should.
+    fn stop_reason(&mut self) -> Option<Stop> {
+        let illegal = self
+            .gameboy
+            .take_events()
+            .iter()
+            .any(|event| matches!(event, Event::IllegalOpcode { .. }));
+        // A locked up CPU never runs again, so stop on every step instead of hanging.
+        if illegal || self.gameboy.locked() {
+            return Some(Stop::Illegal);
+        }
+        if let Some(hit) = self.gameboy.take_watch_hits().first() {
+            let (kind, _, _) = self.watchpoints[hit.index];
+            return Some(Stop::Watch(kind, hit.addr));
+        }
+        self.breakpoints
+            .contains(&self.gameboy.regs().pc())
+            .then_some(Stop::Trap)
+    }
+
+    fn stop_reply(&self, stop: Stop) -> String {
+        match stop {
+            Stop::Trap | Stop::Disconnected => "S05".into(),
+            Stop::Watch(kind, addr) => {
+                let name = match kind {
+                    2 => "watch",
+                    3 => "rwatch",
+                    _ => "awatch",
+                };
+                format!("T05{}:{:x};", name, addr)
+            }
+            Stop::Interrupted => "S02".into(),
+            Stop::Illegal => "S04".into(),
+        }
+    }
+
+    fn read_registers(&self) -> String {
+        let regs = self.gameboy.regs();
+        REGISTERS
+            .iter()
+            .map(|name| hex_word(register(regs, name)))
+            .collect()
+    }
+
+    fn write_registers(&mut self, args: &str) -> Option<String> {
+        let bytes = parse_hex_bytes(args)?;
+        if bytes.len() != REGISTERS.len() * 2 {
+            return None;
+        }
+        let regs = self.gameboy.regs_mut();
+        for (name, val) in REGISTERS.iter().zip(bytes.chunks(2)) {
+            set_register(regs, name, u16::from_le_bytes([val[0], val[1]]));
+        }
+        Some("OK".into())
+    }
+
+    fn read_register(&self, args: &str) -> Option<String> {
+        let name = REGISTERS.get(usize::from_str_radix(args, 16).ok()?)?;
+        Some(hex_word(register(self.gameboy.regs(), name)))
+    }
+
+    fn write_register(&mut self, args: &str) -> Option<String> {
+        let (idx, val) = args.split_once('=')?;
+        let name = REGISTERS.get(usize::from_str_radix(idx, 16).ok()?)?;
+        let bytes = parse_hex_bytes(val)?;
+        let [low, high] = bytes[..] else {
+            return None;
+        };
+        set_register(
+            self.gameboy.regs_mut(),
+            name,
+            u16::from_le_bytes([low, high]),
+        );
+        Some("OK".into())
+    }
+
+    fn read_memory(&self, args: &str) -> Option<String> {
+        let (addr, len) = parse_addr_len(args)?;
+        Some(
+            (0..len)
+                .map(|i| format!("{:02x}", self.gameboy.read_mem(addr.wrapping_add(i))))
+                .collect(),
+        )
+    }
+
+    /// Writes memory, overwriting the ROM if needed.
+    fn write_memory(&mut self, args: &str) -> Option<String> {
+        let (range, data) = args.split_once(':')?;
+        let (addr, len) = parse_addr_len(range)?;
+        let bytes = parse_hex_bytes(data)?;
+        if bytes.len() != len as usize {
+            return None;
+        }
+        for (i, byte) in bytes.into_iter().enumerate() {
+            self.gameboy.write_mem(addr.wrapping_add(i as u16), byte);
+        }
+        Some("OK".into())
+    }
+
+    /// Inserts a breakpoint of type 0 or 1, or a watchpoint of type 2 (write), 3 (read) or 4 (access).
+    fn insert_point(&mut self, args: &str) -> Option<String> {
+        let (kind, addr, len) = parse_point(args)?;
+        let range = addr..=addr.saturating_add(len.max(1) - 1);
+        let kinds: &[WatchKind] = match kind {
+            0 | 1 => {
+                self.breakpoints.push(addr);
+                &[]
+            }
+            2 => &[WatchKind::Write],
+            3 => &[WatchKind::Read],
+            4 => &[WatchKind::Read, WatchKind::Write],
+            _ => return Some(String::new()),
+        };
+        for watch_kind in kinds {
+            self.gameboy.add_watchpoint(Watchpoint {
+                range: range.clone(),
+                kind: *watch_kind,
+            });
+            self.watchpoints.push((kind, addr, len));
+        }
+        Some("OK".into())
+    }
+
+    fn remove_point(&mut self, args: &str) -> Option<String> {
+        let point = parse_point(args)?;
+        match point.0 {
+            0 | 1 => {
+                let idx = self.breakpoints.iter().position(|addr| *addr == point.1)?;
+                self.breakpoints.remove(idx);
+            }
+            2..=4 => {
+                // Remove from the back, so that the indices of the remaining ones stay valid.
+                for idx in (0..self.watchpoints.len()).rev() {
+                    if self.watchpoints[idx] == point {
+                        self.watchpoints.remove(idx);
+                        self.gameboy.remove_watchpoint(idx);
+                    }
+                }
+            }
+            _ => return Some(String::new()),
+        }
+        Some("OK".into())
+    }
+
+    /// Reads the next packet, acknowledging it. Returns `None` when the client disconnects.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        let mut byte = [0];
+        loop {
+            // Skip acks, and interrupts that arrive after the program stopped.
+            loop {
+                if self.reader.read(&mut byte)? == 0 {
+                    return Ok(None);
+                }
+                if byte[0] == b'$' {
+                    break;
+                }
+            }
+            let mut data = Vec::new();
+            loop {
+                if self.reader.read(&mut byte)? == 0 {
+                    return Ok(None);
+                }
+                if byte[0] == b'#' {
+                    break;
+                }
+                data.push(byte[0]);
+            }
+            let mut checksum = [0; 2];
+            self.reader.read_exact(&mut checksum)?;
+            let valid = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok())
+                == Some(checksum_of(&data));
+            if self.ack {
+                self.writer.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.writer.write_all(packet.as_bytes())?;
+        self.writer.flush()
+    }
+}
+
+/// Returns the register named `name`, which is one of [`REGISTERS`].
+fn register(regs: &Regs, name: &str) -> u16 {
+    match name {
+        "af" => regs.af(),
+        "bc" => regs.bc(),
+        "de" => regs.de(),
+        "hl" => regs.hl(),
+        "sp" => regs.sp(),
+        _ => regs.pc(),
+    }
+}
+
+/// Sets the register named `name`, which is one of [`REGISTERS`].
+fn set_register(regs: &mut Regs, name: &str, val: u16) {
+    match name {
+        "af" => regs.set_af(val),
+        "bc" => regs.set_bc(val),
+        "de" => regs.set_de(val),
+        "hl" => regs.set_hl(val),
+        "sp" => regs.set_sp(val),
+        _ => regs.set_pc(val),
+    }
+}
+
+/// Formats `val` as little-endian hex.
+fn hex_word(val: u16) -> String {
+    val.to_le_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn parse_hex_bytes(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Parses `addr,len`, both in hex.
+fn parse_addr_len(args: &str) -> Option<(u16, u16)> {
+    let (addr, len) = args.split_once(',')?;
+    Some((
+        u16::from_str_radix(addr, 16).ok()?,
+        u16::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+/// Parses the `type,addr,kind` of a breakpoint or watchpoint packet.
+fn parse_point(args: &str) -> Option<(u8, u16, u16)> {
+    let (kind, rest) = args.split_once(',')?;
+    let (addr, len) = parse_addr_len(rest.split(';').next()?)?;
+    Some((kind.parse().ok()?, addr, len))
+}
+
+/// Returns the checksum of a packet, which is the sum of its bytes modulo 256.
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
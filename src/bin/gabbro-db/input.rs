@@ -0,0 +1,128 @@
+/// The command line, with a cursor and the history of entered commands.
+#[derive(Default)]
+pub struct InputLine {
+    text: String,
+    /// The position of the cursor, in characters.
+    cursor: usize,
+    history: Vec<String>,
+    /// The entry of `history` being shown, while browsing it.
+    browsing: Option<usize>,
+    /// The line that was being edited before browsing the history.
+    draft: String,
+}
+
+impl InputLine {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns the position of the cursor, in characters.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Returns the text before the cursor.
+    pub fn before_cursor(&self) -> &str {
+        &self.text[..self.byte_index(self.cursor)]
+    }
+
+    /// Replaces the text, and moves the cursor to its end.
+    pub fn set(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.cursor = text.chars().count();
+    }
+
+    /// Inserts `c` at the cursor.
+    pub fn insert(&mut self, c: char) {
+        self.text.insert(self.byte_index(self.cursor), c);
+        self.cursor += 1;
+    }
+
+    /// Inserts `s` at the cursor.
+    pub fn insert_str(&mut self, s: &str) {
+        s.chars().for_each(|c| self.insert(c));
+    }
+
+    /// Removes the character before the cursor.
+    pub fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.text.remove(self.byte_index(self.cursor));
+        }
+    }
+
+    /// Removes the character at the cursor.
+    pub fn delete(&mut self) {
+        if self.cursor < self.text.chars().count() {
+            self.text.remove(self.byte_index(self.cursor));
+        }
+    }
+
+    pub fn move_left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn move_right(&mut self) {
+        self.cursor = (self.cursor + 1).min(self.text.chars().count());
+    }
+
+    pub fn move_home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn move_end(&mut self) {
+        self.cursor = self.text.chars().count();
+    }
+
+    /// Shows the previous command in the history.
+    pub fn history_prev(&mut self) {
+        let idx = match self.browsing {
+            Some(0) => return,
+            Some(idx) => idx - 1,
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = self.text.clone();
+                self.history.len() - 1
+            }
+        };
+        self.browsing = Some(idx);
+        let text = self.history[idx].clone();
+        self.set(&text);
+    }
+
+    /// Shows the next command in the history, or the line being edited before browsing it.
+    pub fn history_next(&mut self) {
+        let Some(idx) = self.browsing else {
+            return;
+        };
+        if idx + 1 < self.history.len() {
+            self.browsing = Some(idx + 1);
+            let text = self.history[idx + 1].clone();
+            self.set(&text);
+        } else {
+            self.browsing = None;
+            let draft = std::mem::take(&mut self.draft);
+            self.set(&draft);
+        }
+    }
+
+    /// Clears the line and returns it, adding it to the history unless it repeats the last entry.
+    pub fn submit(&mut self) -> String {
+        let text = std::mem::take(&mut self.text);
+        if !text.trim().is_empty() && self.history.last() != Some(&text) {
+            self.history.push(text.clone());
+        }
+        self.cursor = 0;
+        self.browsing = None;
+        self.draft.clear();
+        text
+    }
+
+    /// Returns the byte index of the character at `pos`.
+    fn byte_index(&self, pos: usize) -> usize {
+        self.text
+            .char_indices()
+            .nth(pos)
+            .map_or(self.text.len(), |(idx, _)| idx)
+    }
+}
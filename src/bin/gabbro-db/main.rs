@@ -1,42 +1,283 @@
+mod dap;
 mod debugger;
+mod diff;
+mod expr;
+mod gdb;
+mod input;
+mod peripherals;
+mod source;
 mod ui;
 use crossterm::{
-    event::{self, Event, KeyCode},
+    event::{self, Event, KeyCode, KeyModifiers},
     execute,
     terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use debugger::GameboyDebugger;
-use gabbro::Gameboy;
-use std::{env, fs, io, time::Duration};
+use dap::DapServer;
+use debugger::{GameboyDebugger, Stop};
+use gabbro::{ButtonState, CodeDataLog, Disassembly, Gameboy, SymbolTable};
+use gdb::GdbServer;
+use peripherals::{Button, CellJoypad, FrameLcd, Keys, SharedFrame};
+use std::{
+    cell::Cell,
+    env,
+    fs::{self, File},
+    io::{self, BufRead, BufReader},
+    net::TcpListener,
+    path::Path,
+    process,
+    rc::Rc,
+    time::Duration,
+};
 use tui::{
     backend::{Backend, CrosstermBackend},
     Terminal,
 };
 
+const USAGE: &str = "Usage: gabbro-db <rom> [--diff <log> [--context <n>] [--stub-ly]] [--disasm <dir> [--cdl <log>]] [--gdb <port>] [--batch [<script>] [--json] [--max-cycles <n>]]
+       gabbro-db --dap [<port>]";
+
+/// The number of matching lines shown before a divergence by default.
+const DEFAULT_CONTEXT: usize = 10;
+
+/// The Game Boy being debugged, which keeps its last frame and can be played from the keyboard.
+pub type DebugGameboy = Gameboy<FrameLcd, (), CellJoypad, ()>;
+
 fn main() {
-    if let Some(rom_path) = env::args().nth(1) {
-        let rom = fs::read(rom_path)
-            .map_err(|_| log::error!("ROM file could not be opened"))
-            .unwrap();
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    // A DAP client launches the ROM itself.
+    if args.first().is_some_and(|arg| arg == "--dap") {
+        match args.get(1).map(|port| port.parse::<u16>()) {
+            None => serve_dap_stdio(),
+            Some(Ok(port)) => serve_dap(port),
+            Some(Err(_)) => println!("Invalid port\n{}", USAGE),
+        }
+        return;
+    }
+    let Some(rom_path) = args.first() else {
+        log::error!("Please provide a path to a valid Game Boy ROM.");
+        println!("{}", USAGE);
+        return;
+    };
 
-        let mut gb = Gameboy::builder(rom).build();
+    let mut diff_log = None;
+    let mut context = DEFAULT_CONTEXT;
+    let mut stub_ly = false;
+    let mut disasm_dir = None;
+    let mut cdl_path = None;
+    let mut gdb_port = None;
+    let mut batch = None;
+    let mut json = false;
+    let mut max_cycles = None;
+    let mut opts = args[1..].iter().peekable();
+    while let Some(opt) = opts.next() {
+        match opt.as_str() {
+            "--diff" => match opts.next() {
+                Some(path) => diff_log = Some(path.clone()),
+                None => return println!("Missing log path\n{}", USAGE),
+            },
+            "--context" => match opts.next().map(|n| n.parse::<usize>()) {
+                Some(Ok(n)) => context = n,
+                _ => return println!("Invalid number of context lines\n{}", USAGE),
+            },
+            "--stub-ly" => stub_ly = true,
+            "--disasm" => match opts.next() {
+                Some(path) => disasm_dir = Some(path.clone()),
+                None => return println!("Missing output directory\n{}", USAGE),
+            },
+            "--cdl" => match opts.next() {
+                Some(path) => cdl_path = Some(path.clone()),
+                None => return println!("Missing log path\n{}", USAGE),
+            },
+            "--gdb" => match opts.next().map(|port| port.parse::<u16>()) {
+                Some(Ok(port)) => gdb_port = Some(port),
+                _ => return println!("Invalid port\n{}", USAGE),
+            },
+            "--batch" => {
+                // The script is optional, so the next argument may be another option.
+                let script = opts.next_if(|arg| !arg.starts_with("--"));
+                batch = Some(script.cloned());
+            }
+            "--json" => json = true,
+            "--max-cycles" => match opts.next().map(|n| n.parse::<u64>()) {
+                Some(Ok(n)) => max_cycles = Some(n),
+                _ => return println!("Invalid number of cycles\n{}", USAGE),
+            },
+            _ => return println!("Unknown option: {}\n{}", opt, USAGE),
+        }
+    }
 
-        terminal::enable_raw_mode().unwrap();
-        let mut stdout = io::stdout();
-        execute!(stdout, EnterAlternateScreen).unwrap();
-        let backend = CrosstermBackend::new(stdout);
-        let mut terminal = Terminal::new(backend).unwrap();
+    let rom = fs::read(rom_path)
+        .map_err(|_| log::error!("ROM file could not be opened"))
+        .unwrap();
 
-        let mut debugger = GameboyDebugger::new(&mut gb);
-        run_debugger(&mut terminal, &mut debugger).unwrap();
+    // RGBDS names the symbol file after the ROM.
+    let symbols = fs::read_to_string(Path::new(rom_path).with_extension("sym"))
+        .map(|src| SymbolTable::parse(&src))
+        .unwrap_or_default();
 
-        terminal::disable_raw_mode().unwrap();
-        execute!(terminal.backend_mut(), LeaveAlternateScreen).unwrap();
-    } else {
-        log::error!("Please provide a path to a valid Game Boy ROM.");
+    if let Some(dir) = disasm_dir {
+        let cdl = match cdl_path.map(fs::read).transpose() {
+            Ok(cdl) => cdl.map(CodeDataLog::from_bytes),
+            Err(e) => return println!("Failed to read the code/data log: {}", e),
+        };
+        write_disasm(&rom, &symbols, cdl.as_ref(), Path::new(&dir));
+        return;
+    }
+
+    let screen = SharedFrame::default();
+    let buttons = Rc::new(Cell::new(ButtonState::new()));
+    let mut gb = build_gameboy(rom, screen.clone(), buttons.clone());
+    if stub_ly {
+        gb.stub_ly(Some(0x90));
+    }
+
+    if let Some(log_path) = diff_log {
+        run_diff(&mut gb, Path::new(&log_path), context);
+        return;
+    }
+
+    if let Some(port) = gdb_port {
+        serve_gdb(&mut gb, port);
+        return;
+    }
+
+    let mut debugger = GameboyDebugger::new(&mut gb, symbols, screen, Keys::new(buttons));
+    if let Some(script) = batch {
+        // Scripts don't depend on the init file, but can source it.
+        debugger.set_max_cycles(max_cycles);
+        match run_batch(&mut debugger, script.as_deref().map(Path::new), json) {
+            Ok(true) => return,
+            Ok(false) => process::exit(1),
+            Err(e) => {
+                println!("Failed to read commands: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+    // Restores the breakpoints, watchpoints and other settings saved for the ROM.
+    debugger.load_init(&Path::new(rom_path).with_extension("gabbro-db"));
+
+    terminal::enable_raw_mode().unwrap();
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen).unwrap();
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend).unwrap();
+    run_debugger(&mut terminal, &mut debugger).unwrap();
+
+    terminal::disable_raw_mode().unwrap();
+    execute!(terminal.backend_mut(), LeaveAlternateScreen).unwrap();
+}
+
+/// Builds a Game Boy that draws to `screen`, and reads its buttons from `buttons`.
+pub fn build_gameboy(
+    rom: Vec<u8>,
+    screen: SharedFrame,
+    buttons: Rc<Cell<ButtonState>>,
+) -> DebugGameboy {
+    Gameboy::builder(rom)
+        .lcd(FrameLcd::new(screen))
+        .joypad(CellJoypad::new(buttons))
+        .build()
+}
+
+/// Runs the ROM headlessly against the reference trace log at `log_path`,
+/// and prints where it first diverges.
+fn run_diff(gameboy: &mut DebugGameboy, log_path: &Path, context: usize) {
+    let result = diff::open_log(log_path).and_then(|log| diff::run(gameboy, log, context));
+    match result {
+        Ok(Some(divergence)) => print!("{}", divergence),
+        Ok(None) => println!("No divergence found"),
+        Err(e) => println!("Failed to read {}: {}", log_path.display(), e),
     }
 }
 
+/// Waits for a GDB client on `port` of the local host, and serves it until it detaches.
+fn serve_gdb(gameboy: &mut DebugGameboy, port: u16) {
+    let result = TcpListener::bind(("127.0.0.1", port)).and_then(|listener| {
+        println!("Waiting for a GDB client on 127.0.0.1:{}", port);
+        GdbServer::accept(gameboy, &listener)?.serve()
+    });
+    match result {
+        Ok(()) => println!("GDB client detached"),
+        Err(e) => println!("GDB server failed: {}", e),
+    }
+}
+
+/// Serves a DAP client over stdin and stdout, until it disconnects.
+fn serve_dap_stdio() {
+    if let Err(e) = DapServer::new(io::stdin(), io::stdout()).serve() {
+        log::error!("DAP server failed: {}", e);
+    }
+}
+
+/// Waits for a DAP client on `port` of the local host, and serves it until it disconnects.
+fn serve_dap(port: u16) {
+    let result = TcpListener::bind(("127.0.0.1", port)).and_then(|listener| {
+        println!("Waiting for a DAP client on 127.0.0.1:{}", port);
+        let (stream, _) = listener.accept()?;
+        DapServer::new(stream.try_clone()?, stream).serve()
+    });
+    match result {
+        Ok(()) => println!("DAP client disconnected"),
+        Err(e) => println!("DAP server failed: {}", e),
+    }
+}
+
+/// Disassembles the whole ROM, and writes the RGBDS source files to `dir`.
+fn write_disasm(rom: &[u8], symbols: &SymbolTable, cdl: Option<&CodeDataLog>, dir: &Path) {
+    let files = Disassembly::new(rom, symbols, cdl).files();
+    let result = fs::create_dir_all(dir).and_then(|_| {
+        files
+            .iter()
+            .try_for_each(|(name, src)| fs::write(dir.join(name), src))
+    });
+    match result {
+        Ok(()) => println!("Wrote {} files to {}", files.len(), dir.display()),
+        Err(e) => println!("Failed to write to {}: {}", dir.display(), e),
+    }
+}
+
+/// Runs the commands in `script`, or else from stdin, one per line, and prints their results
+/// as plain text or as JSON lines. Runs of the program complete before the next command,
+/// or when they reach the cycle limit. Returns whether all commands succeeded.
+fn run_batch(
+    debugger: &mut GameboyDebugger,
+    script: Option<&Path>,
+    json: bool,
+) -> io::Result<bool> {
+    let input: Box<dyn BufRead> = match script {
+        Some(path) => Box::new(BufReader::new(File::open(path)?)),
+        None => Box::new(io::stdin().lock()),
+    };
+    let mut succeeded = true;
+    for line in input.lines() {
+        let line = line?;
+        let command = line.trim();
+        if command.is_empty() || command.starts_with('#') {
+            continue;
+        }
+        debugger.set_input(command);
+        if debugger.run_command() {
+            break;
+        }
+        debugger.run_to_stop();
+        succeeded &= !debugger.failed();
+        if json {
+            let result = serde_json::json!({
+                "command": command,
+                "ok": !debugger.failed(),
+                "stop": debugger.stop().map(Stop::name),
+                "output": debugger.output(),
+                "pc": debugger.registers().pc(),
+            });
+            println!("{}", result);
+        } else {
+            println!("> {}\n{}", command, debugger.output());
+        }
+    }
+    Ok(succeeded)
+}
+
 fn run_debugger<B: Backend>(
     terminal: &mut Terminal<B>,
     debugger: &mut GameboyDebugger,
@@ -44,19 +285,60 @@ fn run_debugger<B: Backend>(
     loop {
         terminal.draw(|f| ui::draw(f, debugger))?;
 
-        if crossterm::event::poll(Duration::from_millis(500))? {
+        // Only wait for input while stopped, so that a running program keeps running.
+        // While playing, the program is paced to the hardware, so there is time to wait briefly.
+        let timeout = if debugger.is_playing() {
+            Duration::from_millis(5)
+        } else if debugger.is_running() {
+            Duration::ZERO
+        } else {
+            Duration::from_millis(500)
+        };
+        if crossterm::event::poll(timeout)? {
             if let Event::Key(key) = event::read()? {
+                let ctrl_c =
+                    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
                 match key.code {
-                    KeyCode::Char(c) => debugger.push_input(c),
-                    KeyCode::Backspace => debugger.pop_input(),
-                    KeyCode::Enter => {
-                        if debugger.run_command() {
-                            return Ok(());
+                    KeyCode::Esc if debugger.is_running() => debugger.interrupt(),
+                    _ if ctrl_c => debugger.interrupt(),
+                    code if debugger.is_playing() => {
+                        if let Some(button) = button(code) {
+                            debugger.press(button);
                         }
                     }
+                    _ if debugger.is_running() => (),
+                    KeyCode::Char(c) => debugger.input_mut().insert(c),
+                    KeyCode::Backspace => debugger.input_mut().backspace(),
+                    KeyCode::Delete => debugger.input_mut().delete(),
+                    KeyCode::Left => debugger.input_mut().move_left(),
+                    KeyCode::Right => debugger.input_mut().move_right(),
+                    KeyCode::Home => debugger.input_mut().move_home(),
+                    KeyCode::End => debugger.input_mut().move_end(),
+                    KeyCode::Up => debugger.input_mut().history_prev(),
+                    KeyCode::Down => debugger.input_mut().history_next(),
+                    KeyCode::Tab => debugger.complete(),
+                    KeyCode::PageUp => debugger.scroll_memory(-0x80),
+                    KeyCode::PageDown => debugger.scroll_memory(0x80),
+                    KeyCode::Enter if debugger.run_command() => return Ok(()),
                     _ => (),
                 }
             }
         }
+        debugger.run_slice();
+    }
+}
+
+/// Returns the button played with the key `code` in play mode.
+fn button(code: KeyCode) -> Option<Button> {
+    match code {
+        KeyCode::Right => Some(Button::Right),
+        KeyCode::Left => Some(Button::Left),
+        KeyCode::Up => Some(Button::Up),
+        KeyCode::Down => Some(Button::Down),
+        KeyCode::Char('x') => Some(Button::A),
+        KeyCode::Char('z') => Some(Button::B),
+        KeyCode::Backspace => Some(Button::Select),
+        KeyCode::Enter => Some(Button::Start),
+        _ => None,
     }
 }
@@ -0,0 +1,125 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use gabbro::{ButtonState, Joypad, Lcd, LcdColor, LCD_HEIGHT, LCD_WIDTH};
+
+/// The last complete frame drawn by the PPU, shared between the LCD and the debugger.
+pub type SharedFrame = Rc<RefCell<Vec<LcdColor>>>;
+
+/// An LCD that keeps the last complete frame, so that it can be shown in the screen panel.
+#[derive(Clone)]
+pub struct FrameLcd {
+    frame: SharedFrame,
+    pixels: Vec<LcdColor>,
+}
+
+impl FrameLcd {
+    pub fn new(frame: SharedFrame) -> Self {
+        frame
+            .borrow_mut()
+            .resize(LCD_WIDTH * LCD_HEIGHT, LcdColor::White);
+        Self {
+            frame,
+            pixels: Vec::with_capacity(LCD_WIDTH * LCD_HEIGHT),
+        }
+    }
+}
+
+impl Lcd for FrameLcd {
+    fn push_pixel(&mut self, color: LcdColor) {
+        if self.pixels.len() < LCD_WIDTH * LCD_HEIGHT {
+            self.pixels.push(color);
+        }
+    }
+
+    fn frame_ready(&mut self) {
+        let mut frame = self.frame.borrow_mut();
+        frame[..self.pixels.len()].copy_from_slice(&self.pixels);
+        self.pixels.clear();
+    }
+}
+
+/// A joypad that reads the buttons held down in play mode.
+#[derive(Clone)]
+pub struct CellJoypad {
+    state: Rc<Cell<ButtonState>>,
+}
+
+impl CellJoypad {
+    pub fn new(state: Rc<Cell<ButtonState>>) -> Self {
+        Self { state }
+    }
+}
+
+impl Joypad for CellJoypad {
+    fn get_button_state(&mut self) -> ButtonState {
+        self.state.get()
+    }
+}
+
+/// A button of the Game Boy.
+#[derive(Clone, Copy)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+/// The buttons held down from the keyboard in play mode.
+/// Terminals only report key presses, so a button is released once its key stops repeating.
+pub struct Keys {
+    state: Rc<Cell<ButtonState>>,
+    /// When each button is released, by [`Button`].
+    released_at: [Option<Instant>; 8],
+}
+
+impl Keys {
+    /// How long a button stays held after a key press. Longer than the usual delay
+    /// before a held key starts repeating.
+    const HOLD: Duration = Duration::from_millis(600);
+
+    pub fn new(state: Rc<Cell<ButtonState>>) -> Self {
+        Self {
+            state,
+            released_at: [None; 8],
+        }
+    }
+
+    /// Holds `button` down, until its key stops repeating.
+    pub fn press(&mut self, button: Button) {
+        self.released_at[button as usize] = Some(Instant::now() + Self::HOLD);
+        self.update();
+    }
+
+    /// Releases all buttons.
+    pub fn release_all(&mut self) {
+        self.released_at = [None; 8];
+        self.update();
+    }
+
+    /// Releases the buttons whose keys stopped repeating, and passes the held buttons to the joypad.
+    pub fn update(&mut self) {
+        let now = Instant::now();
+        let held = self
+            .released_at
+            .map(|released_at| released_at.is_some_and(|released_at| released_at > now));
+        self.state.set(ButtonState {
+            right: held[Button::Right as usize],
+            left: held[Button::Left as usize],
+            up: held[Button::Up as usize],
+            down: held[Button::Down as usize],
+            a: held[Button::A as usize],
+            b: held[Button::B as usize],
+            select: held[Button::Select as usize],
+            start: held[Button::Start as usize],
+        });
+    }
+}
@@ -0,0 +1,181 @@
+use gabbro::{decode, SymbolTable};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The extensions of RGBDS assembly source files.
+const EXTENSIONS: [&str; 4] = ["asm", "inc", "s", "z80"];
+
+/// The mnemonics of all SM83 instructions, as written in RGBDS assembly.
+const MNEMONICS: [&str; 47] = [
+    "adc", "add", "and", "bit", "call", "ccf", "cp", "cpl", "daa", "dec", "di", "ei", "halt",
+    "inc", "jp", "jr", "ld", "ldd", "ldh", "ldi", "nop", "or", "pop", "push", "res", "ret", "reti",
+    "rl", "rla", "rlc", "rlca", "rr", "rra", "rrc", "rrca", "rst", "sbc", "scf", "set", "sla",
+    "sra", "srl", "stop", "sub", "swap", "xor", "ldio",
+];
+
+/// Directives that do not emit any bytes, and so do not move instructions after them.
+const NO_BYTES: [&str; 11] = [
+    "def",
+    "redef",
+    "export",
+    "global",
+    "assert",
+    "static_assert",
+    "purge",
+    "print",
+    "println",
+    "warn",
+    "opt",
+];
+
+/// Maps lines of RGBDS assembly sources to the ROM locations of their instructions.
+/// RGBDS does not output line information, so instructions are found by starting at every
+/// label of the symbol file, and following the instructions in the ROM for as long as they
+/// match the instructions in the source.
+#[derive(Default)]
+pub struct SourceMap {
+    /// The ROM bank and address of every line with an instruction, by file and line.
+    lines: HashMap<PathBuf, BTreeMap<usize, (usize, u16)>>,
+    /// The file and line of every instruction, by ROM bank and address.
+    addrs: HashMap<(usize, u16), (PathBuf, usize)>,
+}
+
+impl SourceMap {
+    /// Maps all source files in `dir` and its subdirectories.
+    pub fn load_dir(&mut self, dir: &Path, rom: &[u8], symbols: &SymbolTable) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let hidden = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if hidden {
+                continue;
+            }
+            if path.is_dir() {
+                self.load_dir(&path, rom, symbols)?;
+            } else if path
+                .extension()
+                .is_some_and(|ext| EXTENSIONS.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+            {
+                self.load_file(&path, rom, symbols)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Maps the lines of the source file at `path`, unless it was mapped before.
+    pub fn load_file(&mut self, path: &Path, rom: &[u8], symbols: &SymbolTable) -> io::Result<()> {
+        let path = fs::canonicalize(path)?;
+        if self.lines.contains_key(&path) {
+            return Ok(());
+        }
+        let src = fs::read_to_string(&path)?;
+        let mut lines = BTreeMap::new();
+        let mut scope = String::new();
+        let mut cursor = None;
+        for (idx, line) in src.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or_default();
+            let (label, code) = split_label(line);
+            if let Some(label) = label {
+                let name = match label.strip_prefix('.') {
+                    Some(_) => format!("{}{}", scope, label),
+                    None => {
+                        scope = label.split('.').next().unwrap_or_default().to_string();
+                        label.to_string()
+                    }
+                };
+                cursor = symbols
+                    .resolve(&name)
+                    .map(|(bank, addr)| (bank as usize, addr));
+            }
+            let mut words = code.split_whitespace();
+            let Some(first) = words.next().map(str::to_ascii_lowercase) else {
+                continue;
+            };
+            let second = words.next().map(str::to_ascii_lowercase);
+            if NO_BYTES.contains(&first.as_str())
+                || matches!(second.as_deref(), Some("equ" | "equs" | "=" | "set"))
+            {
+                continue;
+            }
+            if !MNEMONICS.contains(&first.as_str()) {
+                // Anything else, like data or a macro, emits bytes the ROM can't tell the size of.
+                cursor = None;
+                continue;
+            }
+            cursor = cursor.and_then(|(bank, addr)| {
+                let offset = rom_offset(bank, addr)?;
+                let bytes = [0, 1, 2].map(|i| rom.get(offset + i).copied().unwrap_or(0));
+                let (len, mnemonic) = decode(bytes);
+                let decoded = mnemonic.to_string();
+                let name = decoded.split_whitespace().next().unwrap_or_default();
+                if !name.eq_ignore_ascii_case(normalize(&first)) {
+                    return None;
+                }
+                lines.insert(idx + 1, (bank, addr));
+                self.addrs.insert((bank, addr), (path.clone(), idx + 1));
+                addr.checked_add(len as u16).map(|next| (bank, next))
+            });
+        }
+        self.lines.insert(path, lines);
+        Ok(())
+    }
+
+    /// Returns the first line at or after `line` in the file at `path` that has an instruction,
+    /// together with the ROM bank and address of the instruction.
+    pub fn resolve_line(&self, path: &Path, line: usize) -> Option<(usize, (usize, u16))> {
+        let path = fs::canonicalize(path).ok()?;
+        self.lines
+            .get(&path)?
+            .range(line..)
+            .next()
+            .map(|(line, location)| (*line, *location))
+    }
+
+    /// Returns the file and line of the instruction at `addr` in `bank`.
+    pub fn line_of(&self, bank: usize, addr: u16) -> Option<(&Path, usize)> {
+        self.addrs
+            .get(&(bank, addr))
+            .map(|(path, line)| (path.as_path(), *line))
+    }
+}
+
+/// Splits the label a line starts with, if any, from the rest of the line.
+/// Local labels may leave out the colon.
+fn split_label(line: &str) -> (Option<&str>, &str) {
+    if line.starts_with(char::is_whitespace) {
+        return (None, line);
+    }
+    let end = line
+        .find(|c: char| !(c.is_ascii_alphanumeric() || "_.#@$".contains(c)))
+        .unwrap_or(line.len());
+    let rest = &line[end..];
+    let local = end > 1
+        && line.starts_with('.')
+        && (rest.is_empty() || rest.starts_with(char::is_whitespace));
+    match rest.strip_prefix(':') {
+        Some(rest) if end > 0 => (Some(&line[..end]), rest.trim_start_matches(':')),
+        None if local => (Some(&line[..end]), rest),
+        _ => (None, line),
+    }
+}
+
+/// Returns the name the disassembler uses for `mnemonic`.
+fn normalize(mnemonic: &str) -> &str {
+    match mnemonic {
+        "ldh" | "ldi" | "ldd" | "ldio" => "ld",
+        _ => mnemonic,
+    }
+}
+
+/// Returns the offset in the ROM of `addr` in `bank`, if it is in ROM.
+fn rom_offset(bank: usize, addr: u16) -> Option<usize> {
+    match addr {
+        0x0000..=0x3fff => Some(addr as usize),
+        0x4000..=0x7fff => Some(bank.max(1) * 0x4000 + (addr as usize - 0x4000)),
+        _ => None,
+    }
+}
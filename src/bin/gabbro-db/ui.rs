@@ -1,7 +1,9 @@
-use crate::debugger::GameboyDebugger;
+use crate::debugger::{describe, GameboyDebugger, HardwareView};
+use gabbro::{LcdColor, LCD_HEIGHT, LCD_WIDTH};
 use tui::{
     backend::Backend,
     layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Style},
     text::{Span, Spans},
     widgets::{Block, Borders, Paragraph},
     Frame,
@@ -12,6 +14,13 @@ pub fn draw<B: Backend>(f: &mut Frame<B>, debugger: &mut GameboyDebugger) {
         .constraints([Constraint::Min(0), Constraint::Length(4)].as_ref())
         .split(f.size());
 
+    draw_input(f, debugger, term_chunks[1]);
+    // While playing, the screen takes the place of all other panels.
+    if debugger.is_playing() {
+        draw_screen(f, debugger, term_chunks[0]);
+        return;
+    }
+
     let info_chunks = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
@@ -25,55 +34,125 @@ pub fn draw<B: Backend>(f: &mut Frame<B>, debugger: &mut GameboyDebugger) {
         .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
         .split(info_chunks[1]);
 
+    let top_right_chunks = Layout::default()
+        .constraints([Constraint::Length(10), Constraint::Min(0)])
+        .split(right_chunks[0]);
+
+    let state_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(top_right_chunks[0]);
+
+    let bottom_left_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(left_chunks[1]);
+
+    let call_chunks = Layout::default()
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(bottom_left_chunks[1]);
+
     let bottom_right_chunks = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
         .split(right_chunks[1]);
 
-    draw_input(f, debugger, term_chunks[1]);
     draw_disassembly(f, debugger, left_chunks[0]);
-    draw_stack(f, debugger, left_chunks[1]);
-    draw_registers(f, debugger, right_chunks[0]);
+    draw_screen(f, debugger, bottom_left_chunks[0]);
+    draw_stack(f, debugger, call_chunks[0]);
+    draw_backtrace(f, debugger, call_chunks[1]);
+    draw_registers(f, debugger, state_chunks[0]);
+    draw_hardware(f, debugger, state_chunks[1]);
+    draw_memory(f, debugger, top_right_chunks[1]);
     draw_breakpoints(f, debugger, bottom_right_chunks[0]);
     draw_watchpoints(f, debugger, bottom_right_chunks[1]);
 }
 
 pub fn draw_input<B: Backend>(f: &mut Frame<B>, debugger: &mut GameboyDebugger, area: Rect) {
+    let status = debugger.running_status();
     let text = vec![
-        Spans::from(debugger.output()),
-        Spans::from(vec![Span::from("> "), Span::from(debugger.input())]),
+        Spans::from(status.as_deref().unwrap_or(debugger.output())),
+        Spans::from(vec![Span::from("> "), Span::from(debugger.input().text())]),
     ];
     let block = Block::default()
         .borders(Borders::ALL)
         .title(Span::from("Input"));
     let paragraph = Paragraph::new(text).block(block);
     f.render_widget(paragraph, area);
+    if status.is_none() {
+        // After the border and the prompt, on the second line.
+        let x = area.x + 3 + debugger.input().cursor() as u16;
+        f.set_cursor(x.min(area.right().saturating_sub(2)), area.y + 2);
+    }
 }
 
 pub fn draw_disassembly<B: Backend>(f: &mut Frame<B>, debugger: &mut GameboyDebugger, area: Rect) {
-    let text = debugger
-        .disasm(area.height as usize - 2)
-        .iter()
-        .map(|(pc, addr, bytes, mnemonic)| {
-            Spans::from(vec![
-                Span::from(if *pc { "PC > " } else { "     " }),
-                Span::from(format!("{:#06x} ", addr)),
-                Span::from(format!(
-                    "{:08} ",
-                    bytes
-                        .iter()
-                        .map(|byte| format!("{:02x}", byte))
-                        .collect::<Vec<String>>()
-                        .join(" ")
-                )),
-                Span::from(mnemonic.to_string()),
-            ])
+    let mut text = Vec::new();
+    for (pc, addr, bytes, mnemonic) in debugger.disasm(area.height as usize - 2) {
+        if let Some(label) = debugger.label(addr) {
+            text.push(Spans::from(Span::from(format!("{}:", label))));
+        }
+        text.push(Spans::from(vec![
+            Span::from(if pc { "PC > " } else { "     " }),
+            Span::from(format!("{:#06x} ", addr)),
+            Span::from(format!(
+                "{:08} ",
+                bytes
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            )),
+            Span::from(mnemonic),
+        ]));
+    }
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(Span::from("Disassembly"));
+    let paragraph = Paragraph::new(text).block(block);
+    f.render_widget(paragraph, area);
+}
+
+/// Draws the last frame of the game, scaled down to fit `area`.
+/// Every character shows two pixels on top of each other, using a half block.
+pub fn draw_screen<B: Backend>(f: &mut Frame<B>, debugger: &mut GameboyDebugger, area: Rect) {
+    let (width, height) = (
+        area.width.saturating_sub(2).max(1) as usize,
+        area.height.saturating_sub(2).max(1) as usize,
+    );
+    let scale = LCD_WIDTH
+        .div_ceil(width)
+        .max(LCD_HEIGHT.div_ceil(height * 2))
+        .max(1);
+    let screen = debugger.screen();
+    let pixel = |x: usize, y: usize| match screen[y * scale * LCD_WIDTH + x * scale] {
+        LcdColor::White => Color::Rgb(0xff, 0xff, 0xff),
+        LcdColor::LightGray => Color::Rgb(0xaa, 0xaa, 0xaa),
+        LcdColor::DarkGray => Color::Rgb(0x55, 0x55, 0x55),
+        LcdColor::Black => Color::Rgb(0x00, 0x00, 0x00),
+    };
+    let (cols, rows) = (LCD_WIDTH / scale, LCD_HEIGHT / scale);
+    let text = (0..rows)
+        .step_by(2)
+        .map(|y| {
+            let spans = (0..cols)
+                .map(|x| {
+                    let bottom = if y + 1 < rows {
+                        pixel(x, y + 1)
+                    } else {
+                        Color::Reset
+                    };
+                    Span::styled("▀", Style::default().fg(pixel(x, y)).bg(bottom))
+                })
+                .collect::<Vec<Span>>();
+            Spans::from(spans)
         })
         .collect::<Vec<Spans>>();
 
     let block = Block::default()
         .borders(Borders::ALL)
-        .title(Span::from("Disassembly"));
+        .title(Span::from("Screen"));
     let paragraph = Paragraph::new(text).block(block);
     f.render_widget(paragraph, area);
 }
@@ -99,6 +178,21 @@ pub fn draw_stack<B: Backend>(f: &mut Frame<B>, debugger: &mut GameboyDebugger,
     f.render_widget(paragraph, area);
 }
 
+pub fn draw_backtrace<B: Backend>(f: &mut Frame<B>, debugger: &mut GameboyDebugger, area: Rect) {
+    let text = debugger
+        .backtrace()
+        .into_iter()
+        .enumerate()
+        .map(|(i, frame)| Spans::from(Span::from(format!("#{} {}", i, frame))))
+        .collect::<Vec<Spans>>();
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(Span::from("Backtrace"));
+    let paragraph = Paragraph::new(text).block(block);
+    f.render_widget(paragraph, area);
+}
+
 pub fn draw_registers<B: Backend>(f: &mut Frame<B>, debugger: &mut GameboyDebugger, area: Rect) {
     let registers = debugger.registers();
     let text = vec![
@@ -134,6 +228,11 @@ pub fn draw_registers<B: Backend>(f: &mut Frame<B>, debugger: &mut GameboyDebugg
             registers.pc(),
             registers.sp()
         ))),
+        Spans::from(Span::from(format!(
+            "IME: {}         Halted: {}",
+            debugger.ime() as u8,
+            debugger.halted() as u8
+        ))),
     ];
 
     let block = Block::default()
@@ -143,12 +242,174 @@ pub fn draw_registers<B: Backend>(f: &mut Frame<B>, debugger: &mut GameboyDebugg
     f.render_widget(paragraph, area);
 }
 
+pub fn draw_hardware<B: Backend>(f: &mut Frame<B>, debugger: &mut GameboyDebugger, area: Rect) {
+    let flag = |set: bool| if set { '1' } else { '0' };
+    let (title, lines) = match debugger.hardware_view() {
+        HardwareView::Ppu => {
+            let ppu = debugger.ppu_state();
+            let mode = ["HBLANK", "VBLANK", "OAM", "DRAW"][ppu.mode as usize];
+            let lines = vec![
+                format!("LCDC: {:#04x}  STAT: {:#04x}", ppu.lcdc, ppu.stat),
+                format!("Mode: {} ({})  Dot: {}", ppu.mode, mode, ppu.line_dots),
+                format!("LY: {:3}  LYC: {:3}", ppu.ly, ppu.lyc),
+                format!("SCX: {:3}  SCY: {:3}", ppu.scx, ppu.scy),
+                format!("WX: {:3}   WY: {:3}", ppu.wx, ppu.wy),
+                format!(
+                    "BGP: {:#04x}  OBP0: {:#04x}  OBP1: {:#04x}",
+                    ppu.bgp, ppu.obp0, ppu.obp1
+                ),
+                format!(
+                    "LCD: {}  BG: {}  WIN: {}  OBJ: {}{}",
+                    flag(ppu.lcd_enabled),
+                    flag(ppu.bg_enabled),
+                    flag(ppu.window_enabled),
+                    flag(ppu.sprites_enabled),
+                    if ppu.tall_sprites { " 8x16" } else { " 8x8" }
+                ),
+                format!(
+                    "BG: {:#06x}  WIN: {:#06x}  Tiles: {:#06x}",
+                    ppu.bg_map, ppu.window_map, ppu.tile_data
+                ),
+            ];
+            ("PPU", lines)
+        }
+        HardwareView::Timer => {
+            let timer = debugger.timer_state();
+            let lines = vec![
+                format!("DIV: {:#04x}  ({:#06x})", timer.div >> 8, timer.div),
+                format!("TIMA: {:#04x}  TMA: {:#04x}", timer.tima, timer.tma),
+                format!("TAC: {:#04x}", timer.tac),
+                format!(
+                    "Enabled: {}  Frequency: {} Hz",
+                    flag(timer.enabled),
+                    timer.frequency
+                ),
+            ];
+            ("Timer", lines)
+        }
+        HardwareView::Interrupts => {
+            let ints = debugger.interrupt_state();
+            let mut lines = vec![format!(
+                "IME: {}  IE: {:#04x}  IF: {:#04x}",
+                flag(ints.ime),
+                ints.enable,
+                ints.flags
+            )];
+            let names = ["VBLANK", "LCDSTAT", "TIMER", "SERIAL", "JOYPAD"];
+            for (bit, name) in names.iter().enumerate() {
+                lines.push(format!(
+                    "{:8} IE: {}  IF: {}",
+                    name,
+                    flag((ints.enable >> bit) & 1 != 0),
+                    flag((ints.flags >> bit) & 1 != 0)
+                ));
+            }
+            ("Interrupts", lines)
+        }
+        HardwareView::Apu => {
+            let apu = debugger.apu_state();
+            let mut lines = vec![
+                format!(
+                    "NR50: {:#04x}  NR51: {:#04x}  NR52: {:#04x}",
+                    apu.nr50, apu.nr51, apu.nr52
+                ),
+                format!(
+                    "Enabled: {}  Volume: L {} R {}",
+                    flag(apu.enabled),
+                    apu.left_volume,
+                    apu.right_volume
+                ),
+            ];
+            for (i, ch) in apu.channels.iter().enumerate() {
+                lines.push(format!(
+                    "CH{} {} {}{} vol {:2} {:8.1} Hz{}",
+                    i + 1,
+                    if ch.active { "on " } else { "off" },
+                    if ch.left { 'L' } else { '-' },
+                    if ch.right { 'R' } else { '-' },
+                    ch.volume,
+                    ch.frequency,
+                    if !ch.dac_enabled {
+                        " DAC off"
+                    } else if ch.length_enabled {
+                        " len"
+                    } else {
+                        ""
+                    }
+                ));
+            }
+            ("APU", lines)
+        }
+    };
+    let text = lines
+        .into_iter()
+        .map(|line| Spans::from(Span::from(line)))
+        .collect::<Vec<Spans>>();
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(Span::from(title));
+    let paragraph = Paragraph::new(text).block(block);
+    f.render_widget(paragraph, area);
+}
+
+pub fn draw_memory<B: Backend>(f: &mut Frame<B>, debugger: &mut GameboyDebugger, area: Rect) {
+    // Each byte takes up 4 columns, 3 in hex and 1 in ASCII, next to 7 for the address
+    // and the separator, and 2 for the borders.
+    let row_len = if area.width >= 2 + 7 + 16 * 4 { 16 } else { 8 };
+    let rows = area.height.saturating_sub(2) as usize;
+    let start = debugger.memory_addr();
+    let memory = debugger.memory(start, rows * row_len);
+    let text = memory
+        .chunks(row_len)
+        .enumerate()
+        .map(|(row, bytes)| {
+            let addr = start.wrapping_add((row * row_len) as u16);
+            let mut spans = vec![Span::from(format!("{:04x}  ", addr))];
+            for (val, changed) in bytes {
+                let style = if *changed {
+                    Style::default().fg(Color::Yellow)
+                } else {
+                    Style::default()
+                };
+                spans.push(Span::styled(format!("{:02x} ", val), style));
+            }
+            let ascii = bytes
+                .iter()
+                .map(|(val, _)| match val {
+                    0x20..=0x7e => *val as char,
+                    _ => '.',
+                })
+                .collect::<String>();
+            spans.push(Span::from(format!(" {}", ascii)));
+            Spans::from(spans)
+        })
+        .collect::<Vec<Spans>>();
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(Span::from("Memory"));
+    let paragraph = Paragraph::new(text).block(block);
+    f.render_widget(paragraph, area);
+}
+
 pub fn draw_breakpoints<B: Backend>(f: &mut Frame<B>, debugger: &mut GameboyDebugger, area: Rect) {
     let text = debugger
         .breakpoints()
         .iter()
         .enumerate()
-        .map(|(i, bp)| Spans::from(Span::from(format!("{:02} {:#06x}", i, bp))))
+        .map(|(i, (breakpoint, trigger))| {
+            let condition = trigger
+                .condition()
+                .map_or(String::new(), |condition| format!(" if {}", condition));
+            Spans::from(Span::from(format!(
+                "{:02} {}{} ({} hits)",
+                i,
+                breakpoint,
+                condition,
+                trigger.hits()
+            )))
+        })
         .collect::<Vec<Spans>>();
 
     let block = Block::default()
@@ -164,7 +425,12 @@ pub fn draw_watchpoints<B: Backend>(f: &mut Frame<B>, debugger: &mut GameboyDebu
         .iter()
         .enumerate()
         .map(|(i, (wp, val))| {
-            Spans::from(Span::from(format!("{:02} {:#06x}: {:#04x}", i, wp, val)))
+            Spans::from(Span::from(format!(
+                "{:02} {}: {:#04x}",
+                i,
+                describe(wp),
+                val
+            )))
         })
         .collect::<Vec<Spans>>();
 
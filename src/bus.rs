@@ -1,16 +1,40 @@
 use crate::{
     apu::Apu,
     cartridge::Cartridge,
-    cpu::interrupts::InterruptControl,
+    cpu::{interrupts::InterruptControl, MemoryBus},
     joypad::JoypadController,
     peripherals::{Cable, Joypad, Lcd, Speaker},
     ppu::Ppu,
     serial::SerialController,
     timer::Timer,
 };
+#[cfg(feature = "debug")]
+use crate::{
+    apu::ApuState,
+    cpu::{
+        cdl::CodeDataLog,
+        events::{Event, Events},
+        watchpoints::Watchpoints,
+        Access,
+    },
+    ppu::PpuState,
+    timer::TimerState,
+};
+#[cfg(feature = "debug")]
+use std::mem;
+
+/// The debugging tools of a [`Bus`], detached from it while taking or restoring a snapshot.
+#[cfg(feature = "debug")]
+pub struct BusTools {
+    ly_stub: Option<u8>,
+    cdl: Option<CodeDataLog>,
+    watchpoints: Watchpoints,
+    events: Events,
+}
 
 /// The bus which handles all reads and writes from/to memory.
 /// Also used to access all parts of the Game Boy besides the CPU.
+#[derive(Clone)]
 pub struct Bus<L, S, J, C>
 where
     L: Lcd,
@@ -27,6 +51,18 @@ where
     apu: Apu<S>,
     ppu: Ppu<L>,
     pub interrupts: InterruptControl,
+    /// When set, reads from `LY` return this value instead of the current scanline.
+    #[cfg(feature = "debug")]
+    pub ly_stub: Option<u8>,
+    /// When set, logs how the CPU accesses every byte of the ROM.
+    #[cfg(feature = "debug")]
+    pub cdl: Option<CodeDataLog>,
+    /// Checked on every read and write of the CPU.
+    #[cfg(feature = "debug")]
+    pub watchpoints: Watchpoints,
+    /// The hardware events caused by the CPU, for a debugger to stop on.
+    #[cfg(feature = "debug")]
+    pub events: Events,
 }
 
 impl<L, S, J, C> Bus<L, S, J, C>
@@ -53,6 +89,14 @@ where
             apu: Apu::new(speaker),
             ppu: Ppu::new(lcd),
             interrupts: InterruptControl::new(),
+            #[cfg(feature = "debug")]
+            ly_stub: None,
+            #[cfg(feature = "debug")]
+            cdl: None,
+            #[cfg(feature = "debug")]
+            watchpoints: Watchpoints::default(),
+            #[cfg(feature = "debug")]
+            events: Events::default(),
         }
     }
 
@@ -91,6 +135,9 @@ where
             0xff41 => self.ppu.stat.byte(),
             0xff42 => self.ppu.fetcher.scy,
             0xff43 => self.ppu.fetcher.scx,
+            #[cfg(feature = "debug")]
+            0xff44 => self.ly_stub.unwrap_or(self.ppu.fetcher.ly),
+            #[cfg(not(feature = "debug"))]
             0xff44 => self.ppu.fetcher.ly,
             0xff45 => self.ppu.lyc,
             0xff46 => 0xff,
@@ -163,6 +210,147 @@ where
         }
     }
 
+    /// Reads the value at `addr` without any effect on the hardware.
+    /// Unlike [`Bus::read`], reads from external RAM also work while it is disabled.
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0xa000..=0xbfff => {
+                let offset = self.cart.mbc.ram_offset(addr - 0xa000);
+                self.cart.mbc.ram().get(offset).copied().unwrap_or(0xff)
+            }
+            _ => self.read(addr),
+        }
+    }
+
+    /// Writes `val` to `addr` without writing to the MBC registers. Writes to ROM overwrite
+    /// the ROM itself, and writes to external RAM also work while it is disabled.
+    /// IO registers are stored without the side effects of [`Bus::write`]: `DIV` is set instead
+    /// of reset, writing `DMA` does not start a transfer, and the trigger bits of `NRx4` don't
+    /// restart the sound channels. Like on the hardware, the read-only bits of `P1` keep the
+    /// state of the buttons, and the unused bits of the sound registers are dropped.
+    pub fn poke(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x0000..=0x7fff => self.cart.mbc.patch_rom(addr, val),
+            0xa000..=0xbfff => {
+                let offset = self.cart.mbc.ram_offset(addr - 0xa000);
+                if let Some(byte) = self.cart.mbc.ram_mut().get_mut(offset) {
+                    *byte = val;
+                }
+            }
+            0xff04 => self.timer.div = (val as u16) << 8,
+            0xff10..=0xff3f => self.apu.poke(addr, val),
+            0xff46 => (),
+            _ => self.write(addr, val),
+        }
+    }
+
+    /// Reads `addr` in the ROM bank `bank`. Only the lower 14 bits of `addr` are used,
+    /// so that `0x4000 - 0x7fff` reads the same as `0x0000 - 0x3fff`.
+    /// Returns `0xff` beyond the end of the ROM.
+    pub fn peek_rom(&self, bank: usize, addr: u16) -> u8 {
+        bank.checked_mul(0x4000)
+            .and_then(|base| self.cart.mbc.rom().get(base + (addr as usize & 0x3fff)))
+            .copied()
+            .unwrap_or(0xff)
+    }
+
+    /// Reads `addr` in the external RAM bank `bank`. Only the lower 13 bits of `addr` are used,
+    /// so that `0xa000 - 0xbfff` can be read. Returns `0xff` beyond the end of the RAM.
+    pub fn peek_cart_ram(&self, bank: usize, addr: u16) -> u8 {
+        bank.checked_mul(0x2000)
+            .and_then(|base| self.cart.mbc.ram().get(base + (addr as usize & 0x1fff)))
+            .copied()
+            .unwrap_or(0xff)
+    }
+
+    /// Writes the value `val` to the memory mapped at `addr`.
+    /// Unlike [`Bus::write`], writes to ROM overwrite the ROM itself instead of the MBC registers.
+    #[cfg(feature = "debug")]
+    pub fn patch(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x0000..=0x7fff => self.cart.mbc.patch_rom(addr, val),
+            _ => self.write(addr, val),
+        }
+    }
+
+    /// Returns the ROM bank currently mapped to `0x4000 - 0x7fff`.
+    #[cfg(feature = "debug")]
+    pub fn rom_bank(&self) -> usize {
+        self.cart.mbc.rom_bank()
+    }
+
+    /// Starts a new Code/Data Log covering the whole ROM, discarding any previous one.
+    #[cfg(feature = "debug")]
+    pub fn start_cdl(&mut self) {
+        self.cdl = Some(CodeDataLog::new(self.cart.mbc.rom_len()));
+    }
+
+    /// Detaches the debugging tools, so that they are left out of snapshots.
+    #[cfg(feature = "debug")]
+    pub fn take_tools(&mut self) -> BusTools {
+        BusTools {
+            ly_stub: self.ly_stub,
+            cdl: self.cdl.take(),
+            watchpoints: mem::take(&mut self.watchpoints),
+            events: mem::take(&mut self.events),
+        }
+    }
+
+    /// Attaches the debugging tools detached with [`Bus::take_tools`],
+    /// so that they are kept as they are after restoring a snapshot.
+    #[cfg(feature = "debug")]
+    pub fn set_tools(&mut self, tools: BusTools) {
+        self.ly_stub = tools.ly_stub;
+        self.cdl = tools.cdl;
+        self.watchpoints = tools.watchpoints;
+        self.events = tools.events;
+    }
+
+    /// Returns a snapshot of the PPU registers.
+    #[cfg(feature = "debug")]
+    pub fn ppu_state(&self) -> PpuState {
+        self.ppu.state()
+    }
+
+    /// Returns a snapshot of the timer registers.
+    #[cfg(feature = "debug")]
+    pub fn timer_state(&self) -> TimerState {
+        self.timer.state()
+    }
+
+    /// Returns a snapshot of the APU registers and channels.
+    #[cfg(feature = "debug")]
+    pub fn apu_state(&self) -> ApuState {
+        self.apu.state()
+    }
+
+    /// Writes `val` to `addr` like [`Bus::write`], and records the hardware events it causes.
+    #[cfg(feature = "debug")]
+    fn write_recording_events(&mut self, addr: u16, val: u8) {
+        let bank = self.rom_bank();
+        let lcdc = self.ppu.fetcher.lcdc.byte();
+        self.write(addr, val);
+        match addr {
+            0x0000..=0x7fff if self.rom_bank() != bank => {
+                let bank = self.rom_bank();
+                self.events.record(Event::BankSwitch { bank });
+            }
+            0xff00..=0xff7f | 0xffff => {
+                self.events.record(Event::IoWrite { addr, val });
+                match addr {
+                    0xff40 if (lcdc ^ val) & 0x80 != 0 => self.events.record(Event::Lcd {
+                        on: val & 0x80 != 0,
+                    }),
+                    0xff46 => self.events.record(Event::DmaStart {
+                        source: (val as u16) << 8,
+                    }),
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+    }
+
     /// Emulates a machine cycle for all parts of the Game Boy that are stored in the [`Bus`].
     /// This does not include the CPU.
     pub fn io_step(&mut self) {
@@ -185,3 +373,70 @@ where
         }
     }
 }
+
+impl<L, S, J, C> MemoryBus for Bus<L, S, J, C>
+where
+    L: Lcd,
+    S: Speaker,
+    J: Joypad,
+    C: Cable,
+{
+    fn read(&mut self, addr: u16) -> u8 {
+        Bus::read(self, addr)
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        #[cfg(feature = "debug")]
+        if !self.watchpoints.is_empty() {
+            let old = Bus::read(self, addr);
+            self.watchpoints.check_write(addr, old, val);
+        }
+        #[cfg(feature = "debug")]
+        if self.events.enabled() {
+            return self.write_recording_events(addr, val);
+        }
+        Bus::write(self, addr, val)
+    }
+
+    fn tick(&mut self) {
+        self.io_step();
+    }
+
+    fn interrupt_pending(&self) -> bool {
+        self.interrupts.pending()
+    }
+
+    fn take_interrupt(&mut self) -> Option<u16> {
+        self.interrupts.step()
+    }
+
+    #[cfg(feature = "debug")]
+    fn rom_bank(&self) -> usize {
+        Bus::rom_bank(self)
+    }
+
+    #[cfg(feature = "debug")]
+    fn record_event(&mut self, event: Event) {
+        self.events.record(event);
+    }
+
+    #[cfg(feature = "debug")]
+    fn start_interrupt(&mut self) {
+        self.watchpoints.start_interrupt();
+    }
+
+    #[cfg(feature = "debug")]
+    fn read_as(&mut self, addr: u16, access: Access) -> u8 {
+        let val = Bus::read(self, addr);
+        if let Some(cdl) = &mut self.cdl {
+            let offset = (addr <= 0x7fff).then(|| self.cart.mbc.rom_offset(addr));
+            cdl.log(addr, offset, access, val);
+        }
+        match access {
+            Access::Opcode => self.watchpoints.set_pc(addr),
+            Access::Data if !self.watchpoints.is_empty() => self.watchpoints.check_read(addr, val),
+            _ => (),
+        }
+        val
+    }
+}
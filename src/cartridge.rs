@@ -3,22 +3,77 @@ pub mod mbc1;
 pub mod nombc;
 pub use mbc1::Mbc1;
 pub use nombc::NoMbc;
-use std::fmt;
+use std::{fmt, ops::Deref, sync::Arc};
 
-pub trait Mbc: fmt::Display {
+/// The contents of a ROM, shared between copies of a cartridge until one of them patches it.
+/// This keeps snapshots of the Game Boy from copying the whole ROM.
+#[derive(Clone)]
+pub struct Rom(Arc<[u8]>);
+
+impl Rom {
+    pub fn new(rom: Vec<u8>) -> Self {
+        Self(rom.into())
+    }
+
+    /// Overwrites the byte at `idx` with `val`, copying the ROM first if it is shared.
+    pub fn patch(&mut self, idx: usize, val: u8) {
+        Arc::make_mut(&mut self.0)[idx] = val;
+    }
+}
+
+impl Deref for Rom {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+pub trait Mbc: fmt::Display + Send {
     /// Reads the value at `addr` from the selected ROM bank.
     fn read_rom(&self, addr: u16) -> u8;
     /// Writes `val` to `addr` in the selected ROM bank.
     /// The MBC's usually handle this by writing `val` to one of their internal registers.
     fn write_rom(&mut self, addr: u16, val: u8);
+    /// Overwrites the value at `addr` in the selected ROM bank with `val`,
+    /// instead of writing to the MBC's internal registers.
+    fn patch_rom(&mut self, addr: u16, val: u8);
+    /// Returns the ROM bank currently mapped to `0x4000 - 0x7fff`.
+    #[cfg(feature = "debug")]
+    fn rom_bank(&self) -> usize;
+    /// Translates `addr` to an offset into the ROM, based on the selected ROM bank.
+    #[cfg(feature = "debug")]
+    fn rom_offset(&self, addr: u16) -> usize;
+    /// Returns the size of the ROM in bytes.
+    #[cfg(feature = "debug")]
+    fn rom_len(&self) -> usize;
 
     /// Reads the value at `addr` from the selected RAM bank.
     fn read_ram(&self, addr: u16) -> u8;
     /// Writes `val` to `addr` in the selected RAM bank.
     fn write_ram(&mut self, addr: u16, val: u8);
+    /// Translates `addr` to an offset into the RAM, based on the selected RAM bank.
+    fn ram_offset(&self, addr: u16) -> usize;
+
+    /// Returns the whole ROM.
+    fn rom(&self) -> &[u8];
+    /// Returns the whole RAM.
+    fn ram(&self) -> &[u8];
+    /// Returns the whole RAM, for writing.
+    fn ram_mut(&mut self) -> &mut [u8];
+
+    /// Returns a copy of the MBC with its RAM and registers, sharing its ROM.
+    fn clone_box(&self) -> Box<dyn Mbc>;
+}
+
+impl Clone for Box<dyn Mbc> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
 }
 
 /// Stores some header information of the ROM, as well as the MBC.
+#[derive(Clone)]
 pub struct Cartridge {
     title: String,
     version: u8,
@@ -1,8 +1,9 @@
-use crate::cartridge::Mbc;
+use crate::cartridge::{Mbc, Rom};
 use std::fmt;
 
 /// A memory bank controller of type 1.
 /// Stores its registers, as well as ROM and RAM.
+#[derive(Clone)]
 pub struct Mbc1 {
     ram_enable: usize,
     rom_bank: usize,
@@ -10,7 +11,7 @@ pub struct Mbc1 {
     bank_mode: usize,
 
     addr_mask: usize,
-    rom: Vec<u8>,
+    rom: Rom,
     ram: Vec<u8>,
 }
 
@@ -23,14 +24,13 @@ impl Mbc1 {
             ram_bank: 0,
             bank_mode: 0,
             addr_mask: 0x3fff | ((rom_banks - 1) << 14),
-            rom,
+            rom: Rom::new(rom),
             ram: vec![0; 0x2000],
         }
     }
-}
 
-impl Mbc for Mbc1 {
-    fn read_rom(&self, addr: u16) -> u8 {
+    /// Translates `addr` to an index into the ROM, based on the selected ROM bank.
+    fn rom_index(&self, addr: u16) -> usize {
         // Bit 00 - 13 decided by address
         let base_addr = addr as usize & 0x3fff;
         // Bit 14 - 18 decided by rom bank
@@ -44,7 +44,13 @@ impl Mbc for Mbc1 {
         // Bit 19 - 20 decided by mode and ram bank
         let mode_addr = (self.bank_mode * self.ram_bank) & 0x03;
 
-        self.rom[(base_addr | bank_addr << 14 | mode_addr << 19) & self.addr_mask]
+        (base_addr | bank_addr << 14 | mode_addr << 19) & self.addr_mask
+    }
+}
+
+impl Mbc for Mbc1 {
+    fn read_rom(&self, addr: u16) -> u8 {
+        self.rom[self.rom_index(addr)]
     }
 
     fn write_rom(&mut self, addr: u16, val: u8) {
@@ -59,26 +65,63 @@ impl Mbc for Mbc1 {
         }
     }
 
+    fn patch_rom(&mut self, addr: u16, val: u8) {
+        let idx = self.rom_index(addr);
+        self.rom.patch(idx, val);
+    }
+
+    #[cfg(feature = "debug")]
+    fn rom_bank(&self) -> usize {
+        self.rom_index(0x4000) >> 14
+    }
+
+    #[cfg(feature = "debug")]
+    fn rom_offset(&self, addr: u16) -> usize {
+        self.rom_index(addr)
+    }
+
+    #[cfg(feature = "debug")]
+    fn rom_len(&self) -> usize {
+        self.rom.len()
+    }
+
     fn read_ram(&self, addr: u16) -> u8 {
         if self.ram_enable != 0x0a {
             return 0xff;
         }
-        // Bit 00 - 12 decided by address
-        let base_addr = addr as usize;
-        // Bit 13 - 14 decided by ram bank
-        let bank_addr = self.ram_bank;
-        self.ram[base_addr | bank_addr << 13]
+        self.ram[self.ram_offset(addr)]
     }
 
     fn write_ram(&mut self, addr: u16, val: u8) {
         if self.ram_enable != 0x0a {
             return;
         }
+        let idx = self.ram_offset(addr);
+        self.ram[idx] = val;
+    }
+
+    fn ram_offset(&self, addr: u16) -> usize {
         // Bit 00 - 12 decided by address
         let base_addr = addr as usize;
         // Bit 13 - 14 decided by ram bank
         let bank_addr = self.ram_bank;
-        self.ram[base_addr | bank_addr << 13] = val;
+        base_addr | bank_addr << 13
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn clone_box(&self) -> Box<dyn Mbc> {
+        Box::new(self.clone())
     }
 }
 
@@ -1,15 +1,16 @@
-use crate::cartridge::Mbc;
+use crate::cartridge::{Mbc, Rom};
 use std::fmt;
 
+#[derive(Clone)]
 pub struct NoMbc {
-    rom: Vec<u8>,
+    rom: Rom,
     ram: Vec<u8>,
 }
 
 impl NoMbc {
     pub fn new(rom: Vec<u8>) -> Self {
         Self {
-            rom,
+            rom: Rom::new(rom),
             ram: vec![0; 0x2000],
         }
     }
@@ -20,12 +21,43 @@ impl Mbc for NoMbc {
         self.rom[addr as usize]
     }
     fn write_rom(&mut self, _: u16, _: u8) {}
+    fn patch_rom(&mut self, addr: u16, val: u8) {
+        self.rom.patch(addr as usize, val);
+    }
+    #[cfg(feature = "debug")]
+    fn rom_bank(&self) -> usize {
+        1
+    }
+    #[cfg(feature = "debug")]
+    fn rom_offset(&self, addr: u16) -> usize {
+        addr as usize
+    }
+    #[cfg(feature = "debug")]
+    fn rom_len(&self) -> usize {
+        self.rom.len()
+    }
     fn read_ram(&self, addr: u16) -> u8 {
         self.ram[addr as usize]
     }
     fn write_ram(&mut self, addr: u16, val: u8) {
         self.ram[addr as usize] = val;
     }
+    fn ram_offset(&self, addr: u16) -> usize {
+        addr as usize
+    }
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn clone_box(&self) -> Box<dyn Mbc> {
+        Box::new(self.clone())
+    }
 }
 
 impl fmt::Display for NoMbc {
@@ -1,63 +1,159 @@
+#[cfg(feature = "debug")]
+pub mod call_stack;
+#[cfg(feature = "debug")]
+pub mod cdl;
+#[cfg(feature = "debug")]
+pub mod events;
+pub mod hooks;
 pub mod instructions;
 pub mod interrupts;
+#[cfg(feature = "debug")]
+pub mod profiler;
 pub mod registers;
-use crate::{
-    bus::Bus,
-    cpu::{instructions::bitwise::BITWISE_PREFIX, registers::Regs},
-    peripherals::{Cable, Joypad, Lcd, Speaker},
+#[cfg(feature = "debug")]
+pub mod test_memory;
+#[cfg(feature = "debug")]
+pub mod watchpoints;
+#[cfg(feature = "debug")]
+use crate::cpu::{
+    call_stack::{CallFrame, CallStack},
+    events::Event,
+    profiler::{Profiler, Sample},
 };
+use crate::cpu::{hooks::Hooks, instructions::bitwise::BITWISE_PREFIX, registers::Regs};
+
+/// The interface through which the CPU accesses memory and the rest of the hardware.
+/// Implementing this allows the CPU to be used with a custom memory map.
+pub trait MemoryBus {
+    /// Reads a value from the memory mapped at `addr`.
+    fn read(&mut self, addr: u16) -> u8;
+    /// Writes the value `val` to the memory mapped at `addr`.
+    fn write(&mut self, addr: u16, val: u8);
+    /// Emulates a machine cycle for all hardware connected to the bus.
+    /// Called once every machine cycle, before the CPU accesses memory during that cycle.
+    fn tick(&mut self);
+    /// Returns whether an interrupt is requested and enabled, which wakes the CPU from `HALT`.
+    fn interrupt_pending(&self) -> bool {
+        false
+    }
+    /// Acknowledges the requested interrupt with the highest priority, if any,
+    /// and returns the address of its handler.
+    fn take_interrupt(&mut self) -> Option<u16> {
+        None
+    }
+    /// Returns the ROM bank currently mapped to `0x4000 - 0x7fff`.
+    #[cfg(feature = "debug")]
+    fn rom_bank(&self) -> usize {
+        1
+    }
+    /// Records a hardware event caused by the CPU.
+    #[cfg(feature = "debug")]
+    fn record_event(&mut self, _event: Event) {}
+    /// Called when the CPU starts dispatching an interrupt, before it pushes `PC`.
+    #[cfg(feature = "debug")]
+    fn start_interrupt(&mut self) {}
+    /// Reads a value from the memory mapped at `addr`, knowing what the CPU reads it for.
+    /// Called by the CPU instead of [`MemoryBus::read`].
+    fn read_as(&mut self, addr: u16, _access: Access) -> u8 {
+        self.read(addr)
+    }
+}
+
+/// What the CPU reads a byte for.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Access {
+    /// The first byte of an instruction.
+    Opcode,
+    /// One of the other bytes of an instruction.
+    Operand,
+    /// A value read by an instruction.
+    Data,
+}
 
 /// State of the Interrupt Master Enable (IME).
 /// - Disabled: All interrupts are disabled.
 /// - Enabling: Interrupts will be enabled next cycle.
 /// - Enabled:  Interrupts are enabled according to the IE register.
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq)]
 enum ImeState {
     Disabled,
     Enabling,
     Enabled,
 }
 
-/// Emulates the Game Boy CPU.
-pub struct Cpu<L, S, J, C>
+/// The debugging tools of a [`Cpu`], detached from it while taking or restoring a snapshot.
+#[cfg(feature = "debug")]
+pub(crate) struct CpuTools {
+    profiler: Option<Profiler>,
+    hooks: Hooks,
+}
+
+/// Emulates the Game Boy CPU, accessing memory through the [`MemoryBus`] `B`.
+///
+/// Hooks are closures which can't be cloned, so a clone of a `Cpu` has no hooks.
+#[derive(Clone)]
+pub struct Cpu<B>
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
-    bus: Bus<L, S, J, C>,
+    bus: B,
     regs: Regs,
     ime: ImeState,
     halted: bool,
+    /// Whether an invalid opcode locked up the CPU, which then ignores interrupts.
+    locked: bool,
+    #[cfg(feature = "debug")]
+    call_stack: Option<CallStack>,
+    #[cfg(feature = "debug")]
+    profiler: Option<Profiler>,
+    hooks: Hooks,
+    /// The number of machine cycles emulated so far.
+    cycles: u64,
 }
 
-impl<L, S, J, C> Cpu<L, S, J, C>
+impl<B> Cpu<B>
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
-    /// Initializes a new CPU.
-    pub(crate) fn new(rom: Vec<u8>, lcd: L, speaker: S, joypad: J, cable: C) -> Self {
+    /// Initializes a new CPU connected to `bus`.
+    pub fn new(bus: B) -> Self {
         Self {
-            bus: Bus::new(rom, lcd, speaker, joypad, cable),
+            bus,
             regs: Regs::new(),
             ime: ImeState::Enabled,
             halted: false,
+            locked: false,
+            #[cfg(feature = "debug")]
+            call_stack: None,
+            #[cfg(feature = "debug")]
+            profiler: None,
+            hooks: Hooks::default(),
+            cycles: 0,
         }
     }
 
     /// Fetches and executes one instruction, and checks for interrupts.
-    pub(crate) fn step(&mut self) {
+    pub fn step(&mut self) {
+        // Sample before executing, so that calls and returns count towards the caller.
+        #[cfg(feature = "debug")]
+        let sample = self.profiler.is_some().then(|| Sample {
+            bank: self.pc_bank(),
+            addr: self.regs.pc(),
+            cycles: self.cycles,
+            halted: self.halted,
+            node: self.profiler.as_ref().map_or(0, Profiler::current),
+        });
+
         if self.ime == ImeState::Enabling {
             self.ime = ImeState::Enabled;
         }
 
-        if self.halted {
+        if self.locked {
+            // Only the rest of the hardware keeps running.
+            self.cycle();
+        } else if self.halted {
             self.cycle();
-            if self.bus.interrupts.pending() {
+            if self.bus.interrupt_pending() {
                 log::debug!("CPU: Unhalted");
                 self.halted = false;
             }
@@ -65,17 +161,24 @@ where
             self.execute_next();
         }
 
-        if self.ime == ImeState::Enabled {
+        if self.ime == ImeState::Enabled && !self.locked {
             // Handle an interrupt if there is any.
-            if let Some(addr) = self.bus.interrupts.step() {
+            if let Some(addr) = self.bus.take_interrupt() {
                 self.handle_interrupt(addr);
             }
         }
+
+        #[cfg(feature = "debug")]
+        if let (Some(profiler), Some(sample)) = (&mut self.profiler, sample) {
+            profiler.record(sample, self.cycles);
+        }
     }
 
     /// Executes the instruction currently at `(PC)`.
     fn execute_next(&mut self) {
-        let opcode = self.fetch_byte();
+        let pc = self.regs.pc();
+        let opcode = self.fetch_opcode();
+        self.hooks.exec(pc, opcode, self.cycles);
         match opcode {
             BITWISE_PREFIX => {
                 let opcode = self.fetch_byte();
@@ -87,11 +190,19 @@ where
 
     /// Handles an interrupt. Takes 5 machine cycles.
     fn handle_interrupt(&mut self, addr: u16) {
+        self.hooks.set_pc(self.regs.pc());
+        #[cfg(feature = "debug")]
+        self.bus.start_interrupt();
         self.ime = ImeState::Disabled;
         self.cycle();
         self.cycle();
         self.stack_push(self.regs.pc());
         self.cycle();
+        #[cfg(feature = "debug")]
+        {
+            self.push_frame(addr, true);
+            self.bus.record_event(Event::Interrupt { handler: addr });
+        }
         self.regs.set_pc(addr);
     }
 
@@ -100,13 +211,16 @@ where
     /// Also called during some instructions if they take an extra internal cycle,
     /// like for branch instructions and 16-bit arithmetic.
     pub(crate) fn cycle(&mut self) {
-        self.bus.io_step();
+        self.bus.tick();
+        self.cycles += 1;
     }
 
     /// Reads the byte at `addr`. Takes a machine cycle.
     pub(crate) fn read_byte(&mut self, addr: u16) -> u8 {
         self.cycle();
-        self.bus.read(addr)
+        let val = self.bus.read_as(addr, Access::Data);
+        self.hooks.read(addr, val, self.cycles);
+        val
     }
 
     /// Reads two bytes at `addr` and `addr + 1`. Takes two machine cycles.
@@ -118,7 +232,9 @@ where
     /// Writes `val` to `addr`. Takes a machine cycle.
     pub(crate) fn write_byte(&mut self, addr: u16, val: u8) {
         self.cycle();
-        self.bus.write(addr, val);
+        if let Some(val) = self.hooks.write(addr, val, self.cycles) {
+            self.bus.write(addr, val);
+        }
     }
 
     /// Writes `val` to `addr` and `addr + 1`. Takes two machine cycles.
@@ -127,11 +243,20 @@ where
         self.write_byte(addr + 1, (val >> 8) as u8);
     }
 
+    /// Fetches the opcode at `(PC)`, and increments `PC`. Takes a machine cycle.
+    fn fetch_opcode(&mut self) -> u8 {
+        let addr = self.regs.pc();
+        self.regs.inc_pc();
+        self.cycle();
+        self.bus.read_as(addr, Access::Opcode)
+    }
+
     /// Fetches the byte at `(PC)`, and increments `PC`. Takes a machine cycle.
     pub(crate) fn fetch_byte(&mut self) -> u8 {
         let addr = self.regs.pc();
         self.regs.inc_pc();
-        self.read_byte(addr)
+        self.cycle();
+        self.bus.read_as(addr, Access::Operand)
     }
 
     /// Fetches two bytes at `(PC)`, and increments `PC` twice. Takes two machine cycles.
@@ -164,13 +289,185 @@ where
         self.read_byte(addr)
     }
 
-    #[cfg(feature = "debug")]
-    pub(crate) fn regs(&self) -> &Regs {
+    /// Retrieves the current CPU register values.
+    pub fn regs(&self) -> &Regs {
         &self.regs
     }
 
+    /// Retrieves a mutable reference to the CPU registers.
+    /// Needed without the `debug` feature to call into code on a custom bus,
+    /// like the init and play routines of GBS music, by setting `PC` and `SP`.
+    pub fn regs_mut(&mut self) -> &mut Regs {
+        &mut self.regs
+    }
+
+    /// Returns whether the Interrupt Master Enable is set, or will be set after the next instruction.
+    pub fn ime(&self) -> bool {
+        self.ime != ImeState::Disabled
+    }
+
+    /// Sets the Interrupt Master Enable.
     #[cfg(feature = "debug")]
-    pub(crate) fn bus(&self) -> &Bus<L, S, J, C> {
+    pub fn set_ime(&mut self, ime: bool) {
+        self.ime = if ime {
+            ImeState::Enabled
+        } else {
+            ImeState::Disabled
+        };
+    }
+
+    /// Returns whether the CPU is halted, waiting for an interrupt.
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Returns whether an invalid opcode locked up the CPU.
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Halts the CPU until an interrupt is requested, or wakes it up.
+    #[cfg(feature = "debug")]
+    pub fn set_halted(&mut self, halted: bool) {
+        self.halted = halted;
+    }
+
+    /// Enables or disables the shadow call stack. Disabling it discards all frames.
+    #[cfg(feature = "debug")]
+    pub fn track_calls(&mut self, enabled: bool) {
+        self.call_stack = enabled.then(CallStack::default);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.set_stack([]);
+        }
+    }
+
+    /// Returns the frames on the shadow call stack, starting with the outermost one.
+    /// Empty if call tracking is disabled.
+    #[cfg(feature = "debug")]
+    pub fn call_stack(&self) -> &[CallFrame] {
+        self.call_stack.as_ref().map_or(&[], CallStack::frames)
+    }
+
+    /// Starts counting executed instructions and cycles, discarding any previous counts.
+    /// Also enables the shadow call stack, so that counts can be attributed to calls.
+    #[cfg(feature = "debug")]
+    pub fn start_profiling(&mut self) {
+        self.resume_profiling(Profiler::default());
+    }
+
+    /// Continues counting executed instructions and cycles on top of the counts in `profiler`,
+    /// like one returned by [`Cpu::stop_profiling`]. Counts are attributed to the current calls.
+    #[cfg(feature = "debug")]
+    pub fn resume_profiling(&mut self, mut profiler: Profiler) {
+        if self.call_stack.is_none() {
+            self.track_calls(true);
+        }
+        profiler.set_stack(self.call_stack().iter().map(|f| (f.bank, f.target)));
+        self.profiler = Some(profiler);
+    }
+
+    /// Stops counting executed instructions and cycles, and returns the counts so far.
+    #[cfg(feature = "debug")]
+    pub fn stop_profiling(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    /// Returns the profiler, if profiling is running.
+    #[cfg(feature = "debug")]
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Detaches the profiler and hooks, so that they are left out of snapshots.
+    #[cfg(feature = "debug")]
+    pub(crate) fn take_tools(&mut self) -> CpuTools {
+        CpuTools {
+            profiler: self.profiler.take(),
+            hooks: std::mem::take(&mut self.hooks),
+        }
+    }
+
+    /// Attaches the profiler and hooks detached with [`Cpu::take_tools`],
+    /// so that they keep running after restoring a snapshot.
+    #[cfg(feature = "debug")]
+    pub(crate) fn set_tools(&mut self, tools: CpuTools) {
+        self.hooks = tools.hooks;
+        self.profiler = None;
+        // The restored call stack may differ from the one the profiler followed.
+        if let Some(profiler) = tools.profiler {
+            self.resume_profiling(profiler);
+        }
+    }
+
+    /// Returns the hooks called on memory accesses of the CPU.
+    pub fn hooks(&self) -> &Hooks {
+        &self.hooks
+    }
+
+    /// Returns the hooks called on memory accesses of the CPU, to add or remove them.
+    pub fn hooks_mut(&mut self) -> &mut Hooks {
+        &mut self.hooks
+    }
+
+    /// Returns the number of machine cycles emulated so far.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Returns the ROM bank that `PC` is in.
+    #[cfg(feature = "debug")]
+    fn pc_bank(&self) -> usize {
+        match self.regs.pc() {
+            0x4000..=0x7fff => self.bus.rom_bank(),
+            _ => 0,
+        }
+    }
+
+    /// Pushes a frame for a call to `target` onto the shadow call stack, if it is enabled.
+    /// Should be called right after the return address is pushed.
+    #[cfg(feature = "debug")]
+    pub(crate) fn push_frame(&mut self, target: u16, interrupt: bool) {
+        let bank = match target {
+            0x4000..=0x7fff => self.bus.rom_bank(),
+            _ => 0,
+        };
+        let (return_addr, sp) = (self.regs.pc(), self.regs.sp());
+        if let Some(call_stack) = &mut self.call_stack {
+            call_stack.call(CallFrame {
+                target,
+                bank,
+                return_addr,
+                sp,
+                interrupt,
+            });
+            if let Some(profiler) = &mut self.profiler {
+                profiler.call(bank, target);
+            }
+        }
+    }
+
+    /// Pops the returned from frames off the shadow call stack, if it is enabled.
+    /// Should be called right before the return address is popped.
+    #[cfg(feature = "debug")]
+    pub(crate) fn pop_frame(&mut self) {
+        let sp = self.regs.sp();
+        if let Some(call_stack) = &mut self.call_stack {
+            call_stack.ret(sp);
+            if let Some(profiler) = &mut self.profiler {
+                profiler.ret(call_stack.frames().len());
+            }
+        }
+    }
+
+    /// Retrieves the bus the CPU is connected to.
+    pub fn bus(&self) -> &B {
         &self.bus
     }
+
+    /// Retrieves a mutable reference to the bus the CPU is connected to.
+    /// The bus is owned by the CPU, so this is the only way to reach a custom bus
+    /// after creating the CPU, for example to load data into it.
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
 }
@@ -0,0 +1,40 @@
+/// A call that has not returned yet.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CallFrame {
+    /// The address that was called.
+    pub target: u16,
+    /// The ROM bank `target` is in.
+    pub bank: usize,
+    /// The address the call returns to.
+    pub return_addr: u16,
+    /// The stack pointer pointing at the return address.
+    pub sp: u16,
+    /// Whether the call was an interrupt dispatch, rather than a `CALL` or `RST`.
+    pub interrupt: bool,
+}
+
+/// A shadow call stack that follows calls and returns, independent of the stack in memory.
+#[derive(Clone, Default)]
+pub struct CallStack {
+    frames: Vec<CallFrame>,
+}
+
+impl CallStack {
+    /// Pushes a new frame.
+    pub fn call(&mut self, frame: CallFrame) {
+        self.frames.push(frame);
+    }
+
+    /// Pops the frame returned from by a return with `SP` at `sp`. Also pops any frames
+    /// whose return address was discarded from the stack without returning.
+    pub fn ret(&mut self, sp: u16) {
+        while self.frames.last().is_some_and(|frame| frame.sp <= sp) {
+            self.frames.pop();
+        }
+    }
+
+    /// Returns all frames, starting with the outermost one.
+    pub fn frames(&self) -> &[CallFrame] {
+        &self.frames
+    }
+}
@@ -0,0 +1,112 @@
+use crate::cpu::Access;
+
+/// The size of a single ROM bank.
+const BANK_SIZE: usize = 0x4000;
+
+/// The header that Mesen puts in front of the flags in newer `.cdl` files, followed by a CRC32.
+const MESEN_HEADER: &[u8] = b"CDLv2";
+
+/// A Code/Data Log, which marks how every byte of the ROM was accessed while the game ran.
+/// Stored as one byte of flags per ROM byte, in ROM order, using the bits of Mesen's Game Boy
+/// Code/Data Logger. A `.cdl` file is exactly these bytes, without a header, which Mesen loads
+/// as well. Mesen's own files, which start with a `CDLv2` header, can be loaded too.
+///
+/// The file format can't tell opcodes from operands, so that distinction is only kept in memory
+/// for a log that was recorded here. A loaded log only knows the opcodes at jump targets and
+/// subroutine entry points.
+#[derive(Clone)]
+pub struct CodeDataLog {
+    flags: Vec<u8>,
+    /// Whether each ROM byte was executed as the first byte of an instruction.
+    opcodes: Vec<bool>,
+    /// The address the next opcode is fetched from, unless the CPU branches.
+    next_opcode: Option<u16>,
+    /// Whether the last opcode was a `call` or `rst`, so that a branch enters a subroutine.
+    call: bool,
+}
+
+impl CodeDataLog {
+    /// The byte was executed, either as an opcode or as an operand.
+    pub const CODE: u8 = 0x01;
+    /// The byte was read as data.
+    pub const DATA: u8 = 0x02;
+    /// The byte was executed as an opcode right after a branch.
+    pub const JUMP_TARGET: u8 = 0x04;
+    /// The byte was executed as an opcode right after a `call` or `rst`.
+    pub const SUB_ENTRY_POINT: u8 = 0x08;
+
+    /// Initializes an empty log for a ROM of `len` bytes.
+    pub fn new(len: usize) -> Self {
+        Self::from_bytes(vec![0; len])
+    }
+
+    /// Loads a log from the contents of a `.cdl` file, with or without Mesen's header.
+    pub fn from_bytes(mut flags: Vec<u8>) -> Self {
+        if flags.starts_with(MESEN_HEADER) {
+            flags.drain(..(MESEN_HEADER.len() + 4).min(flags.len()));
+        }
+        Self {
+            opcodes: vec![false; flags.len()],
+            flags,
+            next_opcode: None,
+            call: false,
+        }
+    }
+
+    /// Records an access of the CPU to `addr`, which is at `offset` in the ROM if it is mapped to ROM.
+    /// `val` is the byte that was read.
+    pub(crate) fn log(&mut self, addr: u16, offset: Option<usize>, access: Access, val: u8) {
+        let mut flags = match access {
+            Access::Opcode | Access::Operand => Self::CODE,
+            Access::Data => Self::DATA,
+        };
+        match access {
+            Access::Opcode => {
+                if self.next_opcode.is_some_and(|next| next != addr) {
+                    flags |= match self.call {
+                        true => Self::JUMP_TARGET | Self::SUB_ENTRY_POINT,
+                        false => Self::JUMP_TARGET,
+                    };
+                }
+                self.next_opcode = Some(addr.wrapping_add(1));
+                // call, call cc and rst
+                self.call = matches!(val, 0xcd | 0xc4 | 0xcc | 0xd4 | 0xdc) || val & 0xc7 == 0xc7;
+                if let Some(opcode) = offset.and_then(|offset| self.opcodes.get_mut(offset)) {
+                    *opcode = true;
+                }
+            }
+            Access::Operand => self.next_opcode = Some(addr.wrapping_add(1)),
+            Access::Data => (),
+        }
+        if let Some(byte) = offset.and_then(|offset| self.flags.get_mut(offset)) {
+            *byte |= flags;
+        }
+    }
+
+    /// Returns the flags of every ROM byte, in the format of a `.cdl` file.
+    pub fn bytes(&self) -> &[u8] {
+        &self.flags
+    }
+
+    /// Returns the bank and address of every byte that is known to have been executed as an opcode.
+    pub fn opcodes(&self) -> impl Iterator<Item = (usize, u16)> + '_ {
+        self.flags
+            .iter()
+            .zip(&self.opcodes)
+            .enumerate()
+            .filter(|(_, (flags, opcode))| {
+                **opcode || *flags & (Self::JUMP_TARGET | Self::SUB_ENTRY_POINT) != 0
+            })
+            .map(|(offset, _)| match offset / BANK_SIZE {
+                0 => (0, offset as u16),
+                bank => (bank, (BANK_SIZE + offset % BANK_SIZE) as u16),
+            })
+    }
+
+    /// Returns the number of bytes that were executed, read as data, and never accessed.
+    pub fn coverage(&self) -> (usize, usize, usize) {
+        let count = |flag: u8| self.flags.iter().filter(|f| *f & flag != 0).count();
+        let unused = self.flags.iter().filter(|f| **f == 0).count();
+        (count(Self::CODE), count(Self::DATA), unused)
+    }
+}
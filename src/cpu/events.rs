@@ -0,0 +1,85 @@
+use std::{fmt, mem};
+
+/// A hardware event that a debugger can stop on.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Event {
+    /// The CPU jumped to the handler of an interrupt.
+    Interrupt { handler: u16 },
+    /// The CPU wrote `val` to the IO register at `addr`.
+    IoWrite { addr: u16, val: u8 },
+    /// A write to the MBC mapped another ROM bank to `0x4000 - 0x7fff`.
+    BankSwitch { bank: usize },
+    /// An OAM DMA transfer started, copying from `source`.
+    DmaStart { source: u16 },
+    /// The LCD was turned on or off.
+    Lcd { on: bool },
+    /// The CPU fetched an invalid opcode, which locks it up.
+    IllegalOpcode { addr: u16, opcode: u8 },
+}
+
+impl Event {
+    /// Returns the name of the interrupt whose handler is at `handler`.
+    pub fn interrupt_name(handler: u16) -> &'static str {
+        match handler {
+            0x40 => "VBLANK",
+            0x48 => "LCDSTAT",
+            0x50 => "TIMER",
+            0x58 => "SERIAL",
+            0x60 => "JOYPAD",
+            _ => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Interrupt { handler } => {
+                write!(f, "{} interrupt", Event::interrupt_name(*handler))
+            }
+            Event::IoWrite { addr, val } => write!(f, "write of {:#04x} to {:#06x}", val, addr),
+            Event::BankSwitch { bank } => write!(f, "switch to ROM bank {}", bank),
+            Event::DmaStart { source } => write!(f, "OAM DMA from {:#06x}", source),
+            Event::Lcd { on: true } => write!(f, "LCD turned on"),
+            Event::Lcd { on: false } => write!(f, "LCD turned off"),
+            Event::IllegalOpcode { addr, opcode } => {
+                write!(f, "illegal opcode {:#04x} at {:#06x}", opcode, addr)
+            }
+        }
+    }
+}
+
+/// The hardware events that happened since they were last taken.
+/// Only recorded while enabled, so that nothing piles up when no one is listening.
+#[derive(Clone, Default)]
+pub struct Events {
+    enabled: bool,
+    events: Vec<Event>,
+}
+
+impl Events {
+    /// Starts or stops recording events. Stopping discards the recorded events.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.events.clear();
+        }
+    }
+
+    /// Returns whether events are being recorded.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns and clears the events recorded since the last call, in order.
+    pub fn take(&mut self) -> Vec<Event> {
+        mem::take(&mut self.events)
+    }
+
+    /// Records `event`, if recording is enabled.
+    pub(crate) fn record(&mut self, event: Event) {
+        if self.enabled {
+            self.events.push(event);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::ops::RangeInclusive;
+
+/// A memory access of the CPU, passed to hooks.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MemAccess {
+    /// The address that was accessed.
+    pub addr: u16,
+    /// The value that was read or is about to be written, or the opcode of an executed instruction.
+    pub val: u8,
+    /// The address of the instruction that accessed memory.
+    /// For the writes of an interrupt pushing `PC`, the address it returns to.
+    pub pc: u16,
+    /// The number of machine cycles emulated so far.
+    pub cycles: u64,
+}
+
+/// What a write hook does with the write that triggered it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WriteAction {
+    /// Writes the value unchanged.
+    Keep,
+    /// Writes this value instead.
+    Replace(u8),
+    /// Drops the write, leaving memory unchanged.
+    Block,
+}
+
+/// Identifies a registered hook, to remove it later.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct HookId(u64);
+
+struct Hook<F>
+where
+    F: ?Sized,
+{
+    id: HookId,
+    range: RangeInclusive<u16>,
+    f: Box<F>,
+}
+
+type ReadFn = dyn FnMut(&MemAccess) + Send;
+type WriteFn = dyn FnMut(&MemAccess) -> WriteAction + Send;
+
+/// Callbacks called on reads, writes and instruction fetches of the CPU within address ranges.
+/// When no hooks of a kind are registered, checking them is a single branch.
+#[derive(Default)]
+pub struct Hooks {
+    reads: Vec<Hook<ReadFn>>,
+    writes: Vec<Hook<WriteFn>>,
+    execs: Vec<Hook<ReadFn>>,
+    next_id: u64,
+    /// The address of the instruction being executed.
+    pc: u16,
+}
+
+impl Hooks {
+    /// Calls `hook` whenever an instruction reads from an address in `range`.
+    /// Fetches of opcodes and operands are not reads, and only opcodes are seen by exec hooks.
+    pub fn add_read<F>(&mut self, range: RangeInclusive<u16>, hook: F) -> HookId
+    where
+        F: FnMut(&MemAccess) + Send + 'static,
+    {
+        let id = self.next_id();
+        self.reads.push(Hook {
+            id,
+            range,
+            f: Box::new(hook),
+        });
+        id
+    }
+
+    /// Calls `hook` whenever an instruction writes to an address in `range`,
+    /// before the write happens. The returned [`WriteAction`] decides what is written.
+    pub fn add_write<F>(&mut self, range: RangeInclusive<u16>, hook: F) -> HookId
+    where
+        F: FnMut(&MemAccess) -> WriteAction + Send + 'static,
+    {
+        let id = self.next_id();
+        self.writes.push(Hook {
+            id,
+            range,
+            f: Box::new(hook),
+        });
+        id
+    }
+
+    /// Calls `hook` whenever the CPU executes an instruction at an address in `range`.
+    pub fn add_exec<F>(&mut self, range: RangeInclusive<u16>, hook: F) -> HookId
+    where
+        F: FnMut(&MemAccess) + Send + 'static,
+    {
+        let id = self.next_id();
+        self.execs.push(Hook {
+            id,
+            range,
+            f: Box::new(hook),
+        });
+        id
+    }
+
+    /// Removes the hook `id`, and returns whether it existed.
+    pub fn remove(&mut self, id: HookId) -> bool {
+        let len = self.len();
+        self.reads.retain(|hook| hook.id != id);
+        self.writes.retain(|hook| hook.id != id);
+        self.execs.retain(|hook| hook.id != id);
+        self.len() != len
+    }
+
+    /// Removes all hooks.
+    pub fn clear(&mut self) {
+        self.reads.clear();
+        self.writes.clear();
+        self.execs.clear();
+    }
+
+    /// Returns the number of registered hooks.
+    pub fn len(&self) -> usize {
+        self.reads.len() + self.writes.len() + self.execs.len()
+    }
+
+    /// Returns whether no hooks are registered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Calls the exec hooks for the instruction with `opcode` at `pc`.
+    #[inline]
+    pub(crate) fn exec(&mut self, pc: u16, opcode: u8, cycles: u64) {
+        if self.is_empty() {
+            return;
+        }
+        self.pc = pc;
+        let access = MemAccess {
+            addr: pc,
+            val: opcode,
+            pc,
+            cycles,
+        };
+        for hook in self.execs.iter_mut() {
+            if hook.range.contains(&pc) {
+                (hook.f)(&access);
+            }
+        }
+    }
+
+    /// Sets the address reported to hooks for the accesses that follow.
+    #[inline]
+    pub(crate) fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    /// Calls the read hooks for an instruction reading `val` from `addr`.
+    #[inline]
+    pub(crate) fn read(&mut self, addr: u16, val: u8, cycles: u64) {
+        if self.reads.is_empty() {
+            return;
+        }
+        let access = MemAccess {
+            addr,
+            val,
+            pc: self.pc,
+            cycles,
+        };
+        for hook in self.reads.iter_mut() {
+            if hook.range.contains(&addr) {
+                (hook.f)(&access);
+            }
+        }
+    }
+
+    /// Calls the write hooks for an instruction writing `val` to `addr`.
+    /// Returns the value to write, or `None` if the write is blocked.
+    #[inline]
+    pub(crate) fn write(&mut self, addr: u16, val: u8, cycles: u64) -> Option<u8> {
+        if self.writes.is_empty() {
+            return Some(val);
+        }
+        let mut access = MemAccess {
+            addr,
+            val,
+            pc: self.pc,
+            cycles,
+        };
+        for hook in self.writes.iter_mut() {
+            if !hook.range.contains(&addr) {
+                continue;
+            }
+            // Later hooks see the value replaced by earlier ones.
+            match (hook.f)(&access) {
+                WriteAction::Keep => (),
+                WriteAction::Replace(val) => access.val = val,
+                WriteAction::Block => return None,
+            }
+        }
+        Some(access.val)
+    }
+
+    fn next_id(&mut self) -> HookId {
+        self.next_id += 1;
+        HookId(self.next_id)
+    }
+}
+
+/// The hooks are closures which can't be cloned, so a clone has none.
+impl Clone for Hooks {
+    fn clone(&self) -> Self {
+        Self {
+            next_id: self.next_id,
+            pc: self.pc,
+            ..Self::default()
+        }
+    }
+}
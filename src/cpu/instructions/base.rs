@@ -1,14 +1,8 @@
-use crate::{
-    cpu::{instructions::helpers, Cpu, ImeState},
-    peripherals::{Cable, Joypad, Lcd, Speaker},
-};
+use crate::cpu::{instructions::helpers, Cpu, ImeState, MemoryBus};
 
-impl<L, S, J, C> Cpu<L, S, J, C>
+impl<B> Cpu<B>
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     pub(in crate::cpu) fn execute_base(&mut self, opcode: u8) {
         match opcode {
@@ -730,7 +724,7 @@ where
                 }
             }
             0xcb => {
-                helpers::invalid();
+                helpers::invalid(self, opcode);
             }
             0xcc => {
                 let val = self.fetch_word();
@@ -771,7 +765,7 @@ where
                 }
             }
             0xd3 => {
-                helpers::invalid();
+                helpers::invalid(self, opcode);
             }
             0xd4 => {
                 let val = self.fetch_word();
@@ -812,7 +806,7 @@ where
                 }
             }
             0xdb => {
-                helpers::invalid();
+                helpers::invalid(self, opcode);
             }
             0xdc => {
                 let val = self.fetch_word();
@@ -822,7 +816,7 @@ where
                 }
             }
             0xdd => {
-                helpers::invalid();
+                helpers::invalid(self, opcode);
             }
             0xde => {
                 let val = self.fetch_byte();
@@ -844,10 +838,10 @@ where
                 self.write_byte(0xff00 + self.regs.c() as u16, self.regs.a());
             }
             0xe3 => {
-                helpers::invalid();
+                helpers::invalid(self, opcode);
             }
             0xe4 => {
-                helpers::invalid();
+                helpers::invalid(self, opcode);
             }
             0xe5 => {
                 self.cycle();
@@ -876,13 +870,13 @@ where
                 self.write_byte(addr, self.regs.a());
             }
             0xeb => {
-                helpers::invalid();
+                helpers::invalid(self, opcode);
             }
             0xec => {
-                helpers::invalid();
+                helpers::invalid(self, opcode);
             }
             0xed => {
-                helpers::invalid();
+                helpers::invalid(self, opcode);
             }
             0xee => {
                 let val = self.fetch_byte();
@@ -909,7 +903,7 @@ where
                 self.ime = ImeState::Disabled;
             }
             0xf4 => {
-                helpers::invalid();
+                helpers::invalid(self, opcode);
             }
             0xf5 => {
                 self.cycle();
@@ -943,10 +937,10 @@ where
                 self.ime = ImeState::Enabling;
             }
             0xfc => {
-                helpers::invalid();
+                helpers::invalid(self, opcode);
             }
             0xfd => {
-                helpers::invalid();
+                helpers::invalid(self, opcode);
             }
             0xfe => {
                 let val = self.fetch_byte();
@@ -1,16 +1,10 @@
-use crate::{
-    cpu::{instructions::helpers, Cpu},
-    peripherals::{Cable, Joypad, Lcd, Speaker},
-};
+use crate::cpu::{instructions::helpers, Cpu, MemoryBus};
 
 pub const BITWISE_PREFIX: u8 = 0xcb;
 
-impl<L, S, J, C> Cpu<L, S, J, C>
+impl<B> Cpu<B>
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     pub(in crate::cpu) fn execute_bitwise(&mut self, opcode: u8) {
         match opcode {
@@ -1,6 +1,13 @@
+pub mod asm;
 pub mod base;
 pub mod bitwise;
+pub mod disasm;
+pub mod symbols;
 
+use crate::cpu::instructions::{
+    bitwise::BITWISE_PREFIX,
+    debug::{base::BASE_INSTR_INFO, bitwise::BITWISE_INSTR_INFO},
+};
 use std::fmt::{Display, Formatter, Result};
 
 /// The size of the parameter expected by an instruction.
@@ -11,6 +18,7 @@ pub enum ParamType {
     Word,
 }
 
+#[allow(dead_code)]
 #[derive(Clone, Copy)]
 pub enum Cycles {
     Normal(usize),
@@ -46,11 +54,33 @@ pub trait HasImmediate {
     fn with_immediate(self, bytes: &[u8]) -> Self;
 }
 
+/// Decodes the instruction at the start of `bytes`. Returns the length of the instruction,
+/// and the mnemonic.
+pub fn decode(bytes: [u8; 3]) -> (usize, Mnemonic) {
+    match bytes[0] {
+        BITWISE_PREFIX => {
+            // All bitwise instructions have length 2,
+            // and no bytes containing immediates.
+            (2, *BITWISE_INSTR_INFO[bytes[1] as usize].mnemonic())
+        }
+        opcode => {
+            // Length can vary for other instructions.
+            let instr = &BASE_INSTR_INFO[opcode as usize];
+            let len = match instr.param_type() {
+                ParamType::None => 1,
+                ParamType::Byte => 2,
+                ParamType::Word => 3,
+            };
+            (len, instr.mnemonic().with_immediate(&bytes[1..len]))
+        }
+    }
+}
+
 // Currently only used for disassembly, but might be interesting
 // to use for generalizing instruction operations. Will mean
 // rewriting all instructions and part of the cpu though.
 /// The visual representation of an instruction.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Mnemonic {
     // Misc
     /// `NOP`
@@ -248,7 +278,7 @@ pub enum Mnemonic {
     Invalid,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Addr {
     /// `(FF00+u8)`
     Imm8(Imm<u8>),
@@ -264,7 +294,7 @@ pub enum Addr {
     Reg16Dec(Reg16),
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Reg8 {
     A,
     B,
@@ -275,7 +305,7 @@ pub enum Reg8 {
     L,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Reg16 {
     AF,
     BC,
@@ -284,7 +314,7 @@ pub enum Reg16 {
     SP,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Cond {
     /// Condition that is satisfied if the Zero flag is set.
     Z,
@@ -296,12 +326,52 @@ pub enum Cond {
     NC,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Imm<T> {
     Known(T),
     Unknown,
 }
 
+impl Mnemonic {
+    /// Formats the mnemonic located at `addr` like its [`Display`] implementation, but with
+    /// the targets of jumps, calls and address loads replaced by the label returned by `label`.
+    pub fn to_string_with_labels<F>(&self, addr: u16, label: F) -> String
+    where
+        F: Fn(u16) -> Option<String>,
+    {
+        let jr = |e: &i8| addr.wrapping_add(2).wrapping_add(*e as u16);
+        match self {
+            Self::Jr(Imm::Known(e)) => label(jr(e)).map(|l| format!("{:04} {}", "JR", l)),
+            Self::JrCond(c, Imm::Known(e)) => {
+                label(jr(e)).map(|l| format!("{:04} {},{}", "JR", c, l))
+            }
+            Self::Jp(Imm::Known(i)) => label(*i).map(|l| format!("{:04} {}", "JP", l)),
+            Self::JpCond(c, Imm::Known(i)) => label(*i).map(|l| format!("{:04} {},{}", "JP", c, l)),
+            Self::Call(Imm::Known(i)) => label(*i).map(|l| format!("{:04} {}", "CALL", l)),
+            Self::CallCond(c, Imm::Known(i)) => {
+                label(*i).map(|l| format!("{:04} {},{}", "CALL", c, l))
+            }
+            Self::Rst(Imm::Known(i)) => label(*i as u16).map(|l| format!("{:04} {}", "RST", l)),
+            Self::LdReg16Imm(r, Imm::Known(i)) => {
+                label(*i).map(|l| format!("{:04} {},{}", "LD", r, l))
+            }
+            Self::LdImmAddrSp(Imm::Known(i)) => {
+                label(*i).map(|l| format!("{:04} ({}),{}", "LD", l, Reg16::SP))
+            }
+            Self::LdReg8Addr(r, a) => a
+                .target()
+                .and_then(&label)
+                .map(|l| format!("{:04} {},({})", "LD", r, l)),
+            Self::LdAddrReg8(a, r) => a
+                .target()
+                .and_then(&label)
+                .map(|l| format!("{:04} ({}),{}", "LD", l, r)),
+            _ => None,
+        }
+        .unwrap_or_else(|| self.to_string())
+    }
+}
+
 impl Display for Mnemonic {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         write!(
@@ -450,6 +520,17 @@ impl Display for Addr {
     }
 }
 
+impl Addr {
+    /// Returns the address referred to by an immediate address.
+    fn target(&self) -> Option<u16> {
+        match self {
+            Self::Imm8(Imm::Known(i)) => Some(0xff00 | *i as u16),
+            Self::Imm16(Imm::Known(i)) => Some(*i),
+            _ => None,
+        }
+    }
+}
+
 impl HasImmediate for Addr {
     fn with_immediate(self, bytes: &[u8]) -> Self {
         match self {
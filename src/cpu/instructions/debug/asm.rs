@@ -0,0 +1,538 @@
+use crate::cpu::instructions::{
+    bitwise::BITWISE_PREFIX,
+    debug::{
+        base::BASE_INSTR_INFO, bitwise::BITWISE_INSTR_INFO, Addr, Cond, HasImmediate, Imm,
+        InstrInfo, Mnemonic, ParamType, Reg16, Reg8,
+    },
+};
+
+/// A parsed instruction operand.
+#[derive(Clone, Copy, PartialEq)]
+enum Operand {
+    R8(Reg8),
+    R16(Reg16),
+    /// `[..]`, a memory operand.
+    Mem(Addr),
+    /// A numeric expression.
+    Num(i32),
+    /// `sp+e`, only valid as the source of `ld hl,sp+e`.
+    SpOffset(i32),
+}
+
+/// Assembles one or more instructions in RGBDS syntax, separated by newlines,
+/// into machine code that will be placed at `addr`.
+///
+/// Memory operands can be written with either `[]` or `()`. Numbers are decimal, or hex
+/// when prefixed with `$` or `0x`, or binary when prefixed with `%`. A lone `$` or `@` refers to
+/// the address of the current instruction, which allows relative jumps like `jr nz,$-5`.
+/// Comments start with `;`.
+pub fn assemble(src: &str, addr: u16) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    for line in src.lines() {
+        let line = line.split(';').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let instr_addr = addr.wrapping_add(bytes.len() as u16);
+        let mnemonic = parse(line, instr_addr).map_err(|e| format!("{}: {}", line, e))?;
+        bytes.extend(encode(&mnemonic).ok_or_else(|| format!("{}: Cannot be encoded", line))?);
+    }
+    Ok(bytes)
+}
+
+/// Parses a single instruction at `addr` into its mnemonic.
+pub fn parse(line: &str, addr: u16) -> Result<Mnemonic, String> {
+    let line = line.trim().to_lowercase();
+    let (name, operands) = match line.split_once(char::is_whitespace) {
+        Some((name, operands)) => (name, operands.trim()),
+        None => (line.as_str(), ""),
+    };
+    let operands = match operands {
+        "" => vec![],
+        _ => operands.split(',').map(str::trim).collect::<Vec<&str>>(),
+    };
+
+    match name {
+        "jr" | "jp" | "call" | "ret" => return parse_branch(name, &operands, addr),
+        "rst" => {
+            return match operands[..] {
+                [vec] => match parse_num(vec, addr)? {
+                    vec @ (0x00 | 0x08 | 0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38) => {
+                        Ok(Mnemonic::Rst(Imm::Known(vec as u8)))
+                    }
+                    _ => Err(format!("Invalid reset vector: {}", vec)),
+                },
+                _ => Err("Expected a single operand".to_string()),
+            }
+        }
+        _ => (),
+    }
+
+    let operands = operands
+        .iter()
+        .map(|op| parse_operand(op, addr))
+        .collect::<Result<Vec<Operand>, String>>()?;
+    use Operand::*;
+    let hl = Addr::Reg16(Reg16::HL);
+    let mnemonic = match (name, &operands[..]) {
+        ("nop", []) => Mnemonic::Nop,
+        ("stop", [] | [Num(0)]) => Mnemonic::Stop,
+        ("halt", []) => Mnemonic::Halt,
+        ("di", []) => Mnemonic::Di,
+        ("ei", []) => Mnemonic::Ei,
+        ("daa", []) => Mnemonic::Daa,
+        ("cpl", [] | [R8(Reg8::A)]) => Mnemonic::Cpl,
+        ("scf", []) => Mnemonic::Scf,
+        ("ccf", []) => Mnemonic::Ccf,
+        ("rlca", []) => Mnemonic::Rlca,
+        ("rrca", []) => Mnemonic::Rrca,
+        ("rla", []) => Mnemonic::Rla,
+        ("rra", []) => Mnemonic::Rra,
+        ("reti", []) => Mnemonic::Reti,
+
+        ("ld", [R8(r1), R8(r2)]) => Mnemonic::LdReg8Reg8(*r1, *r2),
+        ("ld", [R8(r), Mem(a)]) => Mnemonic::LdReg8Addr(*r, *a),
+        ("ld", [Mem(a), R8(r)]) => Mnemonic::LdAddrReg8(*a, *r),
+        ("ld", [R8(r), Num(n)]) => Mnemonic::LdReg8Imm(*r, Imm::Known(to_u8(*n)?)),
+        ("ld", [Mem(a), Num(n)]) if *a == hl => Mnemonic::LdHlAddrImm(Imm::Known(to_u8(*n)?)),
+        ("ld", [R16(r), Num(n)]) if *r != Reg16::AF => {
+            Mnemonic::LdReg16Imm(*r, Imm::Known(to_u16(*n)?))
+        }
+        ("ld", [Mem(Addr::Imm16(i)), R16(Reg16::SP)]) => Mnemonic::LdImmAddrSp(*i),
+        ("ld", [R16(Reg16::SP), R16(Reg16::HL)]) => Mnemonic::LdSpHl,
+        ("ld", [R16(Reg16::HL), SpOffset(e)]) => Mnemonic::LdHlAddSpImm(Imm::Known(to_i8(*e)?)),
+        ("ldh", [Mem(a), R8(Reg8::A)]) => Mnemonic::LdAddrReg8(high_addr(a)?, Reg8::A),
+        ("ldh", [R8(Reg8::A), Mem(a)]) => Mnemonic::LdReg8Addr(Reg8::A, high_addr(a)?),
+        ("ldi", [Mem(a), R8(Reg8::A)]) if *a == hl => {
+            Mnemonic::LdAddrReg8(Addr::Reg16Inc(Reg16::HL), Reg8::A)
+        }
+        ("ldi", [R8(Reg8::A), Mem(a)]) if *a == hl => {
+            Mnemonic::LdReg8Addr(Reg8::A, Addr::Reg16Inc(Reg16::HL))
+        }
+        ("ldd", [Mem(a), R8(Reg8::A)]) if *a == hl => {
+            Mnemonic::LdAddrReg8(Addr::Reg16Dec(Reg16::HL), Reg8::A)
+        }
+        ("ldd", [R8(Reg8::A), Mem(a)]) if *a == hl => {
+            Mnemonic::LdReg8Addr(Reg8::A, Addr::Reg16Dec(Reg16::HL))
+        }
+        ("push", [R16(r)]) if *r != Reg16::SP => Mnemonic::Push(*r),
+        ("pop", [R16(r)]) if *r != Reg16::SP => Mnemonic::Pop(*r),
+
+        ("inc", [R8(r)]) => Mnemonic::IncReg8(*r),
+        ("inc", [Mem(a)]) if *a == hl => Mnemonic::IncHlAddr,
+        ("inc", [R16(r)]) if *r != Reg16::AF => Mnemonic::IncReg16(*r),
+        ("dec", [R8(r)]) => Mnemonic::DecReg8(*r),
+        ("dec", [Mem(a)]) if *a == hl => Mnemonic::DecHlAddr,
+        ("dec", [R16(r)]) if *r != Reg16::AF => Mnemonic::DecReg16(*r),
+        ("add", [R16(Reg16::HL), R16(r)]) if *r != Reg16::AF => Mnemonic::AddHlReg16(*r),
+        ("add", [R16(Reg16::SP), Num(e)]) => Mnemonic::AddSpImm(Imm::Known(to_i8(*e)?)),
+        (
+            "add" | "adc" | "sub" | "sbc" | "and" | "xor" | "or" | "cp",
+            [R8(Reg8::A), src] | [src],
+        ) => parse_alu(name, src)?,
+
+        ("rlc" | "rrc" | "rl" | "rr" | "sla" | "sra" | "swap" | "srl", [R8(r)]) => match name {
+            "rlc" => Mnemonic::RlcReg8(*r),
+            "rrc" => Mnemonic::RrcReg8(*r),
+            "rl" => Mnemonic::RlReg8(*r),
+            "rr" => Mnemonic::RrReg8(*r),
+            "sla" => Mnemonic::SlaReg8(*r),
+            "sra" => Mnemonic::SraReg8(*r),
+            "swap" => Mnemonic::SwapReg8(*r),
+            _ => Mnemonic::SrlReg8(*r),
+        },
+        ("rlc" | "rrc" | "rl" | "rr" | "sla" | "sra" | "swap" | "srl", [Mem(a)]) if *a == hl => {
+            match name {
+                "rlc" => Mnemonic::RlcHlAddr,
+                "rrc" => Mnemonic::RrcHlAddr,
+                "rl" => Mnemonic::RlHlAddr,
+                "rr" => Mnemonic::RrHlAddr,
+                "sla" => Mnemonic::SlaHlAddr,
+                "sra" => Mnemonic::SraHlAddr,
+                "swap" => Mnemonic::SwapHlAddr,
+                _ => Mnemonic::SrlHlAddr,
+            }
+        }
+        ("bit" | "res" | "set", [Num(b), target]) => {
+            if !(0..8).contains(b) {
+                return Err(format!("Invalid bit index: {}", b));
+            }
+            let b = Imm::Known(*b as u8);
+            match (name, target) {
+                ("bit", R8(r)) => Mnemonic::BitReg8(b, *r),
+                ("res", R8(r)) => Mnemonic::ResReg8(b, *r),
+                ("set", R8(r)) => Mnemonic::SetReg8(b, *r),
+                ("bit", Mem(a)) if *a == hl => Mnemonic::BitHlAddr(b),
+                ("res", Mem(a)) if *a == hl => Mnemonic::ResHlAddr(b),
+                ("set", Mem(a)) if *a == hl => Mnemonic::SetHlAddr(b),
+                _ => return Err("Invalid operands".to_string()),
+            }
+        }
+        _ => return Err("Unknown instruction or invalid operands".to_string()),
+    };
+    Ok(mnemonic)
+}
+
+/// Encodes `mnemonic` by looking up the opcode whose table entry results in the same mnemonic
+/// once its immediate is filled in. Returns `None` if no such opcode exists.
+pub fn encode(mnemonic: &Mnemonic) -> Option<Vec<u8>> {
+    let imm = immediate(mnemonic);
+    let matches = |info: &InstrInfo| {
+        let len = match info.param_type() {
+            ParamType::None => 0,
+            ParamType::Byte => 1,
+            ParamType::Word => 2,
+        };
+        len == imm.len() && info.mnemonic().with_immediate(&imm) == *mnemonic
+    };
+
+    if let Some(opcode) = BASE_INSTR_INFO
+        .iter()
+        .position(|info| *info.mnemonic() != Mnemonic::Invalid && matches(info))
+    {
+        let mut bytes = vec![opcode as u8];
+        bytes.extend(imm);
+        return Some(bytes);
+    }
+    BITWISE_INSTR_INFO
+        .iter()
+        .position(matches)
+        .map(|opcode| vec![BITWISE_PREFIX, opcode as u8])
+}
+
+/// Returns the bytes following the opcode of `mnemonic`.
+/// This is the inverse of [`HasImmediate::with_immediate`].
+fn immediate(mnemonic: &Mnemonic) -> Vec<u8> {
+    let addr_bytes = |a: &Addr| match a {
+        Addr::Imm8(Imm::Known(i)) => vec![*i],
+        Addr::Imm16(Imm::Known(i)) => i.to_le_bytes().to_vec(),
+        _ => vec![],
+    };
+    match mnemonic {
+        // The byte after `STOP` is ignored, but is part of the instruction.
+        Mnemonic::Stop => vec![0x00],
+        Mnemonic::LdReg8Addr(_, a) | Mnemonic::LdAddrReg8(a, _) => addr_bytes(a),
+        Mnemonic::LdReg8Imm(_, Imm::Known(i))
+        | Mnemonic::LdHlAddrImm(Imm::Known(i))
+        | Mnemonic::AddAImm(Imm::Known(i))
+        | Mnemonic::AdcAImm(Imm::Known(i))
+        | Mnemonic::SubAImm(Imm::Known(i))
+        | Mnemonic::SbcAImm(Imm::Known(i))
+        | Mnemonic::AndAImm(Imm::Known(i))
+        | Mnemonic::XorAImm(Imm::Known(i))
+        | Mnemonic::OrAImm(Imm::Known(i))
+        | Mnemonic::CpAImm(Imm::Known(i)) => vec![*i],
+        Mnemonic::LdReg16Imm(_, Imm::Known(i))
+        | Mnemonic::LdImmAddrSp(Imm::Known(i))
+        | Mnemonic::Jp(Imm::Known(i))
+        | Mnemonic::JpCond(_, Imm::Known(i))
+        | Mnemonic::Call(Imm::Known(i))
+        | Mnemonic::CallCond(_, Imm::Known(i)) => i.to_le_bytes().to_vec(),
+        Mnemonic::Jr(Imm::Known(i))
+        | Mnemonic::JrCond(_, Imm::Known(i))
+        | Mnemonic::AddSpImm(Imm::Known(i))
+        | Mnemonic::LdHlAddSpImm(Imm::Known(i)) => vec![*i as u8],
+        _ => vec![],
+    }
+}
+
+/// Parses the branch instructions, whose first operand may be a condition.
+fn parse_branch(name: &str, operands: &[&str], addr: u16) -> Result<Mnemonic, String> {
+    let (cond, operands) = match operands {
+        [first, rest @ ..] if (name == "ret" && rest.is_empty()) || rest.len() == 1 => {
+            match parse_cond(first) {
+                Some(cond) => (Some(cond), rest),
+                None if name == "ret" => return Err(format!("Invalid condition: {}", first)),
+                None => (None, operands),
+            }
+        }
+        _ => (None, operands),
+    };
+    let target = match operands {
+        [] if name == "ret" => return Ok(cond.map_or(Mnemonic::Ret, Mnemonic::RetCond)),
+        [target] if name != "ret" => *target,
+        _ => return Err("Invalid operands".to_string()),
+    };
+
+    if name == "jp" && cond.is_none() && matches!(target, "hl" | "[hl]" | "(hl)") {
+        return Ok(Mnemonic::JpHl);
+    }
+    let target = parse_num(target, addr)?;
+    Ok(match name {
+        "jr" => {
+            // The offset is relative to the address after the 2-byte instruction.
+            let offset = target
+                .checked_sub(addr as i32 + 2)
+                .and_then(|offset| i8::try_from(offset).ok())
+                .ok_or_else(|| format!("Jump target {:#06x} is out of range", target))?;
+            match cond {
+                Some(cond) => Mnemonic::JrCond(cond, Imm::Known(offset)),
+                None => Mnemonic::Jr(Imm::Known(offset)),
+            }
+        }
+        "jp" => match cond {
+            Some(cond) => Mnemonic::JpCond(cond, Imm::Known(to_u16(target)?)),
+            None => Mnemonic::Jp(Imm::Known(to_u16(target)?)),
+        },
+        _ => match cond {
+            Some(cond) => Mnemonic::CallCond(cond, Imm::Known(to_u16(target)?)),
+            None => Mnemonic::Call(Imm::Known(to_u16(target)?)),
+        },
+    })
+}
+
+/// Parses the 8-bit arithmetic and logic instructions, which all take `A` as their destination.
+fn parse_alu(name: &str, src: &Operand) -> Result<Mnemonic, String> {
+    Ok(match src {
+        Operand::R8(r) => match name {
+            "add" => Mnemonic::AddAReg8(*r),
+            "adc" => Mnemonic::AdcAReg8(*r),
+            "sub" => Mnemonic::SubAReg8(*r),
+            "sbc" => Mnemonic::SbcAReg8(*r),
+            "and" => Mnemonic::AndAReg8(*r),
+            "xor" => Mnemonic::XorAReg8(*r),
+            "or" => Mnemonic::OrAReg8(*r),
+            _ => Mnemonic::CpAReg8(*r),
+        },
+        Operand::Mem(Addr::Reg16(Reg16::HL)) => match name {
+            "add" => Mnemonic::AddAHlAddr,
+            "adc" => Mnemonic::AdcAHlAddr,
+            "sub" => Mnemonic::SubAHlAddr,
+            "sbc" => Mnemonic::SbcAHlAddr,
+            "and" => Mnemonic::AndAHlAddr,
+            "xor" => Mnemonic::XorAHlAddr,
+            "or" => Mnemonic::OrAHlAddr,
+            _ => Mnemonic::CpAHlAddr,
+        },
+        Operand::Num(n) => {
+            let i = Imm::Known(to_u8(*n)?);
+            match name {
+                "add" => Mnemonic::AddAImm(i),
+                "adc" => Mnemonic::AdcAImm(i),
+                "sub" => Mnemonic::SubAImm(i),
+                "sbc" => Mnemonic::SbcAImm(i),
+                "and" => Mnemonic::AndAImm(i),
+                "xor" => Mnemonic::XorAImm(i),
+                "or" => Mnemonic::OrAImm(i),
+                _ => Mnemonic::CpAImm(i),
+            }
+        }
+        _ => return Err("Invalid operands".to_string()),
+    })
+}
+
+fn parse_operand(op: &str, addr: u16) -> Result<Operand, String> {
+    if let Some(r) = parse_reg8(op) {
+        return Ok(Operand::R8(r));
+    }
+    if let Some(r) = parse_reg16(op) {
+        return Ok(Operand::R16(r));
+    }
+    if let Some(inner) = op
+        .strip_prefix('[')
+        .and_then(|op| op.strip_suffix(']'))
+        .or_else(|| op.strip_prefix('(').and_then(|op| op.strip_suffix(')')))
+    {
+        return parse_addr(inner.trim(), addr).map(Operand::Mem);
+    }
+    if let Some(offset) = op.strip_prefix("sp") {
+        return parse_num(offset.trim(), addr).map(Operand::SpOffset);
+    }
+    parse_num(op, addr).map(Operand::Num)
+}
+
+/// Parses the inside of a memory operand.
+fn parse_addr(inner: &str, addr: u16) -> Result<Addr, String> {
+    let inner = inner.replace(' ', "");
+    Ok(match inner.as_str() {
+        "bc" => Addr::Reg16(Reg16::BC),
+        "de" => Addr::Reg16(Reg16::DE),
+        "hl" => Addr::Reg16(Reg16::HL),
+        "hl+" | "hli" => Addr::Reg16Inc(Reg16::HL),
+        "hl-" | "hld" => Addr::Reg16Dec(Reg16::HL),
+        "c" | "$ff00+c" | "0xff00+c" | "ff00+c" => Addr::Reg8(Reg8::C),
+        expr => match parse_num(expr, addr)? {
+            n @ 0..=0xffff => Addr::Imm16(Imm::Known(n as u16)),
+            n => return Err(format!("Address out of range: {}", n)),
+        },
+    })
+}
+
+/// Converts a memory operand of `ldh` to its `$ff00`-relative form.
+fn high_addr(a: &Addr) -> Result<Addr, String> {
+    match a {
+        Addr::Reg8(Reg8::C) | Addr::Imm8(_) => Ok(*a),
+        Addr::Imm16(Imm::Known(i)) if *i >= 0xff00 => Ok(Addr::Imm8(Imm::Known(*i as u8))),
+        Addr::Imm16(Imm::Known(i)) if *i <= 0xff => Ok(Addr::Imm8(Imm::Known(*i as u8))),
+        _ => Err("Invalid high memory address".to_string()),
+    }
+}
+
+/// Evaluates a sum of numbers, where a lone `$` or `@` is the value of `addr`.
+fn parse_num(expr: &str, addr: u16) -> Result<i32, String> {
+    let expr = expr.replace(' ', "");
+    if expr.is_empty() {
+        return Err("Expected a number".to_string());
+    }
+    let mut total = 0i32;
+    let mut rest = expr.as_str();
+    let mut sign = 1;
+    if let Some(r) = rest.strip_prefix('+') {
+        rest = r;
+    } else if let Some(r) = rest.strip_prefix('-') {
+        rest = r;
+        sign = -1;
+    }
+    loop {
+        // `$` is both the hex prefix and the current address, so only split on
+        // operators that follow the first character of a term.
+        let end = rest
+            .char_indices()
+            .skip(1)
+            .find(|(_, c)| *c == '+' || *c == '-')
+            .map_or(rest.len(), |(i, _)| i);
+        let (term, tail) = rest.split_at(end);
+        let val = match term {
+            "$" | "@" => addr as i32,
+            _ => parse_term(term).ok_or_else(|| format!("Invalid number: {}", term))?,
+        };
+        total = val
+            .checked_mul(sign)
+            .and_then(|val| total.checked_add(val))
+            .ok_or_else(|| format!("Number out of range: {}", expr))?;
+        match tail.chars().next() {
+            Some('+') => sign = 1,
+            Some('-') => sign = -1,
+            _ => return Ok(total),
+        }
+        rest = &tail[1..];
+    }
+}
+
+fn parse_term(term: &str) -> Option<i32> {
+    if let Some(hex) = term.strip_prefix('$').or_else(|| term.strip_prefix("0x")) {
+        i32::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = term.strip_prefix('%').or_else(|| term.strip_prefix("0b")) {
+        i32::from_str_radix(bin, 2).ok()
+    } else {
+        term.parse::<i32>().ok()
+    }
+}
+
+fn parse_reg8(op: &str) -> Option<Reg8> {
+    Some(match op {
+        "a" => Reg8::A,
+        "b" => Reg8::B,
+        "c" => Reg8::C,
+        "d" => Reg8::D,
+        "e" => Reg8::E,
+        "h" => Reg8::H,
+        "l" => Reg8::L,
+        _ => return None,
+    })
+}
+
+fn parse_reg16(op: &str) -> Option<Reg16> {
+    Some(match op {
+        "af" => Reg16::AF,
+        "bc" => Reg16::BC,
+        "de" => Reg16::DE,
+        "hl" => Reg16::HL,
+        "sp" => Reg16::SP,
+        _ => return None,
+    })
+}
+
+fn parse_cond(op: &str) -> Option<Cond> {
+    Some(match op {
+        "z" => Cond::Z,
+        "nz" => Cond::NZ,
+        "c" => Cond::C,
+        "nc" => Cond::NC,
+        _ => return None,
+    })
+}
+
+fn to_u8(n: i32) -> Result<u8, String> {
+    match n {
+        -0x80..=0xff => Ok(n as u8),
+        _ => Err(format!("Value does not fit in a byte: {}", n)),
+    }
+}
+
+fn to_i8(n: i32) -> Result<i8, String> {
+    i8::try_from(n).map_err(|_| format!("Value does not fit in a signed byte: {}", n))
+}
+
+fn to_u16(n: i32) -> Result<u16, String> {
+    match n {
+        -0x8000..=0xffff => Ok(n as u16),
+        _ => Err(format!("Value does not fit in a word: {}", n)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::instructions::debug::{decode, disasm};
+
+    /// The address the instructions are assembled at.
+    const ADDR: u16 = 0xc000;
+
+    /// Checks that every opcode, decoded with `imm` as its immediate and formatted in RGBDS
+    /// syntax, parses into the same mnemonic and encodes back into the same bytes.
+    fn round_trip(imm: [u8; 2]) {
+        let base = (0..=0xff)
+            .filter(|opcode| *opcode != BITWISE_PREFIX)
+            .filter(|opcode| *BASE_INSTR_INFO[*opcode as usize].mnemonic() != Mnemonic::Invalid)
+            .map(|opcode| [opcode, imm[0], imm[1]]);
+        let bitwise = (0..=0xff).map(|opcode| [BITWISE_PREFIX, opcode, 0]);
+        for bytes in base.chain(bitwise) {
+            let (len, mnemonic) = decode(bytes);
+            let src = disasm::source(&mnemonic, None).unwrap();
+            let parsed = parse(&src, ADDR).unwrap_or_else(|e| panic!("{}: {}", src, e));
+            assert!(parsed == mnemonic, "{} parsed as {}", src, parsed);
+            // The byte after `STOP` is part of the instruction, but not decoded.
+            let bytes = match mnemonic {
+                Mnemonic::Stop => vec![bytes[0], 0x00],
+                _ => bytes[..len].to_vec(),
+            };
+            assert_eq!(encode(&mnemonic), Some(bytes.clone()), "{}", src);
+            assert_eq!(assemble(&src, ADDR), Ok(bytes), "{}", src);
+        }
+    }
+
+    #[test]
+    fn round_trip_positive_immediates() {
+        round_trip([0x34, 0x12]);
+    }
+
+    #[test]
+    fn round_trip_negative_immediates() {
+        round_trip([0xfe, 0xff]);
+    }
+
+    #[test]
+    fn relative_jump_range() {
+        assert_eq!(assemble("jr $c081", ADDR), Ok(vec![0x18, 0x7f]));
+        assert_eq!(assemble("jr nz, $bf82", ADDR), Ok(vec![0x20, 0x80]));
+        assert_eq!(assemble("jr @-1", ADDR), Ok(vec![0x18, 0xfd]));
+        for src in ["jr $c082", "jr $bf81", "jr c, $0000"] {
+            let err = assemble(src, ADDR).unwrap_err();
+            assert!(err.contains("out of range"), "{}: {}", src, err);
+        }
+    }
+
+    #[test]
+    fn overflowing_numbers() {
+        for src in [
+            "jr $7fffffff+1",
+            "jr -$7fffffff-1",
+            "ld a, 2147483647+2147483647",
+            "ld a, -2147483647-2",
+            "jp @+$7fffffff",
+        ] {
+            assert!(assemble(src, ADDR).is_err(), "{}", src);
+        }
+    }
+}
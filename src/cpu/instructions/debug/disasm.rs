@@ -0,0 +1,471 @@
+use crate::cpu::{
+    cdl::CodeDataLog,
+    instructions::debug::{
+        self, asm, symbols::SymbolTable, Addr, Cond, Imm, Mnemonic, Reg16, Reg8,
+    },
+};
+use std::collections::{BTreeMap, HashMap};
+
+/// The size of a single ROM bank.
+const BANK_SIZE: usize = 0x4000;
+
+/// The maximum number of bytes in a single `db` line.
+const BYTES_PER_LINE: usize = 16;
+
+/// The addresses at which the CPU starts executing code by itself, and their labels.
+const ENTRY_POINTS: [(u16, &str); 14] = [
+    (0x0000, "RST_00"),
+    (0x0008, "RST_08"),
+    (0x0010, "RST_10"),
+    (0x0018, "RST_18"),
+    (0x0020, "RST_20"),
+    (0x0028, "RST_28"),
+    (0x0030, "RST_30"),
+    (0x0038, "RST_38"),
+    (0x0040, "VBlankInterrupt"),
+    (0x0048, "LCDCInterrupt"),
+    (0x0050, "TimerOverflowInterrupt"),
+    (0x0058, "SerialTransferCompleteInterrupt"),
+    (0x0060, "JoypadTransitionInterrupt"),
+    (0x0100, "Boot"),
+];
+
+/// What a byte in the ROM was found to be.
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    /// Not reached by any code path, so treated as data.
+    Data,
+    /// The first byte of an instruction with the given length.
+    Instr(usize),
+    /// One of the other bytes of an instruction.
+    Operand,
+}
+
+/// How the code at an address is reached, which decides its label.
+/// Ordered by priority, so that e.g. a function that is also jumped to is labeled as a call.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Target {
+    Jr,
+    Jump,
+    Call,
+}
+
+/// A static disassembly of a whole cartridge ROM.
+/// Code is found by following all code paths from the entry point and the interrupt vectors,
+/// as well as from every opcode in a Code/Data Log, and everything else is treated as data.
+pub struct Disassembly<'a> {
+    rom: &'a [u8],
+    kinds: Vec<Kind>,
+    labels: BTreeMap<usize, String>,
+    targets: BTreeMap<usize, Target>,
+    /// The ROM offset targeted by each branch instruction, by the offset of the instruction.
+    branches: HashMap<usize, usize>,
+}
+
+impl<'a> Disassembly<'a> {
+    /// Disassembles `rom`, which should consist of whole ROM banks.
+    /// Code at addresses in `symbols` is labeled with those names instead of generated ones.
+    /// Code that was executed according to `cdl` is disassembled even if it is only reached
+    /// through e.g. jump tables.
+    pub fn new(rom: &'a [u8], symbols: &SymbolTable, cdl: Option<&CodeDataLog>) -> Self {
+        let mut disasm = Self {
+            rom,
+            kinds: vec![Kind::Data; rom.len()],
+            labels: BTreeMap::new(),
+            targets: BTreeMap::new(),
+            branches: HashMap::new(),
+        };
+        for (addr, name) in ENTRY_POINTS {
+            disasm.labels.insert(addr as usize, name.to_string());
+        }
+        for (bank, addr, label) in symbols.iter() {
+            let offset = match (bank, addr) {
+                (0, 0x0000..=0x3fff) => addr as usize,
+                (1.., 0x4000..=0x7fff) => bank as usize * BANK_SIZE + (addr as usize - BANK_SIZE),
+                _ => continue,
+            };
+            disasm.labels.insert(offset, label.to_string());
+        }
+
+        let mut queue = ENTRY_POINTS
+            .iter()
+            .map(|(addr, _)| (1, *addr))
+            .collect::<Vec<(usize, u16)>>();
+        if let Some(cdl) = cdl {
+            // Popped last, so that statically found code takes precedence.
+            queue.splice(0..0, cdl.opcodes().map(|(bank, addr)| (bank.max(1), addr)));
+        }
+        while let Some((bank, addr)) = queue.pop() {
+            disasm.trace(bank, addr, &mut queue);
+        }
+
+        for (&offset, target) in &disasm.targets {
+            disasm.labels.entry(offset).or_insert_with(|| {
+                let (bank, addr) = location(offset);
+                let prefix = match target {
+                    Target::Jr => "jr",
+                    Target::Jump => "Jump",
+                    Target::Call => "Call",
+                };
+                format!("{}_{:03x}_{:04x}", prefix, bank, addr)
+            });
+        }
+        disasm
+            .labels
+            .retain(|offset, _| matches!(disasm.kinds.get(*offset), Some(Kind::Instr(_))));
+        disasm
+    }
+
+    /// Returns the RGBDS source files making up the disassembly, as pairs of file names and
+    /// contents. `game.asm` includes all other files, and can be reassembled into a
+    /// byte-identical ROM with `rgbasm -o game.o game.asm && rgblink -o game.gb game.o`.
+    pub fn files(&self) -> Vec<(String, String)> {
+        let banks = self.rom.len().div_ceil(BANK_SIZE);
+        let mut main = String::from(
+            "; Reassemble with:\n;   rgbasm -o game.o game.asm\n;   rgblink -o game.gb game.o\n\n",
+        );
+        let mut files = Vec::with_capacity(banks + 1);
+        for bank in 0..banks {
+            let name = format!("bank_{:03x}.asm", bank);
+            main.push_str(&format!("INCLUDE \"{}\"\n", name));
+            files.push((name, self.bank_source(bank)));
+        }
+        files.insert(0, ("game.asm".to_string(), main));
+        files
+    }
+
+    /// Follows the code path starting at `addr`, with `bank` mapped to `0x4000 - 0x7fff`.
+    /// Branch targets are added to `queue`.
+    fn trace(&mut self, mut bank: usize, mut addr: u16, queue: &mut Vec<(usize, u16)>) {
+        // The value last loaded into `A`, used to follow bank switches.
+        let mut a = None;
+        loop {
+            let Some(offset) = self.offset(bank, addr) else {
+                return;
+            };
+            let Some((len, mnemonic)) = self.decode(offset) else {
+                return;
+            };
+            if mnemonic == Mnemonic::Invalid
+                || self.kinds[offset..offset + len]
+                    .iter()
+                    .any(|kind| *kind != Kind::Data)
+            {
+                return;
+            }
+            self.kinds[offset] = Kind::Instr(len);
+            self.kinds[offset + 1..offset + len].fill(Kind::Operand);
+
+            let next = addr.wrapping_add(len as u16);
+            let mut branch = |target: u16, kind: Target| {
+                if let Some(target_offset) = self.offset(bank, target) {
+                    self.branches.insert(offset, target_offset);
+                    let entry = self.targets.entry(target_offset).or_insert(kind);
+                    *entry = kind.max(*entry);
+                    queue.push((bank, target));
+                }
+            };
+            match mnemonic {
+                Mnemonic::Jp(Imm::Known(target)) => return branch(target, Target::Jump),
+                Mnemonic::Jr(Imm::Known(e)) => {
+                    return branch(next.wrapping_add(e as u16), Target::Jr);
+                }
+                Mnemonic::JpCond(_, Imm::Known(target)) => branch(target, Target::Jump),
+                Mnemonic::JrCond(_, Imm::Known(e)) => {
+                    branch(next.wrapping_add(e as u16), Target::Jr)
+                }
+                Mnemonic::Call(Imm::Known(target)) | Mnemonic::CallCond(_, Imm::Known(target)) => {
+                    branch(target, Target::Call)
+                }
+                Mnemonic::Rst(Imm::Known(target)) => branch(target as u16, Target::Call),
+                Mnemonic::Ret | Mnemonic::Reti | Mnemonic::JpHl => return,
+                _ => (),
+            }
+
+            // Writes to `0x2000 - 0x3fff` select the ROM bank on most MBCs.
+            a = match mnemonic {
+                Mnemonic::LdReg8Imm(Reg8::A, Imm::Known(val)) => Some(val as usize),
+                Mnemonic::LdAddrReg8(Addr::Imm16(Imm::Known(0x2000..=0x3fff)), Reg8::A) => {
+                    if let Some(val) = a {
+                        bank = (val % self.banks()).max(1);
+                    }
+                    a
+                }
+                _ => None,
+            };
+            addr = next;
+        }
+    }
+
+    /// Decodes the instruction at `offset`, if it fits within its ROM bank.
+    fn decode(&self, offset: usize) -> Option<(usize, Mnemonic)> {
+        let bytes = [0, 1, 2].map(|i| self.rom.get(offset + i).copied().unwrap_or(0));
+        let (len, mnemonic) = debug::decode(bytes);
+        let bank_end = (offset / BANK_SIZE + 1) * BANK_SIZE;
+        (offset + len <= bank_end.min(self.rom.len())).then_some((len, mnemonic))
+    }
+
+    /// Generates the source of a single ROM bank.
+    fn bank_source(&self, bank: usize) -> String {
+        let mut src = match bank {
+            0 => "SECTION \"ROM Bank $000\", ROM0[$0000]\n".to_string(),
+            _ => format!(
+                "SECTION \"ROM Bank ${:03x}\", ROMX[$4000], BANK[${:x}]\n",
+                bank, bank
+            ),
+        };
+        let start = bank * BANK_SIZE;
+        let end = (start + BANK_SIZE).min(self.rom.len());
+        let mut offset = start;
+        while offset < end {
+            if let Kind::Instr(len) = self.kinds[offset] {
+                if let Some(label) = self.labels.get(&offset) {
+                    src.push_str(&format!("\n{}:\n", label));
+                }
+                src.push_str(&format!("    {}\n", self.instr_source(offset, len)));
+                offset += len;
+            } else {
+                let data_end = (offset..end)
+                    .find(|i| matches!(self.kinds[*i], Kind::Instr(_)))
+                    .unwrap_or(end)
+                    .min(offset + BYTES_PER_LINE);
+                src.push_str(&format!("    {}\n", db(&self.rom[offset..data_end])));
+                offset = data_end;
+            }
+        }
+        src
+    }
+
+    /// Generates the source of the instruction of length `len` at `offset`.
+    /// Falls back to `db` if RGBDS would not assemble the instruction back into the same bytes.
+    fn instr_source(&self, offset: usize, len: usize) -> String {
+        let bytes = &self.rom[offset..offset + len];
+        let (_, mnemonic) = debug::decode([0, 1, 2].map(|i| bytes.get(i).copied().unwrap_or(0)));
+        let src = self.rgbds(offset, &mnemonic);
+        let high_page = |a: &Addr| matches!(a, Addr::Imm16(Imm::Known(0xff00..=0xffff)));
+        let round_trips = asm::encode(&mnemonic).is_some_and(|encoded| encoded == bytes)
+            && match mnemonic {
+                // RGBDS may optimize these into `ldh`.
+                Mnemonic::LdAddrReg8(a, _) | Mnemonic::LdReg8Addr(_, a) => !high_page(&a),
+                _ => true,
+            };
+        match round_trips {
+            true => src,
+            false => format!("{} ; {}", db(bytes), src),
+        }
+    }
+
+    /// Formats `mnemonic`, located at `offset`, in RGBDS syntax.
+    fn rgbds(&self, offset: usize, mnemonic: &Mnemonic) -> String {
+        // Relative jumps can only use a label within the same section.
+        let label = self
+            .branches
+            .get(&offset)
+            .filter(|t| {
+                !matches!(mnemonic, Mnemonic::Jr(_) | Mnemonic::JrCond(..))
+                    || *t / BANK_SIZE == offset / BANK_SIZE
+            })
+            .and_then(|t| self.labels.get(t));
+        source(mnemonic, label.map(String::as_str))
+            .unwrap_or_else(|| db(&self.rom[offset..offset + 1]))
+    }
+
+    /// Translates `addr` to an offset into the ROM, with `bank` mapped to `0x4000 - 0x7fff`.
+    fn offset(&self, bank: usize, addr: u16) -> Option<usize> {
+        let offset = match addr {
+            0x0000..=0x3fff => addr as usize,
+            0x4000..=0x7fff => bank * BANK_SIZE + (addr as usize - BANK_SIZE),
+            _ => return None,
+        };
+        (offset < self.rom.len()).then_some(offset)
+    }
+
+    fn banks(&self) -> usize {
+        self.rom.len().div_ceil(BANK_SIZE).max(1)
+    }
+}
+
+/// Formats `mnemonic` in RGBDS syntax, with branches targeting `label` if given, or else their
+/// address. Relative jumps are written relative to the current address, like `jr @+5`.
+/// Returns `None` for invalid opcodes, which RGBDS can't assemble.
+pub(crate) fn source(mnemonic: &Mnemonic, label: Option<&str>) -> Option<String> {
+    let target = |addr: u16| match label {
+        Some(label) => label.to_string(),
+        None => format!("${:04x}", addr),
+    };
+    let jr_target = |e: &Imm<i8>| match (label, e) {
+        (Some(label), _) => label.to_string(),
+        (None, Imm::Known(e)) => format!("@{:+}", *e as i32 + 2),
+        (None, Imm::Unknown) => "@".to_string(),
+    };
+    let hl = "[hl]";
+    let (name, operands) = match mnemonic {
+        Mnemonic::Nop => ("nop", vec![]),
+        Mnemonic::Stop => ("stop", vec![]),
+        Mnemonic::Halt => ("halt", vec![]),
+        Mnemonic::Di => ("di", vec![]),
+        Mnemonic::Ei => ("ei", vec![]),
+        Mnemonic::LdReg8Reg8(r1, r2) => ("ld", vec![reg8(r1), reg8(r2)]),
+        Mnemonic::LdReg8Addr(r, a) => (ld_name(a), vec![reg8(r), addr(a)]),
+        Mnemonic::LdAddrReg8(a, r) => (ld_name(a), vec![addr(a), reg8(r)]),
+        Mnemonic::LdReg8Imm(r, i) => ("ld", vec![reg8(r), byte(i)]),
+        Mnemonic::LdHlAddrImm(i) => ("ld", vec![hl.into(), byte(i)]),
+        Mnemonic::LdReg16Imm(r, i) => ("ld", vec![reg16(r), word(i)]),
+        Mnemonic::LdImmAddrSp(i) => ("ld", vec![format!("[{}]", word(i)), "sp".into()]),
+        Mnemonic::LdSpHl => ("ld", vec!["sp".into(), "hl".into()]),
+        Mnemonic::Push(r) => ("push", vec![reg16(r)]),
+        Mnemonic::Pop(r) => ("pop", vec![reg16(r)]),
+        Mnemonic::Jr(e) => ("jr", vec![jr_target(e)]),
+        Mnemonic::JrCond(c, e) => ("jr", vec![cond(c), jr_target(e)]),
+        Mnemonic::Jp(i) => ("jp", vec![target(known(i))]),
+        Mnemonic::JpCond(c, i) => ("jp", vec![cond(c), target(known(i))]),
+        Mnemonic::JpHl => ("jp", vec!["hl".into()]),
+        Mnemonic::Call(i) => ("call", vec![target(known(i))]),
+        Mnemonic::CallCond(c, i) => ("call", vec![cond(c), target(known(i))]),
+        Mnemonic::Ret => ("ret", vec![]),
+        Mnemonic::RetCond(c) => ("ret", vec![cond(c)]),
+        Mnemonic::Reti => ("reti", vec![]),
+        Mnemonic::Rst(i) => ("rst", vec![byte(i)]),
+        Mnemonic::IncReg8(r) => ("inc", vec![reg8(r)]),
+        Mnemonic::IncHlAddr => ("inc", vec![hl.into()]),
+        Mnemonic::DecReg8(r) => ("dec", vec![reg8(r)]),
+        Mnemonic::DecHlAddr => ("dec", vec![hl.into()]),
+        Mnemonic::AddAReg8(r) => ("add", vec!["a".into(), reg8(r)]),
+        Mnemonic::AddAHlAddr => ("add", vec!["a".into(), hl.into()]),
+        Mnemonic::AddAImm(i) => ("add", vec!["a".into(), byte(i)]),
+        Mnemonic::AdcAReg8(r) => ("adc", vec!["a".into(), reg8(r)]),
+        Mnemonic::AdcAHlAddr => ("adc", vec!["a".into(), hl.into()]),
+        Mnemonic::AdcAImm(i) => ("adc", vec!["a".into(), byte(i)]),
+        Mnemonic::SubAReg8(r) => ("sub", vec!["a".into(), reg8(r)]),
+        Mnemonic::SubAHlAddr => ("sub", vec!["a".into(), hl.into()]),
+        Mnemonic::SubAImm(i) => ("sub", vec!["a".into(), byte(i)]),
+        Mnemonic::SbcAReg8(r) => ("sbc", vec!["a".into(), reg8(r)]),
+        Mnemonic::SbcAHlAddr => ("sbc", vec!["a".into(), hl.into()]),
+        Mnemonic::SbcAImm(i) => ("sbc", vec!["a".into(), byte(i)]),
+        Mnemonic::AndAReg8(r) => ("and", vec!["a".into(), reg8(r)]),
+        Mnemonic::AndAHlAddr => ("and", vec!["a".into(), hl.into()]),
+        Mnemonic::AndAImm(i) => ("and", vec!["a".into(), byte(i)]),
+        Mnemonic::XorAReg8(r) => ("xor", vec!["a".into(), reg8(r)]),
+        Mnemonic::XorAHlAddr => ("xor", vec!["a".into(), hl.into()]),
+        Mnemonic::XorAImm(i) => ("xor", vec!["a".into(), byte(i)]),
+        Mnemonic::OrAReg8(r) => ("or", vec!["a".into(), reg8(r)]),
+        Mnemonic::OrAHlAddr => ("or", vec!["a".into(), hl.into()]),
+        Mnemonic::OrAImm(i) => ("or", vec!["a".into(), byte(i)]),
+        Mnemonic::CpAReg8(r) => ("cp", vec!["a".into(), reg8(r)]),
+        Mnemonic::CpAHlAddr => ("cp", vec!["a".into(), hl.into()]),
+        Mnemonic::CpAImm(i) => ("cp", vec!["a".into(), byte(i)]),
+        Mnemonic::Daa => ("daa", vec![]),
+        Mnemonic::Cpl => ("cpl", vec![]),
+        Mnemonic::Scf => ("scf", vec![]),
+        Mnemonic::Ccf => ("ccf", vec![]),
+        Mnemonic::Rlca => ("rlca", vec![]),
+        Mnemonic::Rrca => ("rrca", vec![]),
+        Mnemonic::Rla => ("rla", vec![]),
+        Mnemonic::Rra => ("rra", vec![]),
+        Mnemonic::RlcReg8(r) => ("rlc", vec![reg8(r)]),
+        Mnemonic::RlcHlAddr => ("rlc", vec![hl.into()]),
+        Mnemonic::RrcReg8(r) => ("rrc", vec![reg8(r)]),
+        Mnemonic::RrcHlAddr => ("rrc", vec![hl.into()]),
+        Mnemonic::RlReg8(r) => ("rl", vec![reg8(r)]),
+        Mnemonic::RlHlAddr => ("rl", vec![hl.into()]),
+        Mnemonic::RrReg8(r) => ("rr", vec![reg8(r)]),
+        Mnemonic::RrHlAddr => ("rr", vec![hl.into()]),
+        Mnemonic::SlaReg8(r) => ("sla", vec![reg8(r)]),
+        Mnemonic::SlaHlAddr => ("sla", vec![hl.into()]),
+        Mnemonic::SraReg8(r) => ("sra", vec![reg8(r)]),
+        Mnemonic::SraHlAddr => ("sra", vec![hl.into()]),
+        Mnemonic::SwapReg8(r) => ("swap", vec![reg8(r)]),
+        Mnemonic::SwapHlAddr => ("swap", vec![hl.into()]),
+        Mnemonic::SrlReg8(r) => ("srl", vec![reg8(r)]),
+        Mnemonic::SrlHlAddr => ("srl", vec![hl.into()]),
+        Mnemonic::BitReg8(b, r) => ("bit", vec![bit(b), reg8(r)]),
+        Mnemonic::BitHlAddr(b) => ("bit", vec![bit(b), hl.into()]),
+        Mnemonic::ResReg8(b, r) => ("res", vec![bit(b), reg8(r)]),
+        Mnemonic::ResHlAddr(b) => ("res", vec![bit(b), hl.into()]),
+        Mnemonic::SetReg8(b, r) => ("set", vec![bit(b), reg8(r)]),
+        Mnemonic::SetHlAddr(b) => ("set", vec![bit(b), hl.into()]),
+        Mnemonic::IncReg16(r) => ("inc", vec![reg16(r)]),
+        Mnemonic::DecReg16(r) => ("dec", vec![reg16(r)]),
+        Mnemonic::AddHlReg16(r) => ("add", vec!["hl".into(), reg16(r)]),
+        Mnemonic::AddSpImm(e) => ("add", vec!["sp".into(), signed(e)]),
+        Mnemonic::LdHlAddSpImm(e) => ("ld", vec!["hl".into(), format!("sp{:+}", known(e))]),
+        Mnemonic::Invalid => return None,
+    };
+    Some(match operands.is_empty() {
+        true => name.to_string(),
+        false => format!("{} {}", name, operands.join(", ")),
+    })
+}
+
+/// Translates an offset into the ROM to its bank and address.
+fn location(offset: usize) -> (usize, u16) {
+    match offset / BANK_SIZE {
+        0 => (0, offset as u16),
+        bank => (bank, (BANK_SIZE + offset % BANK_SIZE) as u16),
+    }
+}
+
+fn db(bytes: &[u8]) -> String {
+    let bytes = bytes
+        .iter()
+        .map(|byte| format!("${:02x}", byte))
+        .collect::<Vec<String>>();
+    format!("db {}", bytes.join(", "))
+}
+
+fn known<T>(i: &Imm<T>) -> T
+where
+    T: Copy + Default,
+{
+    match i {
+        Imm::Known(i) => *i,
+        Imm::Unknown => T::default(),
+    }
+}
+
+fn byte(i: &Imm<u8>) -> String {
+    format!("${:02x}", known(i))
+}
+
+fn word(i: &Imm<u16>) -> String {
+    format!("${:04x}", known(i))
+}
+
+fn signed(i: &Imm<i8>) -> String {
+    known(i).to_string()
+}
+
+fn bit(i: &Imm<u8>) -> String {
+    known(i).to_string()
+}
+
+/// `LD` is called `LDH` when accessing `0xff00 - 0xffff` with an 8-bit address.
+fn ld_name(a: &Addr) -> &'static str {
+    match a {
+        Addr::Imm8(_) | Addr::Reg8(_) => "ldh",
+        _ => "ld",
+    }
+}
+
+fn addr(a: &Addr) -> String {
+    match a {
+        Addr::Imm8(i) => format!("[$ff{:02x}]", known(i)),
+        Addr::Imm16(i) => format!("[{}]", word(i)),
+        Addr::Reg8(r) => format!("[{}]", reg8(r)),
+        Addr::Reg16(r) => format!("[{}]", reg16(r)),
+        Addr::Reg16Inc(r) => format!("[{}+]", reg16(r)),
+        Addr::Reg16Dec(r) => format!("[{}-]", reg16(r)),
+    }
+}
+
+fn reg8(r: &Reg8) -> String {
+    r.to_string().to_lowercase()
+}
+
+fn reg16(r: &Reg16) -> String {
+    r.to_string().to_lowercase()
+}
+
+fn cond(c: &Cond) -> String {
+    c.to_string().to_lowercase()
+}
@@ -0,0 +1,97 @@
+use std::collections::{BTreeMap, HashMap};
+
+/// Labels loaded from a `.sym` file, as generated by RGBDS.
+#[derive(Default)]
+pub struct SymbolTable {
+    labels: BTreeMap<(u16, u16), String>,
+    addrs: HashMap<String, (u16, u16)>,
+}
+
+impl SymbolTable {
+    /// Initializes an empty symbol table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses the contents of a `.sym` file, made up of `bank:addr label` lines.
+    /// Comments and lines in any other format are ignored.
+    pub fn parse(src: &str) -> Self {
+        let mut symbols = Self::new();
+        for line in src.lines() {
+            let line = line.split(';').next().unwrap_or_default().trim();
+            let Some((location, label)) = line.split_once(char::is_whitespace) else {
+                continue;
+            };
+            let Some((bank, addr)) = location.split_once(':') else {
+                continue;
+            };
+            let (Ok(bank), Ok(addr)) =
+                (u16::from_str_radix(bank, 16), u16::from_str_radix(addr, 16))
+            else {
+                continue;
+            };
+            symbols.insert(bank, addr, label.trim());
+        }
+        symbols
+    }
+
+    /// Adds `label` at `addr` in `bank`. Addresses keep the first label they were given.
+    pub fn insert(&mut self, bank: u16, addr: u16, label: &str) {
+        self.labels
+            .entry((addr, bank))
+            .or_insert_with(|| label.to_string());
+        self.addrs.insert(label.to_string(), (bank, addr));
+    }
+
+    /// Looks up the label at `addr`, with `rom_bank` mapped to `0x4000 - 0x7fff`.
+    /// Addresses outside of ROM match a label in any bank.
+    pub fn label(&self, rom_bank: usize, addr: u16) -> Option<&str> {
+        match addr {
+            0x0000..=0x3fff => self.labels.get(&(addr, 0)),
+            0x4000..=0x7fff => self.labels.get(&(addr, rom_bank as u16)),
+            _ => self
+                .labels
+                .range((addr, 0)..=(addr, u16::MAX))
+                .next()
+                .map(|(_, label)| label),
+        }
+        .map(String::as_str)
+    }
+
+    /// Looks up the closest non-local label at or before `addr` in the same ROM bank,
+    /// which is usually the function containing `addr`. Outside of ROM, only exact matches count.
+    pub fn function(&self, rom_bank: usize, addr: u16) -> Option<&str> {
+        let (start, bank) = match addr {
+            0x0000..=0x3fff => (0x0000, 0),
+            0x4000..=0x7fff => (0x4000, rom_bank as u16),
+            _ => return self.label(rom_bank, addr),
+        };
+        self.labels
+            .range((start, 0)..=(addr, u16::MAX))
+            .rev()
+            .find(|((_, label_bank), label)| *label_bank == bank && !label.contains('.'))
+            .map(|(_, label)| label.as_str())
+    }
+
+    /// Looks up the bank and address of `label`.
+    pub fn resolve(&self, label: &str) -> Option<(u16, u16)> {
+        self.addrs.get(label).copied()
+    }
+
+    /// Returns all labels as `(bank, addr, label)`, ordered by address.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, u16, &str)> {
+        self.labels
+            .iter()
+            .map(|((addr, bank), label)| (*bank, *addr, label.as_str()))
+    }
+
+    /// Returns the number of labelled addresses.
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Returns whether no labels are loaded.
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+}
@@ -1,68 +1,68 @@
-use crate::{
-    cpu::Cpu,
-    peripherals::{Cable, Joypad, Lcd, Speaker},
-};
+#[cfg(feature = "debug")]
+use crate::cpu::events::Event;
+use crate::cpu::{Cpu, MemoryBus};
 
-/// For invalid instructions.
-pub fn invalid() {
-    log::error!("Encountered an invalid instruction.");
-    panic!("Aborting");
+/// For invalid instructions, which lock up the CPU until the Game Boy is reset.
+/// `PC` is left at the invalid opcode.
+pub fn invalid<B>(cpu: &mut Cpu<B>, opcode: u8)
+where
+    B: MemoryBus,
+{
+    let addr = cpu.regs.pc().wrapping_sub(1);
+    log::error!(
+        "Invalid opcode {:#04x} at {:#06x}, locking up",
+        opcode,
+        addr
+    );
+    cpu.regs.set_pc(addr);
+    cpu.locked = true;
+    #[cfg(feature = "debug")]
+    cpu.bus.record_event(Event::IllegalOpcode { addr, opcode });
 }
 
 /// Jump to address `addr`.
-pub fn jp<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, addr: u16)
+pub fn jp<B>(cpu: &mut Cpu<B>, addr: u16)
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     cpu.regs.set_pc(addr);
 }
 
 /// Jump to relative address `PC + val`.
-pub fn jr<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, val: i8)
+pub fn jr<B>(cpu: &mut Cpu<B>, val: i8)
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     cpu.regs.set_pc(cpu.regs.pc().wrapping_add(val as u16));
 }
 
 /// Push `PC` to the stack, and jump to address `addr`. Takes two machine cycles.
-pub fn call<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, addr: u16)
+pub fn call<B>(cpu: &mut Cpu<B>, addr: u16)
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     cpu.stack_push(cpu.regs.pc());
+    #[cfg(feature = "debug")]
+    cpu.push_frame(addr, false);
     jp(cpu, addr);
 }
 
 /// Pop a value from the stack and jump to it. Takes two machine cycles.
-pub fn ret<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>)
+pub fn ret<B>(cpu: &mut Cpu<B>)
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
+    #[cfg(feature = "debug")]
+    cpu.pop_frame();
     let addr = cpu.stack_pop();
     jp(cpu, addr);
 }
 
 /// Calculates the binary-coded decimal of `A` right after an addition or subtraction.
 /// Flags: `Z-0C`.
-pub fn daa<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>)
+pub fn daa<B>(cpu: &mut Cpu<B>)
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     let mut res = cpu.regs.a() as u16;
     if !cpu.regs.flags().n() {
@@ -90,12 +90,9 @@ where
 
 /// Flips the bits of `A`.
 /// Flags: `-11-`.
-pub fn cpl<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>)
+pub fn cpl<B>(cpu: &mut Cpu<B>)
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     cpu.regs.flags_mut().set_n(true);
     cpu.regs.flags_mut().set_h(true);
@@ -105,12 +102,9 @@ where
 
 /// Sets the carry flag
 /// Flags: `-001`.
-pub fn scf<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>)
+pub fn scf<B>(cpu: &mut Cpu<B>)
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     cpu.regs.flags_mut().set_n(false);
     cpu.regs.flags_mut().set_h(false);
@@ -119,12 +113,9 @@ where
 
 /// Flips the carry flag
 /// Flags: `-00C`.
-pub fn ccf<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>)
+pub fn ccf<B>(cpu: &mut Cpu<B>)
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     let c = cpu.regs.flags().c();
 
@@ -135,12 +126,9 @@ where
 
 /// Returns `val + 1`.
 /// Flags: `Z0H-`.
-pub fn inc<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, val: u8) -> u8
+pub fn inc<B>(cpu: &mut Cpu<B>, val: u8) -> u8
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     let res = val.wrapping_add(1);
 
@@ -153,12 +141,9 @@ where
 
 /// Returns `val - 1`.
 /// Flags: `Z1H-`.
-pub fn dec<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, val: u8) -> u8
+pub fn dec<B>(cpu: &mut Cpu<B>, val: u8) -> u8
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     let res = val.wrapping_sub(1);
 
@@ -171,12 +156,9 @@ where
 
 /// Adds `val` to `A`.
 /// Flags: `Z0HC`.
-pub fn add<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, val: u8)
+pub fn add<B>(cpu: &mut Cpu<B>, val: u8)
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     let a = cpu.regs.a();
     let res = a as u16 + val as u16;
@@ -191,12 +173,9 @@ where
 
 /// Adds `val` to `HL`.
 /// Flags: `-0HC`.
-pub fn add_hl<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, val: u16)
+pub fn add_hl<B>(cpu: &mut Cpu<B>, val: u16)
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     let hl = cpu.regs.hl();
     let res = hl as u32 + val as u32;
@@ -212,12 +191,9 @@ where
 
 /// Returns `SP + val`.
 /// Flags: `00HC`.
-pub fn add_sp<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, val: i8) -> u16
+pub fn add_sp<B>(cpu: &mut Cpu<B>, val: i8) -> u16
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     let uval = val as u16;
     let sp = cpu.regs.sp();
@@ -237,12 +213,9 @@ where
 
 /// Adds `val` and the carry flag to `A`.
 /// Flags: `Z0HC`.
-pub fn adc<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, val: u8)
+pub fn adc<B>(cpu: &mut Cpu<B>, val: u8)
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     let a = cpu.regs.a();
     let c = cpu.regs.flags().c() as u8;
@@ -260,12 +233,9 @@ where
 
 /// Subtracts `val` from `A`.
 /// Flags: `Z1HC`.
-pub fn sub<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, val: u8)
+pub fn sub<B>(cpu: &mut Cpu<B>, val: u8)
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     let a = cpu.regs.a();
     let res = a.wrapping_sub(val);
@@ -280,12 +250,9 @@ where
 
 /// Subtracts `val` and the carry flag from `A`.
 /// Flags: `Z1HC`.
-pub fn sbc<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, val: u8)
+pub fn sbc<B>(cpu: &mut Cpu<B>, val: u8)
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     let a = cpu.regs.a();
     let c = cpu.regs.flags().c() as u8;
@@ -301,12 +268,9 @@ where
 
 /// Sets `A` to the bitwise AND of `A` and `val`.
 /// Flags: `Z010`.
-pub fn and<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, val: u8)
+pub fn and<B>(cpu: &mut Cpu<B>, val: u8)
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     let a = cpu.regs.a();
     let res = a & val;
@@ -321,12 +285,9 @@ where
 
 /// Sets `A` to the bitwise XOR of `A` and `val`.
 /// Flags: `Z000`.
-pub fn xor<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, val: u8)
+pub fn xor<B>(cpu: &mut Cpu<B>, val: u8)
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     let a = cpu.regs.a();
     let res = a ^ val;
@@ -341,12 +302,9 @@ where
 
 /// Sets `A` to the bitwise OR of `A` and `val`.
 /// Flags: `Z000`.
-pub fn or<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, val: u8)
+pub fn or<B>(cpu: &mut Cpu<B>, val: u8)
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     let a = cpu.regs.a();
     let res = a | val;
@@ -362,12 +320,9 @@ where
 /// Compares `A` and `val`, and sets flags accordingly.
 /// Flags are set as if `val` is subtracted from `A`.
 /// Flags: `Z1HC`.
-pub fn cp<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, val: u8)
+pub fn cp<B>(cpu: &mut Cpu<B>, val: u8)
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     let a = cpu.regs.a();
 
@@ -379,12 +334,9 @@ where
 
 /// Rotate `val` to the left once.
 /// Flags: `Z00C`.
-pub fn rlc<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, val: u8) -> u8
+pub fn rlc<B>(cpu: &mut Cpu<B>, val: u8) -> u8
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     let bit = val >> 7;
     let res = (val << 1) | bit;
@@ -399,12 +351,9 @@ where
 
 /// Rotate `val` to the right once.
 /// Flags: `Z00C`.
-pub fn rrc<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, val: u8) -> u8
+pub fn rrc<B>(cpu: &mut Cpu<B>, val: u8) -> u8
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     let bit = val & 0x01;
     let res = (val >> 1) | (bit << 7);
@@ -419,12 +368,9 @@ where
 
 /// Rotate `val` to the left once, through the carry flag.
 /// Flags: `Z00C`.
-pub fn rl<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, val: u8) -> u8
+pub fn rl<B>(cpu: &mut Cpu<B>, val: u8) -> u8
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     let c = cpu.regs.flags().c() as u8;
     let bit = val >> 7;
@@ -440,12 +386,9 @@ where
 
 /// Rotate `val` to the right once, through the carry flag.
 /// Flags: `Z00C`.
-pub fn rr<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, val: u8) -> u8
+pub fn rr<B>(cpu: &mut Cpu<B>, val: u8) -> u8
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     let c = cpu.regs.flags().c() as u8;
     let bit = val & 0x01;
@@ -462,12 +405,9 @@ where
 /// Shift `val` to the left once, into the carry flag.
 /// Least significant bit is set to 0.
 /// Flags: `Z00C`.
-pub fn sla<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, val: u8) -> u8
+pub fn sla<B>(cpu: &mut Cpu<B>, val: u8) -> u8
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     let bit = val >> 7;
     let res = val << 1;
@@ -483,12 +423,9 @@ where
 /// Shift `val` to the right once, into the carry flag.
 /// Most significant bit does not change.
 /// Flags: `Z00C`.
-pub fn sra<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, val: u8) -> u8
+pub fn sra<B>(cpu: &mut Cpu<B>, val: u8) -> u8
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     let bit = val & 0x01;
     let res = (val & 0x80) | (val >> 1);
@@ -503,14 +440,11 @@ where
 
 /// Swaps the upper and lower nibble of `val`.
 /// Flags: `Z000`.
-pub fn swap<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, val: u8) -> u8
+pub fn swap<B>(cpu: &mut Cpu<B>, val: u8) -> u8
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
-    let res = (val << 4) | (val >> 4);
+    let res = val.rotate_left(4);
 
     cpu.regs.flags_mut().set_z(res == 0);
     cpu.regs.flags_mut().set_n(false);
@@ -523,12 +457,9 @@ where
 /// Shift `val` to the right once, into the carry flag.
 /// Most significant bit is set to 0.
 /// Flags: `Z00C`.
-pub fn srl<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, val: u8) -> u8
+pub fn srl<B>(cpu: &mut Cpu<B>, val: u8) -> u8
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     let bit = val & 0x01;
     let res = val >> 1;
@@ -544,12 +475,9 @@ where
 /// Checks if the `bit`th bit of `val` is set,
 /// and sets the zero flag accordingly.
 /// Flags: `Z01-`.
-pub fn bit<L, S, J, C>(cpu: &mut Cpu<L, S, J, C>, bit: u8, val: u8)
+pub fn bit<B>(cpu: &mut Cpu<B>, bit: u8, val: u8)
 where
-    L: Lcd,
-    S: Speaker,
-    J: Joypad,
-    C: Cable,
+    B: MemoryBus,
 {
     cpu.regs.flags_mut().set_z((val & (1 << bit)) == 0);
     cpu.regs.flags_mut().set_n(false);
@@ -1,4 +1,5 @@
 /// Represents an interrupt IO register. An 8-bit value consisting of 5 interrupt flags.
+#[derive(Clone)]
 pub struct IntReg {
     byte: u8,
 }
@@ -79,7 +80,21 @@ impl IntReg {
     }
 }
 
+/// A snapshot of the interrupt registers, for inspection.
+/// Each register has one bit per interrupt: VBLANK, LCDSTAT, TIMER, SERIAL and JOYPAD.
+#[cfg(feature = "debug")]
+#[derive(Clone, Copy, Debug)]
+pub struct InterruptState {
+    /// The `IE` register.
+    pub enable: u8,
+    /// The `IF` register.
+    pub flags: u8,
+    /// The interrupt master enable flag of the CPU.
+    pub ime: bool,
+}
+
 /// Stores the `IF` register (`flags`) and the `IE` register (`enable`).
+#[derive(Clone)]
 pub struct InterruptControl {
     /// The `IF` register.
     pub flags: IntReg,
@@ -123,6 +138,16 @@ impl InterruptControl {
         None
     }
 
+    /// Returns a snapshot of the `IE` and `IF` registers, along with `ime`.
+    #[cfg(feature = "debug")]
+    pub fn state(&self, ime: bool) -> InterruptState {
+        InterruptState {
+            enable: self.enable.byte(),
+            flags: self.flags.byte(),
+            ime,
+        }
+    }
+
     /// Checks whether an interrupt request is pending.
     pub fn pending(&self) -> bool {
         self.flags.byte() & self.enable.byte() != 0
@@ -0,0 +1,262 @@
+use crate::cpu::instructions::debug::symbols::SymbolTable;
+use std::{collections::HashMap, fmt::Write};
+
+/// The number of addresses listed in a report.
+const REPORTED_ADDRS: usize = 32;
+
+/// The name of the code that is not inside any call.
+const ROOT: &str = "(root)";
+
+/// Execution counts for a piece of code.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Counts {
+    /// The number of instructions executed.
+    pub instructions: u64,
+    /// The number of machine cycles spent, including time spent halted and dispatching interrupts.
+    pub cycles: u64,
+}
+
+impl Counts {
+    fn add(&mut self, other: Counts) {
+        self.instructions += other.instructions;
+        self.cycles += other.cycles;
+    }
+}
+
+/// The state of the CPU right before a step.
+pub(crate) struct Sample {
+    pub bank: usize,
+    pub addr: u16,
+    pub cycles: u64,
+    pub halted: bool,
+    /// The node of the call stack at the time of the sample.
+    pub node: usize,
+}
+
+/// A call stack, stored as the innermost call and the node of the stack it was called from.
+#[derive(Clone)]
+struct StackNode {
+    parent: usize,
+    /// The bank and address of the call.
+    frame: (usize, u16),
+    depth: usize,
+    counts: Counts,
+}
+
+/// Counts executed instructions and cycles per address, and per call stack.
+/// Call stacks are interned as nodes of a tree, which follows calls and returns,
+/// so that recording a step does not need to look at the whole stack.
+#[derive(Clone)]
+pub struct Profiler {
+    addrs: HashMap<(usize, u16), Counts>,
+    /// The nodes of all call stacks seen so far. The first node is the empty stack.
+    nodes: Vec<StackNode>,
+    /// The node of each call, by the node it was called from and the call itself.
+    children: HashMap<(usize, (usize, u16)), usize>,
+    /// The node of the current call stack.
+    current: usize,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self {
+            addrs: HashMap::new(),
+            nodes: vec![StackNode {
+                parent: 0,
+                frame: (0, 0),
+                depth: 0,
+                counts: Counts::default(),
+            }],
+            children: HashMap::new(),
+            current: 0,
+        }
+    }
+}
+
+impl Profiler {
+    /// Returns the node of the current call stack.
+    pub(crate) fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Records a call to `addr` in `bank`, on top of the current call stack.
+    pub(crate) fn call(&mut self, bank: usize, addr: u16) {
+        let parent = self.current;
+        let depth = self.nodes[parent].depth + 1;
+        let nodes = &mut self.nodes;
+        self.current = *self
+            .children
+            .entry((parent, (bank, addr)))
+            .or_insert_with(|| {
+                nodes.push(StackNode {
+                    parent,
+                    frame: (bank, addr),
+                    depth,
+                    counts: Counts::default(),
+                });
+                nodes.len() - 1
+            });
+    }
+
+    /// Records returns until the call stack is `depth` calls deep.
+    pub(crate) fn ret(&mut self, depth: usize) {
+        while self.nodes[self.current].depth > depth {
+            self.current = self.nodes[self.current].parent;
+        }
+    }
+
+    /// Replaces the current call stack with `frames`, starting with the outermost call.
+    pub(crate) fn set_stack<I>(&mut self, frames: I)
+    where
+        I: IntoIterator<Item = (usize, u16)>,
+    {
+        self.current = 0;
+        frames
+            .into_iter()
+            .for_each(|(bank, addr)| self.call(bank, addr));
+    }
+
+    /// Records a step of the CPU that started at `sample`, and ended after `cycles` cycles.
+    pub(crate) fn record(&mut self, sample: Sample, cycles: u64) {
+        let counts = Counts {
+            instructions: !sample.halted as u64,
+            cycles: cycles - sample.cycles,
+        };
+        self.addrs
+            .entry((sample.bank, sample.addr))
+            .or_default()
+            .add(counts);
+        self.nodes[sample.node].counts.add(counts);
+    }
+
+    /// Returns every call stack that code was executed in, starting with the outermost call,
+    /// and the counts of the code executed in it.
+    fn stacks(&self) -> impl Iterator<Item = (Vec<(usize, u16)>, Counts)> + '_ {
+        (0..self.nodes.len())
+            .filter(|&node| self.nodes[node].counts != Counts::default())
+            .map(|node| {
+                let mut stack = Vec::with_capacity(self.nodes[node].depth);
+                let mut cur = node;
+                while cur != 0 {
+                    stack.push(self.nodes[cur].frame);
+                    cur = self.nodes[cur].parent;
+                }
+                stack.reverse();
+                (stack, self.nodes[node].counts)
+            })
+    }
+
+    /// Returns the counts of every executed address, by bank and address.
+    pub fn addrs(&self) -> &HashMap<(usize, u16), Counts> {
+        &self.addrs
+    }
+
+    /// Returns the sum of all counts.
+    pub fn total(&self) -> Counts {
+        let mut total = Counts::default();
+        self.addrs.values().for_each(|counts| total.add(*counts));
+        total
+    }
+
+    /// Returns the counts per function, sorted by cycles.
+    /// With symbols, code belongs to the closest preceding non-local label.
+    /// Otherwise, it belongs to the innermost call it was executed in.
+    pub fn functions(&self, symbols: &SymbolTable) -> Vec<(String, Counts)> {
+        let mut functions = HashMap::<String, Counts>::new();
+        if symbols.is_empty() {
+            for node in self.nodes.iter().filter(|n| n.counts != Counts::default()) {
+                let name = match node.depth {
+                    0 => ROOT.to_string(),
+                    _ => frame_name(symbols, node.frame.0, node.frame.1),
+                };
+                functions.entry(name).or_default().add(node.counts);
+            }
+        } else {
+            for ((bank, addr), counts) in &self.addrs {
+                let name = symbols
+                    .function(*bank, *addr)
+                    .map_or_else(|| location(*bank, *addr), String::from);
+                functions.entry(name).or_default().add(*counts);
+            }
+        }
+        let mut functions = functions.into_iter().collect::<Vec<(String, Counts)>>();
+        functions.sort_by(|(name1, c1), (name2, c2)| {
+            c2.cycles.cmp(&c1.cycles).then_with(|| name1.cmp(name2))
+        });
+        functions
+    }
+
+    /// Formats a report of the functions and addresses that took the most cycles.
+    pub fn report(&self, symbols: &SymbolTable) -> String {
+        let total = self.total();
+        let percentage = |cycles: u64| cycles as f64 * 100.0 / total.cycles.max(1) as f64;
+        let mut report = format!(
+            "Total: {} instructions, {} cycles\n\nFunctions:\n{:>12} {:>7} {:>12}  Name\n",
+            total.instructions, total.cycles, "Cycles", "%", "Instructions"
+        );
+        for (name, counts) in self.functions(symbols) {
+            let _ = writeln!(
+                report,
+                "{:>12} {:>6.2}% {:>12}  {}",
+                counts.cycles,
+                percentage(counts.cycles),
+                counts.instructions,
+                name
+            );
+        }
+
+        let mut addrs = self.addrs.iter().collect::<Vec<_>>();
+        addrs.sort_by(|(loc1, c1), (loc2, c2)| c2.cycles.cmp(&c1.cycles).then(loc1.cmp(loc2)));
+        let _ = write!(
+            report,
+            "\nAddresses:\n{:>12} {:>7} {:>12}  Address\n",
+            "Cycles", "%", "Instructions"
+        );
+        for ((bank, addr), counts) in addrs.into_iter().take(REPORTED_ADDRS) {
+            let label = symbols
+                .label(*bank, *addr)
+                .map_or(String::new(), |label| format!(" {}", label));
+            let _ = writeln!(
+                report,
+                "{:>12} {:>6.2}% {:>12}  {}{}",
+                counts.cycles,
+                percentage(counts.cycles),
+                counts.instructions,
+                location(*bank, *addr),
+                label
+            );
+        }
+        report
+    }
+
+    /// Formats the cycles per call stack in the collapsed stack format, with one
+    /// `outer;inner cycles` line per stack. Flame graph tools accept this format as input.
+    pub fn collapsed(&self, symbols: &SymbolTable) -> String {
+        let mut lines = self
+            .stacks()
+            .map(|(stack, counts)| {
+                let names = std::iter::once(ROOT.to_string())
+                    .chain(
+                        stack
+                            .iter()
+                            .map(|(bank, addr)| frame_name(symbols, *bank, *addr)),
+                    )
+                    .collect::<Vec<String>>();
+                format!("{} {}", names.join(";"), counts.cycles)
+            })
+            .collect::<Vec<String>>();
+        lines.sort();
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+}
+
+/// Names the function called at `addr` in `bank`.
+fn frame_name(symbols: &SymbolTable, bank: usize, addr: u16) -> String {
+    symbols
+        .label(bank, addr)
+        .map_or_else(|| location(bank, addr), String::from)
+}
+
+fn location(bank: usize, addr: u16) -> String {
+    format!("{:02x}:{:04x}", bank, addr)
+}
@@ -66,6 +66,7 @@ struct R16 {
 }
 
 /// A union of all CPU registers, overlapping correctly.
+#[derive(Clone, Copy)]
 pub union Regs {
     flags: Flags,
     r8: R8,
@@ -0,0 +1,80 @@
+use crate::cpu::MemoryBus;
+
+/// The activity on the memory bus during a single machine cycle.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BusActivity {
+    /// The CPU did not access memory during this cycle.
+    Idle,
+    /// The CPU read the value from the address.
+    Read(u16, u8),
+    /// The CPU wrote the value to the address.
+    Write(u16, u8),
+}
+
+/// A flat 64 KiB [`MemoryBus`] without any hardware mapped into it.
+/// Records the bus activity of every machine cycle, so that the CPU can be validated in isolation.
+pub struct TestMemory {
+    mem: Box<[u8; 0x10000]>,
+    activity: Vec<BusActivity>,
+}
+
+impl TestMemory {
+    /// Initializes a new test memory filled with zeroes.
+    pub fn new() -> Self {
+        Self {
+            mem: Box::new([0; 0x10000]),
+            activity: Vec::new(),
+        }
+    }
+
+    /// Reads the value at `addr`, without recording it as bus activity.
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.mem[addr as usize]
+    }
+
+    /// Writes `val` to `addr`, without recording it as bus activity.
+    pub fn poke(&mut self, addr: u16, val: u8) {
+        self.mem[addr as usize] = val;
+    }
+
+    /// Returns the bus activity of every machine cycle since the last call to [`TestMemory::clear_activity`].
+    pub fn activity(&self) -> &[BusActivity] {
+        &self.activity
+    }
+
+    /// Clears the recorded bus activity.
+    pub fn clear_activity(&mut self) {
+        self.activity.clear();
+    }
+
+    /// Replaces the activity of the current machine cycle with `activity`.
+    fn record(&mut self, activity: BusActivity) {
+        match self.activity.last_mut() {
+            Some(last) => *last = activity,
+            None => self.activity.push(activity),
+        }
+    }
+}
+
+impl MemoryBus for TestMemory {
+    fn read(&mut self, addr: u16) -> u8 {
+        let val = self.peek(addr);
+        self.record(BusActivity::Read(addr, val));
+        val
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.poke(addr, val);
+        self.record(BusActivity::Write(addr, val));
+    }
+
+    fn tick(&mut self) {
+        self.activity.push(BusActivity::Idle);
+    }
+}
+
+impl Default for TestMemory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
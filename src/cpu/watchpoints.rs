@@ -0,0 +1,140 @@
+use std::{fmt, ops::RangeInclusive};
+
+/// The kind of memory access that triggers a watchpoint.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WatchKind {
+    /// Any read by an instruction.
+    Read,
+    /// Any write by an instruction.
+    Write,
+    /// A write by an instruction that changes the value in memory.
+    Change,
+}
+
+impl fmt::Display for WatchKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchKind::Read => write!(f, "read"),
+            WatchKind::Write => write!(f, "write"),
+            WatchKind::Change => write!(f, "change"),
+        }
+    }
+}
+
+/// Triggers when the CPU accesses any address in `range` in the way given by `kind`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    pub kind: WatchKind,
+}
+
+/// An access that triggered a watchpoint.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WatchHit {
+    /// The index of the triggered watchpoint.
+    pub index: usize,
+    pub kind: WatchKind,
+    /// The address that was accessed.
+    pub addr: u16,
+    /// The value before the access.
+    pub old: u8,
+    /// The value after the access, which is the same as `old` for reads.
+    pub new: u8,
+    /// The address of the instruction that accessed memory. For accesses made while dispatching
+    /// an interrupt, the address of the last instruction executed before the dispatch.
+    pub pc: u16,
+    /// Whether the access was made while dispatching an interrupt, rather than by an instruction.
+    pub interrupt: bool,
+}
+
+/// The watchpoints checked on every memory access of the CPU.
+#[derive(Clone, Default)]
+pub struct Watchpoints {
+    points: Vec<Watchpoint>,
+    /// The hits since the last call to [`Watchpoints::take_hits`], at most one per watchpoint.
+    hits: Vec<WatchHit>,
+    /// The address of the instruction being executed.
+    pc: u16,
+    /// Whether the CPU is dispatching an interrupt, instead of executing the instruction at `pc`.
+    interrupt: bool,
+}
+
+impl Watchpoints {
+    /// Adds `watchpoint`, and returns its index.
+    pub fn add(&mut self, watchpoint: Watchpoint) -> usize {
+        self.points.push(watchpoint);
+        self.points.len() - 1
+    }
+
+    /// Removes the watchpoint at `index`, if it exists.
+    /// Discards all hits, since the indices of later watchpoints change.
+    pub fn remove(&mut self, index: usize) -> Option<Watchpoint> {
+        self.hits.clear();
+        (index < self.points.len()).then(|| self.points.remove(index))
+    }
+
+    /// Returns all watchpoints, in the order they were added.
+    pub fn points(&self) -> &[Watchpoint] {
+        &self.points
+    }
+
+    /// Returns and clears the hits since the last call, in the order they happened.
+    /// Only the first hit of each watchpoint is kept, so that a condition can be checked
+    /// for every watchpoint that was hit.
+    pub fn take_hits(&mut self) -> Vec<WatchHit> {
+        std::mem::take(&mut self.hits)
+    }
+
+    /// Records that the CPU started executing the instruction at `pc`.
+    pub(crate) fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+        self.interrupt = false;
+    }
+
+    /// Records that the CPU started dispatching an interrupt.
+    pub(crate) fn start_interrupt(&mut self) {
+        self.interrupt = true;
+    }
+
+    /// Checks an instruction reading `val` from `addr`.
+    pub(crate) fn check_read(&mut self, addr: u16, val: u8) {
+        self.check(addr, val, val, |kind| kind == WatchKind::Read);
+    }
+
+    /// Checks an instruction writing `new` to `addr`, which held `old`.
+    pub(crate) fn check_write(&mut self, addr: u16, old: u8, new: u8) {
+        self.check(addr, old, new, |kind| match kind {
+            WatchKind::Read => false,
+            WatchKind::Write => true,
+            WatchKind::Change => old != new,
+        });
+    }
+
+    /// Returns whether there are no watchpoints, so that accesses do not need to be checked.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    fn check<F>(&mut self, addr: u16, old: u8, new: u8, triggers: F)
+    where
+        F: Fn(WatchKind) -> bool,
+    {
+        for (index, wp) in self.points.iter().enumerate() {
+            if !wp.range.contains(&addr) || !triggers(wp.kind) {
+                continue;
+            }
+            if self.hits.iter().any(|hit| hit.index == index) {
+                continue;
+            }
+            self.hits.push(WatchHit {
+                index,
+                kind: wp.kind,
+                addr,
+                old,
+                new,
+                pc: self.pc,
+                interrupt: self.interrupt,
+            });
+        }
+    }
+}
@@ -1,17 +1,30 @@
 use crate::{
-    cpu::Cpu,
+    bus::Bus,
+    cpu::{
+        hooks::{HookId, MemAccess, WriteAction},
+        Cpu,
+    },
     peripherals::{Cable, Joypad, Lcd, Speaker},
 };
+use std::ops::RangeInclusive;
 
 #[cfg(feature = "debug")]
-use crate::cpu::{
-    instructions::{
-        bitwise::BITWISE_PREFIX,
-        debug::{
-            base::BASE_INSTR_INFO, bitwise::BITWISE_INSTR_INFO, HasImmediate, Mnemonic, ParamType,
-        },
+use crate::{
+    apu::ApuState,
+    bus::BusTools,
+    cpu::{
+        call_stack::CallFrame,
+        cdl::CodeDataLog,
+        events::Event,
+        instructions::debug::{self, asm, Mnemonic},
+        interrupts::InterruptState,
+        profiler::Profiler,
+        registers::Regs,
+        watchpoints::{WatchHit, Watchpoint},
+        CpuTools,
     },
-    registers::Regs,
+    ppu::PpuState,
+    timer::TimerState,
 };
 
 /// Represents an emulated Game Boy.
@@ -22,7 +35,7 @@ where
     J: Joypad,
     C: Cable,
 {
-    cpu: Cpu<L, S, J, C>,
+    cpu: Cpu<Bus<L, S, J, C>>,
 }
 
 impl Gameboy {
@@ -48,62 +61,360 @@ where
 
     /// Makes the Game Boy emulator execute a single instruction,
     /// however many cycles that may take.
-    #[cfg(feature = "debug")]
     pub fn step(&mut self) {
         self.cpu.step();
     }
 
+    /// Reads the byte at `addr` from the currently mapped memory, without ticking
+    /// any hardware. External RAM can be read even while it is disabled.
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.cpu.bus().peek(addr)
+    }
+
+    /// Writes `val` to `addr` in the currently mapped memory, without ticking any hardware.
+    /// Writes to ROM overwrite the ROM instead of MBC registers, and IO registers are stored
+    /// without side effects: writing `DIV` sets it instead of resetting it, writing `DMA`
+    /// doesn't start a transfer, and the trigger bits of `NRx4` don't restart the channels.
+    pub fn poke(&mut self, addr: u16, val: u8) {
+        self.cpu.bus_mut().poke(addr, val);
+    }
+
+    /// Reads the byte at `addr` in ROM bank `bank`, regardless of the selected bank.
+    /// Returns `0xff` beyond the end of the ROM.
+    pub fn peek_rom(&self, bank: usize, addr: u16) -> u8 {
+        self.cpu.bus().peek_rom(bank, addr)
+    }
+
+    /// Reads the byte at `addr` in external RAM bank `bank`, regardless of the selected bank.
+    /// Returns `0xff` beyond the end of the RAM.
+    pub fn peek_cart_ram(&self, bank: usize, addr: u16) -> u8 {
+        self.cpu.bus().peek_cart_ram(bank, addr)
+    }
+
+    /// Calls `hook` whenever an instruction reads from an address in `range`.
+    /// Fetches of opcodes and operands are not reads, and only opcodes are seen by exec hooks.
+    pub fn on_read<F>(&mut self, range: RangeInclusive<u16>, hook: F) -> HookId
+    where
+        F: FnMut(&MemAccess) + Send + 'static,
+    {
+        self.cpu.hooks_mut().add_read(range, hook)
+    }
+
+    /// Calls `hook` whenever an instruction writes to an address in `range`, before the
+    /// write happens. The returned [`WriteAction`] can change the written value or drop the write.
+    pub fn on_write<F>(&mut self, range: RangeInclusive<u16>, hook: F) -> HookId
+    where
+        F: FnMut(&MemAccess) -> WriteAction + Send + 'static,
+    {
+        self.cpu.hooks_mut().add_write(range, hook)
+    }
+
+    /// Calls `hook` whenever the CPU executes an instruction at an address in `range`,
+    /// right after fetching its opcode.
+    pub fn on_exec<F>(&mut self, range: RangeInclusive<u16>, hook: F) -> HookId
+    where
+        F: FnMut(&MemAccess) + Send + 'static,
+    {
+        self.cpu.hooks_mut().add_exec(range, hook)
+    }
+
+    /// Removes the hook `id`, and returns whether it existed.
+    pub fn remove_hook(&mut self, id: HookId) -> bool {
+        self.cpu.hooks_mut().remove(id)
+    }
+
     /// Read the bytes at `addr` from the currently mapped memory.
     #[cfg(feature = "debug")]
     pub fn read_mem(&self, addr: u16) -> u8 {
         self.cpu.bus().read(addr)
     }
 
+    /// Write `val` to `addr` in the currently mapped memory. Writes to ROM overwrite the ROM
+    /// instead of MBC registers, while other writes go through the bus like an instruction's,
+    /// without triggering watchpoints, hooks or event breakpoints.
+    #[cfg(feature = "debug")]
+    pub fn write_mem(&mut self, addr: u16, val: u8) {
+        self.cpu.bus_mut().patch(addr, val);
+    }
+
     /// Retrieve the current CPU register values from the CPU.
     #[cfg(feature = "debug")]
     pub fn regs(&self) -> &Regs {
         self.cpu.regs()
     }
 
+    /// Retrieve a mutable reference to the CPU registers, to change them.
+    #[cfg(feature = "debug")]
+    pub fn regs_mut(&mut self) -> &mut Regs {
+        self.cpu.regs_mut()
+    }
+
+    /// Returns whether the Interrupt Master Enable is set.
+    #[cfg(feature = "debug")]
+    pub fn ime(&self) -> bool {
+        self.cpu.ime()
+    }
+
+    /// Sets the Interrupt Master Enable.
+    #[cfg(feature = "debug")]
+    pub fn set_ime(&mut self, ime: bool) {
+        self.cpu.set_ime(ime);
+    }
+
+    /// Returns whether the CPU is halted, waiting for an interrupt.
+    #[cfg(feature = "debug")]
+    pub fn halted(&self) -> bool {
+        self.cpu.halted()
+    }
+
+    /// Returns whether an invalid opcode locked up the CPU.
+    #[cfg(feature = "debug")]
+    pub fn locked(&self) -> bool {
+        self.cpu.locked()
+    }
+
+    /// Halts the CPU until an interrupt is requested, or wakes it up.
+    #[cfg(feature = "debug")]
+    pub fn set_halted(&mut self, halted: bool) {
+        self.cpu.set_halted(halted);
+    }
+
+    /// Formats the current CPU state as a single trace line, in the format used by
+    /// Gameboy Doctor: the registers, followed by the 4 bytes of memory at `PC`.
+    #[cfg(feature = "debug")]
+    pub fn trace(&self) -> String {
+        let regs = self.cpu.regs();
+        let pc = regs.pc();
+        format!(
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            regs.a(),
+            regs.af() as u8,
+            regs.b(),
+            regs.c(),
+            regs.d(),
+            regs.e(),
+            regs.h(),
+            regs.l(),
+            regs.sp(),
+            pc,
+            self.cpu.bus().read(pc),
+            self.cpu.bus().read(pc.wrapping_add(1)),
+            self.cpu.bus().read(pc.wrapping_add(2)),
+            self.cpu.bus().read(pc.wrapping_add(3)),
+        )
+    }
+
+    /// Makes reads from the `LY` register always return `val`, or the actual
+    /// scanline again when `None`. Reference trace logs are often recorded with
+    /// `LY` stubbed to `0x90`, so that they do not depend on PPU timing.
+    #[cfg(feature = "debug")]
+    pub fn stub_ly(&mut self, val: Option<u8>) {
+        self.cpu.bus_mut().ly_stub = val;
+    }
+
+    /// Enables or disables tracking calls and returns on a shadow call stack.
+    #[cfg(feature = "debug")]
+    pub fn track_calls(&mut self, enabled: bool) {
+        self.cpu.track_calls(enabled);
+    }
+
+    /// Returns the calls that have not returned yet, starting with the outermost one.
+    /// Empty unless call tracking is enabled with [`Gameboy::track_calls`].
+    #[cfg(feature = "debug")]
+    pub fn call_stack(&self) -> &[CallFrame] {
+        self.cpu.call_stack()
+    }
+
+    /// Starts counting executed instructions and cycles per address and per call,
+    /// discarding any previous counts.
+    #[cfg(feature = "debug")]
+    pub fn start_profiling(&mut self) {
+        self.cpu.start_profiling();
+    }
+
+    /// Continues profiling on top of the counts in `profiler`, like one returned by
+    /// [`Gameboy::stop_profiling`].
+    #[cfg(feature = "debug")]
+    pub fn resume_profiling(&mut self, profiler: Profiler) {
+        self.cpu.resume_profiling(profiler);
+    }
+
+    /// Stops profiling, and returns the counts so far.
+    #[cfg(feature = "debug")]
+    pub fn stop_profiling(&mut self) -> Option<Profiler> {
+        self.cpu.stop_profiling()
+    }
+
+    /// Returns the counts so far, if profiling is running.
+    #[cfg(feature = "debug")]
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.cpu.profiler()
+    }
+
+    /// Starts logging whether every byte of the ROM is executed or read as data,
+    /// discarding any previous log.
+    #[cfg(feature = "debug")]
+    pub fn start_cdl(&mut self) {
+        self.cpu.bus_mut().start_cdl();
+    }
+
+    /// Stops logging ROM accesses, and returns the log so far.
+    #[cfg(feature = "debug")]
+    pub fn stop_cdl(&mut self) -> Option<CodeDataLog> {
+        self.cpu.bus_mut().cdl.take()
+    }
+
+    /// Returns the log so far, if ROM accesses are being logged.
+    #[cfg(feature = "debug")]
+    pub fn cdl(&self) -> Option<&CodeDataLog> {
+        self.cpu.bus().cdl.as_ref()
+    }
+
+    /// Adds a watchpoint on memory accesses of the CPU, and returns its index.
+    #[cfg(feature = "debug")]
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        self.cpu.bus_mut().watchpoints.add(watchpoint)
+    }
+
+    /// Removes the watchpoint at `index`, if it exists.
+    #[cfg(feature = "debug")]
+    pub fn remove_watchpoint(&mut self, index: usize) -> Option<Watchpoint> {
+        self.cpu.bus_mut().watchpoints.remove(index)
+    }
+
+    /// Returns all watchpoints, in the order they were added.
+    #[cfg(feature = "debug")]
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        self.cpu.bus().watchpoints.points()
+    }
+
+    /// Returns and clears the watchpoint hits since the last call, at most one per watchpoint.
+    /// Hits are reported once the instruction that triggered them has finished.
+    #[cfg(feature = "debug")]
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        self.cpu.bus_mut().watchpoints.take_hits()
+    }
+
+    /// Starts or stops recording hardware events, see [`Gameboy::take_events`].
+    #[cfg(feature = "debug")]
+    pub fn record_events(&mut self, enabled: bool) {
+        self.cpu.bus_mut().events.set_enabled(enabled);
+    }
+
+    /// Returns and clears the hardware events recorded since the last call, in order.
+    #[cfg(feature = "debug")]
+    pub fn take_events(&mut self) -> Vec<Event> {
+        self.cpu.bus_mut().events.take()
+    }
+
+    /// Returns the number of machine cycles emulated so far.
+    pub fn cycles(&self) -> u64 {
+        self.cpu.cycles()
+    }
+
+    /// Returns a snapshot of the PPU registers.
+    #[cfg(feature = "debug")]
+    pub fn ppu_state(&self) -> PpuState {
+        self.cpu.bus().ppu_state()
+    }
+
+    /// Returns a snapshot of the timer registers.
+    #[cfg(feature = "debug")]
+    pub fn timer_state(&self) -> TimerState {
+        self.cpu.bus().timer_state()
+    }
+
+    /// Returns a snapshot of the interrupt registers.
+    #[cfg(feature = "debug")]
+    pub fn interrupt_state(&self) -> InterruptState {
+        self.cpu.bus().interrupts.state(self.cpu.ime())
+    }
+
+    /// Returns a snapshot of the APU registers and of every sound channel.
+    #[cfg(feature = "debug")]
+    pub fn apu_state(&self) -> ApuState {
+        self.cpu.bus().apu_state()
+    }
+
+    /// Returns the ROM bank currently mapped to `0x4000 - 0x7fff`.
+    #[cfg(feature = "debug")]
+    pub fn rom_bank(&self) -> usize {
+        self.cpu.bus().rom_bank()
+    }
+
     /// Disassembles the instruction at `addr`. Returns both the bytes
     /// corresponding to the instruction, and the mnemonic.
     #[cfg(feature = "debug")]
     pub fn disasm_at(&self, addr: u16) -> (Vec<u8>, Mnemonic) {
-        let mut bytes = Vec::with_capacity(3);
-
-        let opcode = self.cpu.bus().read(addr);
-        bytes.push(opcode);
-        let mnemonic = match opcode {
-            BITWISE_PREFIX => {
-                // All bitwise instructions have length 2,
-                // and no bytes containing immediates.
-                let opcode = self.cpu.bus().read(addr + 1);
-                bytes.push(opcode);
-                *BITWISE_INSTR_INFO[opcode as usize].mnemonic()
-            }
-            _ => {
-                // Length can vary for other instructions.
-                let instr = &BASE_INSTR_INFO[opcode as usize];
-                let imm = match instr.param_type() {
-                    ParamType::None => vec![],
-                    ParamType::Byte => {
-                        let byte = self.cpu.bus().read(addr + 1);
-                        bytes.push(byte);
-                        vec![byte]
-                    }
-                    ParamType::Word => {
-                        let byte1 = self.cpu.bus().read(addr + 1);
-                        let byte2 = self.cpu.bus().read(addr + 2);
-                        bytes.push(byte1);
-                        bytes.push(byte2);
-                        vec![byte1, byte2]
-                    }
-                };
-                instr.mnemonic().with_immediate(&imm)
-            }
-        };
-        (bytes, mnemonic)
+        let bytes = [0, 1, 2].map(|i| self.cpu.bus().read(addr.wrapping_add(i)));
+        let (len, mnemonic) = debug::decode(bytes);
+        (bytes[..len].to_vec(), mnemonic)
+    }
+
+    /// Captures the state of the Game Boy, to restore it later with [`Gameboy::restore`].
+    /// The hooks, watchpoints, profiler, Code/Data Log and other debugging tools are left out,
+    /// and the ROM is shared with the snapshot until either patches it.
+    #[cfg(feature = "debug")]
+    pub fn snapshot(&mut self) -> Snapshot<L, S, J, C>
+    where
+        L: Clone,
+        S: Clone,
+        J: Clone,
+        C: Clone,
+    {
+        let tools = self.take_tools();
+        let cpu = self.cpu.clone();
+        self.set_tools(tools);
+        Snapshot { cpu }
     }
+
+    /// Restores the state captured in `snapshot`. The debugging tools are kept as they are.
+    #[cfg(feature = "debug")]
+    pub fn restore(&mut self, snapshot: &Snapshot<L, S, J, C>)
+    where
+        L: Clone,
+        S: Clone,
+        J: Clone,
+        C: Clone,
+    {
+        let tools = self.take_tools();
+        self.cpu = snapshot.cpu.clone();
+        self.set_tools(tools);
+    }
+
+    #[cfg(feature = "debug")]
+    fn take_tools(&mut self) -> (CpuTools, BusTools) {
+        (self.cpu.take_tools(), self.cpu.bus_mut().take_tools())
+    }
+
+    #[cfg(feature = "debug")]
+    fn set_tools(&mut self, (cpu, bus): (CpuTools, BusTools)) {
+        self.cpu.set_tools(cpu);
+        self.cpu.bus_mut().set_tools(bus);
+    }
+
+    /// Assembles the instructions in `src` and writes the resulting bytes to `addr`,
+    /// overwriting the ROM if needed. Returns the number of bytes written.
+    #[cfg(feature = "debug")]
+    pub fn patch_asm(&mut self, addr: u16, src: &str) -> Result<usize, String> {
+        let bytes = asm::assemble(src, addr)?;
+        for (i, byte) in bytes.iter().enumerate() {
+            self.write_mem(addr.wrapping_add(i as u16), *byte);
+        }
+        Ok(bytes.len())
+    }
+}
+
+/// The state of a [`Gameboy`] at some point, captured with [`Gameboy::snapshot`].
+#[cfg(feature = "debug")]
+pub struct Snapshot<L, S, J, C>
+where
+    L: Lcd,
+    S: Speaker,
+    J: Joypad,
+    C: Cable,
+{
+    cpu: Cpu<Bus<L, S, J, C>>,
 }
 
 /// A builder for a [`Gameboy`], allowing peripherals for different input and output devices to be attached.
@@ -228,7 +539,13 @@ where
     /// Builds a new [`Gameboy`].
     pub fn build(self) -> Gameboy<L, S, J, C> {
         Gameboy {
-            cpu: Cpu::new(self.rom, self.lcd, self.speaker, self.joypad, self.cable),
+            cpu: Cpu::new(Bus::new(
+                self.rom,
+                self.lcd,
+                self.speaker,
+                self.joypad,
+                self.cable,
+            )),
         }
     }
 }
@@ -20,6 +20,7 @@ enum ActBtn {
 
 /// The `P1` IO register. An 8-bit value consisting of 4 bits that represent pressed and released buttons,
 /// and 2 bits that toggle whether action and directional buttons are enabled.
+#[derive(Clone)]
 pub struct P1 {
     byte: u8,
 }
@@ -63,6 +64,7 @@ impl P1 {
 }
 
 /// Emulates the Game Boy joypad.
+#[derive(Clone)]
 pub struct JoypadController<J>
 where
     J: Joypad,
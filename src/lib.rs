@@ -10,7 +10,32 @@ mod serial;
 mod timer;
 pub use apu::APU_SAMPLE_RATE;
 #[cfg(feature = "debug")]
-pub use cpu::{instructions::debug::Mnemonic, registers::Regs};
+pub use apu::{ApuState, ChannelState};
+#[cfg(feature = "debug")]
+pub use cpu::instructions::debug::{
+    asm::assemble, decode, disasm::Disassembly, symbols::SymbolTable, Mnemonic,
+};
+#[cfg(feature = "debug")]
+pub use cpu::{
+    call_stack::CallFrame,
+    cdl::CodeDataLog,
+    events::{Event, Events},
+    interrupts::InterruptState,
+    profiler::{Counts, Profiler},
+    test_memory::{BusActivity, TestMemory},
+    watchpoints::{WatchHit, WatchKind, Watchpoint, Watchpoints},
+};
+pub use cpu::{
+    hooks::{HookId, Hooks, MemAccess, WriteAction},
+    registers::Regs,
+    Access, Cpu, MemoryBus,
+};
 pub use gameboy::Gameboy;
+#[cfg(feature = "debug")]
+pub use gameboy::Snapshot;
 pub use peripherals::{ButtonState, Cable, Joypad, Lcd, LcdColor, Speaker};
+#[cfg(feature = "debug")]
+pub use ppu::PpuState;
 pub use ppu::{LCD_HEIGHT, LCD_WIDTH};
+#[cfg(feature = "debug")]
+pub use timer::TimerState;
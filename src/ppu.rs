@@ -20,6 +20,7 @@ pub const LINE_DOTS: usize = 456;
 pub const FRAME_LINES: u8 = 154;
 
 /// An enum representing the current PPU state in the state machine.
+#[derive(Clone)]
 enum PpuMode {
     Oam = 0x02,
     Draw = 0x03,
@@ -28,6 +29,7 @@ enum PpuMode {
 }
 
 /// The `STAT` IO register, representing the current state of the PPU.
+#[derive(Clone)]
 pub struct Stat {
     byte: u8,
 }
@@ -85,7 +87,41 @@ impl Stat {
     }
 }
 
+/// A snapshot of the PPU registers, for inspection.
+#[cfg(feature = "debug")]
+#[derive(Clone, Copy, Debug)]
+pub struct PpuState {
+    pub lcdc: u8,
+    pub stat: u8,
+    pub ly: u8,
+    pub lyc: u8,
+    pub scx: u8,
+    pub scy: u8,
+    pub wx: u8,
+    pub wy: u8,
+    pub bgp: u8,
+    pub obp0: u8,
+    pub obp1: u8,
+    /// The current mode: 0 for HBLANK, 1 for VBLANK, 2 for OAM and 3 for DRAW.
+    pub mode: u8,
+    /// The number of dots elapsed since the start of the current scanline.
+    pub line_dots: usize,
+    pub lcd_enabled: bool,
+    pub window_enabled: bool,
+    pub sprites_enabled: bool,
+    pub bg_enabled: bool,
+    /// Whether sprites are 8x16 instead of 8x8.
+    pub tall_sprites: bool,
+    /// The address of the tile map used for the background.
+    pub bg_map: u16,
+    /// The address of the tile map used for the window.
+    pub window_map: u16,
+    /// The address of the tile data used for the background and the window.
+    pub tile_data: u16,
+}
+
 /// Emulates the Game Boy Pixel Processing Unit.
+#[derive(Clone)]
 pub struct Ppu<L>
 where
     L: Lcd,
@@ -120,6 +156,37 @@ where
         }
     }
 
+    /// Returns a snapshot of the PPU registers, with `LCDC` and `STAT` decoded.
+    #[cfg(feature = "debug")]
+    pub fn state(&self) -> PpuState {
+        let lcdc = self.fetcher.lcdc.byte();
+        let bit = |n: u8| (lcdc >> n) & 1 != 0;
+        let map = |n: u8| if bit(n) { 0x9c00 } else { 0x9800 };
+        PpuState {
+            lcdc,
+            stat: self.stat.byte(),
+            ly: self.fetcher.ly,
+            lyc: self.lyc,
+            scx: self.fetcher.scx,
+            scy: self.fetcher.scy,
+            wx: self.fetcher.wx,
+            wy: self.fetcher.wy,
+            bgp: self.fetcher.bgp.byte(),
+            obp0: self.fetcher.obp0.byte(),
+            obp1: self.fetcher.obp1.byte(),
+            mode: self.stat.mode() as u8,
+            line_dots: self.line_dots,
+            lcd_enabled: bit(7),
+            window_enabled: bit(5),
+            sprites_enabled: bit(1),
+            bg_enabled: bit(0),
+            tall_sprites: bit(2),
+            bg_map: map(3),
+            window_map: map(6),
+            tile_data: if bit(4) { 0x8000 } else { 0x8800 },
+        }
+    }
+
     /// Emulates a machine cycle of the PPU. Implemented using a state machine.
     /// May request the VBLANK and/or LCDSTAT interrupt.
     pub fn step(&mut self, ints: &mut IntReg) {
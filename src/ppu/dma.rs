@@ -1,5 +1,6 @@
 /// Emulates the Direct Memory Access feature of the PPU,
 /// which copies a part of memory into OAM.
+#[derive(Clone)]
 pub struct Dma {
     pub active: bool,
     source: u16,
@@ -10,6 +10,7 @@ use crate::{
 };
 
 /// The `LCDC` IO register, allowing control of the PPU.
+#[derive(Clone)]
 pub struct Lcdc {
     byte: u8,
 }
@@ -78,6 +79,7 @@ impl Lcdc {
 }
 
 /// Represents a palette IO register. An 8-bit value consisting of 4 colors of 2 bits each.
+#[derive(Clone)]
 pub struct Palette {
     byte: u8,
 }
@@ -109,6 +111,7 @@ impl Palette {
 }
 
 /// An enum representing the current fetcher state in the state machine.
+#[derive(Clone)]
 pub enum FetchState {
     Index,
     Line0,
@@ -118,13 +121,14 @@ pub enum FetchState {
 }
 
 /// An enum representing the current target between background and window to fetch from.
-#[derive(PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq)]
 pub enum FetchTarget {
     Bck,
     Win,
 }
 
 /// Represents the Pixel Fetcher of the Game Boy.
+#[derive(Clone)]
 pub struct PixelFetcher {
     pub state: FetchState,
     pub target: FetchTarget,
@@ -228,10 +232,10 @@ impl PixelFetcher {
         }
         // Every step takes 2 dots, except pushing, which is attempted every dot.
         match self.state {
-            FetchState::Index if dot % 2 == 0 => self.fetch_tile_idx(),
-            FetchState::Line0 if dot % 2 == 0 => self.fetch_tile_low(),
-            FetchState::Line1 if dot % 2 == 0 => self.fetch_tile_high(),
-            FetchState::Sleep if dot % 2 == 0 => self.state = FetchState::Push,
+            FetchState::Index if dot.is_multiple_of(2) => self.fetch_tile_idx(),
+            FetchState::Line0 if dot.is_multiple_of(2) => self.fetch_tile_low(),
+            FetchState::Line1 if dot.is_multiple_of(2) => self.fetch_tile_high(),
+            FetchState::Sleep if dot.is_multiple_of(2) => self.state = FetchState::Push,
             FetchState::Push => self.push_tile_data(),
             _ => (),
         }
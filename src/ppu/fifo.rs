@@ -1,6 +1,7 @@
 use crate::ppu::oam::SprPalette;
 
 /// A bit queue used for Background and Window pixel information.
+#[derive(Clone)]
 pub struct BgwBitQueue {
     queue: u8,
     len: usize,
@@ -39,6 +40,7 @@ impl BgwBitQueue {
 }
 
 /// A bit queue used for Sprite pixel information.
+#[derive(Clone)]
 pub struct SprBitQueue {
     queue: u8,
 }
@@ -69,6 +71,7 @@ impl SprBitQueue {
 }
 
 /// Represents an entry in the pixel FIFO.
+#[derive(Clone)]
 pub struct FifoEntry {
     pub bgw_c: u8,
     pub spr_c: u8,
@@ -78,6 +81,7 @@ pub struct FifoEntry {
 
 /// Represents the Pixel FIFO of the Game Boy.
 /// Consists of 6 bit queues containing background, window and sprite information.
+#[derive(Clone)]
 pub struct PixelFifo {
     bgw0: BgwBitQueue,
     bgw1: BgwBitQueue,
@@ -1,6 +1,7 @@
 use std::cmp::Ordering;
 
 /// An enum representing a sprite palette register.
+#[derive(Clone)]
 pub enum SprPalette {
     Obp0 = 0,
     Obp1 = 1,
@@ -73,6 +74,7 @@ impl Ord for Sprite {
 }
 
 /// Represents the Object Attribute Memory of the Game Boy.
+#[derive(Clone, Copy)]
 pub union Oam {
     bytes: [u8; 0xa0],
     sprites: [Sprite; 40],
@@ -28,6 +28,7 @@ impl TileType {
 }
 
 /// Represents the Video RAM of the Game Boy.
+#[derive(Clone, Copy)]
 pub union Vram {
     bytes: [u8; 0x2000],
     tiles: [Tile; 0x180],
@@ -2,6 +2,7 @@ use crate::{cpu::interrupts::IntReg, peripherals::Cable};
 
 /// A temporary simple implementation of the serial controller.
 /// Made only to be used for Blargg's Game Boy CPU tests.
+#[derive(Clone)]
 pub struct SerialController<C>
 where
     C: Cable,
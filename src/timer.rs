@@ -1,6 +1,7 @@
 use crate::cpu::interrupts::IntReg;
 
 /// An enum representing the mode of the timer.
+#[derive(Clone)]
 enum ClockMode {
     C16 = 1,
     C64 = 2,
@@ -9,6 +10,7 @@ enum ClockMode {
 }
 
 /// The `TAC` IO register of the timer, allowing control over the timer.
+#[derive(Clone)]
 pub struct Tac {
     byte: u8,
 }
@@ -42,7 +44,22 @@ impl Tac {
     }
 }
 
+/// A snapshot of the timer registers, for inspection.
+#[cfg(feature = "debug")]
+#[derive(Clone, Copy, Debug)]
+pub struct TimerState {
+    /// The full internal counter, of which `DIV` is the upper byte.
+    pub div: u16,
+    pub tima: u8,
+    pub tma: u8,
+    pub tac: u8,
+    pub enabled: bool,
+    /// The frequency in Hz at which `TIMA` is incremented when the timer is enabled.
+    pub frequency: u32,
+}
+
 /// Emulates the Game Boy timer.
+#[derive(Clone)]
 pub struct Timer {
     pub div: u16,
     pub tima: u8,
@@ -61,6 +78,24 @@ impl Timer {
         }
     }
 
+    /// Returns a snapshot of the timer registers.
+    #[cfg(feature = "debug")]
+    pub fn state(&self) -> TimerState {
+        TimerState {
+            div: self.div,
+            tima: self.tima,
+            tma: self.tma,
+            tac: self.tac.byte(),
+            enabled: self.tac.enabled(),
+            frequency: match self.tac.mode() {
+                ClockMode::C16 => 262144,
+                ClockMode::C64 => 65536,
+                ClockMode::C256 => 16384,
+                ClockMode::C1024 => 4096,
+            },
+        }
+    }
+
     /// Emulates a machine cycle of the timer, according to the current clock mode.
     /// May request the TIMER interrupt.
     pub fn step(&mut self, ints: &mut IntReg) {
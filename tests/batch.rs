@@ -0,0 +1,96 @@
+//! Drives `gabbro-db` in batch mode, and checks the JSON results of its commands.
+
+use gabbro::CodeDataLog;
+use serde_json::Value;
+use std::{
+    env, fs,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// A ROM that sets `a` to 3, and then loops at `0x4100`.
+fn test_rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x105].copy_from_slice(&[
+        0x3e, 0x03, // ld a, 3
+        0xc3, 0x00, 0x41, // jp $4100
+    ]);
+    rom[0x4100..0x4102].copy_from_slice(&[0x18, 0xfe]); // jr @
+    rom
+}
+
+/// Runs `commands` in batch mode on `rom`, and returns the result of every command.
+fn run_batch(rom: &[u8], commands: &[&str]) -> Vec<Value> {
+    let path = env::temp_dir().join(format!("gabbro-batch-{}.gb", std::process::id()));
+    fs::write(&path, rom).unwrap();
+    let mut debugger = Command::new(env!("CARGO_BIN_EXE_gabbro-db"))
+        .arg(&path)
+        .args(["--batch", "--json", "--max-cycles", "1000000"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = debugger.stdin.take().unwrap();
+    for command in commands {
+        writeln!(stdin, "{}", command).unwrap();
+    }
+    drop(stdin);
+    let output = debugger.wait_with_output().unwrap();
+    fs::remove_file(&path).unwrap();
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn conditional_breakpoint() {
+    let results = run_batch(
+        &test_rom(),
+        &[
+            "break add $4100 if a == 3 && [ff44] > 100",
+            "continue",
+            "print [ff44] > 100",
+        ],
+    );
+    assert_eq!(results.len(), 3);
+    assert!(
+        results.iter().all(|result| result["ok"] == true),
+        "{:?}",
+        results
+    );
+    assert_eq!(results[1]["stop"], "breakpoint");
+    assert_eq!(results[1]["pc"], 0x4100);
+    // Unprefixed numbers in brackets are hexadecimal, and the rest are decimal.
+    assert_eq!(results[2]["output"], "[ff44] > 100 = 1 (0x1)");
+}
+
+#[test]
+fn code_data_log() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x105].copy_from_slice(&[
+        0xcd, 0x50, 0x01, // call $0150
+        0x18, 0xfe, // jr @
+    ]);
+    rom[0x150] = 0xc9; // ret
+    let path = env::temp_dir().join(format!("gabbro-batch-{}.cdl", std::process::id()));
+    let save = format!("cdl save {}", path.display());
+    let results = run_batch(&rom, &["cdl start", "step 4", &save]);
+    assert!(
+        results.iter().all(|result| result["ok"] == true),
+        "{:?}",
+        results
+    );
+    let flags = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(flags.len(), rom.len());
+    assert_eq!(flags[0x100..0x106], [0x01, 0x01, 0x01, 0x05, 0x01, 0x00]);
+    // The subroutine is both a jump target and a subroutine entry point.
+    assert_eq!(flags[0x150], 0x0d);
+
+    // Mesen's header is skipped, and only the opcodes at branch targets are known.
+    let log = CodeDataLog::from_bytes([b"CDLv2", &[0; 4][..], &flags].concat());
+    assert_eq!(log.bytes(), flags);
+    assert_eq!(log.opcodes().collect::<Vec<_>>(), [(0, 0x103), (0, 0x150)]);
+}
@@ -0,0 +1,238 @@
+//! Drives the Debug Adapter Protocol server of `gabbro-db` over stdio, like an editor would.
+
+use serde_json::{json, Value};
+use std::{
+    env, fs,
+    io::{BufRead, BufReader, Read, Write},
+    path::PathBuf,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+/// The source of [`test_rom`], with a local label without a colon.
+const SOURCE: &str = r#"SECTION "Main", ROM0[$150]
+
+Main:
+    ld a, 0 ; start
+.loop
+    inc a
+    call Sub
+    jr .loop
+
+Sub:
+    ld b, a
+    ret
+"#;
+
+/// The source of the entry point of [`test_rom`].
+const ENTRY_SOURCE: &str = r#"SECTION "Entry", ROM0[$100]
+
+Entry:
+    jp Main
+"#;
+
+const SYMBOLS: &str = "00:0100 Entry\n00:0150 Main\n00:0152 Main.loop\n00:0158 Sub\n";
+
+/// The ROM assembled from [`SOURCE`], which jumps to `Main` from the entry point.
+fn test_rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x103].copy_from_slice(&[0xc3, 0x50, 0x01]);
+    rom[0x150..0x15a].copy_from_slice(&[
+        0x3e, 0x00, // ld a, 0
+        0x3c, // inc a
+        0xcd, 0x58, 0x01, // call Sub
+        0x18, 0xfa, // jr .loop
+        0x47, // ld b, a
+        0xc9, // ret
+    ]);
+    rom
+}
+
+/// A client of the server, which kills it when dropped.
+struct Client {
+    server: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    seq: u64,
+    dir: PathBuf,
+}
+
+impl Client {
+    /// Starts `gabbro-db` as a DAP server, with a directory named after `name` holding
+    /// [`test_rom`] and its sources.
+    fn start(name: &str) -> Self {
+        let dir = env::temp_dir().join(format!("gabbro-dap-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("game.gb"), test_rom()).unwrap();
+        fs::write(dir.join("game.sym"), SYMBOLS).unwrap();
+        fs::write(dir.join("main.asm"), SOURCE).unwrap();
+        fs::write(dir.join("entry.asm"), ENTRY_SOURCE).unwrap();
+        let mut server = Command::new(env!("CARGO_BIN_EXE_gabbro-db"))
+            .arg("--dap")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        Self {
+            stdin: server.stdin.take().unwrap(),
+            stdout: BufReader::new(server.stdout.take().unwrap()),
+            server,
+            seq: 1,
+            dir,
+        }
+    }
+
+    /// Frames `command` as a request, without sending it.
+    fn frame(&mut self, command: &str, arguments: Value) -> String {
+        let body = json!({
+            "seq": self.seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        })
+        .to_string();
+        self.seq += 1;
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    /// Sends `command`, and returns the body of its response after checking that it succeeded.
+    fn request(&mut self, command: &str, arguments: Value) -> Value {
+        let request = self.frame(command, arguments);
+        self.stdin.write_all(request.as_bytes()).unwrap();
+        self.response(command)
+    }
+
+    /// Reads messages until the response to `command`, and returns its body.
+    fn response(&mut self, command: &str) -> Value {
+        loop {
+            let message = self.read_message();
+            if message["type"] == "response" {
+                assert_eq!(message["command"], command);
+                assert_eq!(message["success"], true, "{}", message);
+                return message["body"].clone();
+            }
+        }
+    }
+
+    /// Reads messages until the event `event`, and returns its body.
+    fn event(&mut self, event: &str) -> Value {
+        loop {
+            let message = self.read_message();
+            if message["type"] == "event" && message["event"] == event {
+                return message["body"].clone();
+            }
+        }
+    }
+
+    /// Reads a message, checking that its `Content-Length` header matches its body.
+    fn read_message(&mut self) -> Value {
+        let mut len = None;
+        loop {
+            let mut line = String::new();
+            self.stdout.read_line(&mut line).unwrap();
+            assert!(line.ends_with("\r\n"), "unterminated header {:?}", line);
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            len = line
+                .strip_prefix("Content-Length: ")
+                .map(|n| n.parse().unwrap());
+        }
+        let mut body = vec![0; len.expect("missing Content-Length")];
+        self.stdout.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    /// Returns the address of the instruction the program stopped at.
+    fn pc(&mut self) -> String {
+        let trace = self.request("stackTrace", json!({ "threadId": 1 }));
+        trace["stackFrames"][0]["instructionPointerReference"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        let _ = self.server.kill();
+        let _ = self.server.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn dap_session() {
+    let mut client = Client::start("session");
+    let path = client.dir.join("main.asm");
+
+    // Two requests in a single write, one with an extra header, must be split by their lengths.
+    let initialize = client.frame("initialize", json!({ "adapterID": "gabbro" }));
+    let launch = client
+        .frame("launch", json!({ "program": client.dir.join("game.gb") }))
+        .replacen("\r\n\r\n", "\r\nContent-Type: application/json\r\n\r\n", 1);
+    let requests = initialize + &launch;
+    client.stdin.write_all(requests.as_bytes()).unwrap();
+    client.response("initialize");
+    client.response("launch");
+    client.event("initialized");
+
+    // Breakpoints move to the next line with an instruction, also after a label without a colon.
+    let breakpoints = client.request(
+        "setBreakpoints",
+        json!({
+            "source": { "path": path },
+            "breakpoints": [{ "line": 2 }, { "line": 5 }, { "line": 7 }, { "line": 13 }],
+        }),
+    );
+    let lines = breakpoints["breakpoints"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|bp| {
+            (
+                bp["verified"].as_bool().unwrap(),
+                bp["line"].as_u64().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(lines, [(true, 4), (true, 6), (true, 7), (false, 13)]);
+    client.request(
+        "setBreakpoints",
+        json!({ "source": { "path": path }, "breakpoints": [{ "line": 7 }] }),
+    );
+
+    client.request("configurationDone", json!({}));
+    assert_eq!(client.event("stopped")["reason"], "breakpoint");
+    assert_eq!(client.pc(), "0x0153");
+
+    // Stepping an instruction steps over the call.
+    client.request(
+        "next",
+        json!({ "threadId": 1, "granularity": "instruction" }),
+    );
+    assert_eq!(client.event("stopped")["reason"], "step");
+    assert_eq!(client.pc(), "0x0156");
+
+    client.request("disconnect", json!({}));
+}
+
+#[test]
+fn breakpoint_at_entry() {
+    let mut client = Client::start("entry");
+    let path = client.dir.join("entry.asm");
+    client.request("initialize", json!({ "adapterID": "gabbro" }));
+    client.request("launch", json!({ "program": client.dir.join("game.gb") }));
+    client.event("initialized");
+
+    // The breakpoint is hit before the first instruction runs.
+    client.request(
+        "setBreakpoints",
+        json!({ "source": { "path": path }, "breakpoints": [{ "line": 4 }] }),
+    );
+    client.request("configurationDone", json!({}));
+    assert_eq!(client.event("stopped")["reason"], "breakpoint");
+    assert_eq!(client.pc(), "0x0100");
+
+    client.request("disconnect", json!({}));
+}
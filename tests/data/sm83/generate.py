@@ -0,0 +1,380 @@
+"""Generates SM83 single-step test vectors in the SingleStepTests JSON format.
+
+The expected states and bus activity come from the small reference model below, written from
+the documented behaviour of the SM83, and not from the emulator under test. Every case starts
+with `pc` one past the opcode, and ends by fetching the next opcode, like the upstream vectors.
+
+Usage: python3 generate.py <output dir> <cases per opcode>
+The vectors in this directory were generated with `python3 generate.py . 25`.
+"""
+import json, random, sys, os
+
+Z, N, H, C = 0x80, 0x40, 0x20, 0x10
+
+class State:
+    def __init__(self, rng, opcode_bytes):
+        self.rng = rng
+        self.r = {k: rng.randrange(256) for k in "abcdehl"}
+        self.r["f"] = rng.randrange(16) << 4
+        self.sp = rng.randrange(0x10000)
+        self.pc = rng.randrange(0x0100, 0xbf00)
+        self.ime = rng.randrange(2)
+        self.ie = rng.randrange(2)
+        self.mem = {}
+        self.initial_mem = {}
+        self.cycles = []
+        # The opcode bytes before pc, and any bytes following.
+        start = self.pc - 1
+        for i, b in enumerate(opcode_bytes):
+            self.mem[start + i] = b
+            self.initial_mem[start + i] = b
+
+    def snapshot(self):
+        return {
+            "pc": self.pc, "sp": self.sp, "a": self.r["a"], "b": self.r["b"], "c": self.r["c"],
+            "d": self.r["d"], "e": self.r["e"], "f": self.r["f"], "h": self.r["h"], "l": self.r["l"],
+            "ime": self.ime, "ie": self.ie,
+        }
+
+    def peek(self, addr):
+        addr &= 0xffff
+        if addr not in self.mem:
+            v = self.rng.randrange(256)
+            self.mem[addr] = v
+            self.initial_mem[addr] = v
+        return self.mem[addr]
+
+    def read(self, addr):
+        addr &= 0xffff
+        v = self.peek(addr)
+        self.cycles.append([addr, v, "r-m"])
+        return v
+
+    def write(self, addr, v):
+        addr &= 0xffff
+        self.peek(addr)
+        self.mem[addr] = v & 0xff
+        self.cycles.append([addr, v & 0xff, "-wm"])
+
+    def internal(self):
+        self.cycles.append(None)
+
+    def imm8(self):
+        v = self.read(self.pc)
+        self.pc = (self.pc + 1) & 0xffff
+        return v
+
+    def imm16(self):
+        lo = self.imm8()
+        return lo | self.imm8() << 8
+
+    def fetch_next(self):
+        self.read(self.pc)
+        self.pc = (self.pc + 1) & 0xffff
+
+    def flag(self, f):
+        return self.r["f"] & f != 0
+
+    def set_flags(self, z=None, n=None, h=None, c=None):
+        f = self.r["f"]
+        for bit, val in ((Z, z), (N, n), (H, h), (C, c)):
+            if val is not None:
+                f = (f | bit) if val else (f & ~bit)
+        self.r["f"] = f & 0xf0
+
+    def get16(self, rr):
+        if rr == "sp":
+            return self.sp
+        if rr == "af":
+            return self.r["a"] << 8 | self.r["f"]
+        return self.r[rr[0]] << 8 | self.r[rr[1]]
+
+    def set16(self, rr, v):
+        v &= 0xffff
+        if rr == "sp":
+            self.sp = v
+        elif rr == "af":
+            self.r["a"], self.r["f"] = v >> 8, v & 0xf0
+        else:
+            self.r[rr[0]], self.r[rr[1]] = v >> 8, v & 0xff
+
+    def push(self, v):
+        self.sp = (self.sp - 1) & 0xffff
+        self.write(self.sp, v >> 8)
+        self.sp = (self.sp - 1) & 0xffff
+        self.write(self.sp, v & 0xff)
+
+    def pop(self):
+        lo = self.read(self.sp)
+        self.sp = (self.sp + 1) & 0xffff
+        hi = self.read(self.sp)
+        self.sp = (self.sp + 1) & 0xffff
+        return hi << 8 | lo
+
+def cond(s, cc):
+    return {"nz": not s.flag(Z), "z": s.flag(Z), "nc": not s.flag(C), "c": s.flag(C)}[cc]
+
+def alu(s, op, v):
+    a = s.r["a"]
+    if op in ("add", "adc"):
+        carry = 1 if op == "adc" and s.flag(C) else 0
+        res = a + v + carry
+        s.set_flags(z=res & 0xff == 0, n=False, h=(a & 0xf) + (v & 0xf) + carry > 0xf, c=res > 0xff)
+        s.r["a"] = res & 0xff
+    elif op in ("sub", "sbc", "cp"):
+        carry = 1 if op == "sbc" and s.flag(C) else 0
+        res = a - v - carry
+        s.set_flags(z=res & 0xff == 0, n=True, h=(a & 0xf) - (v & 0xf) - carry < 0, c=res < 0)
+        if op != "cp":
+            s.r["a"] = res & 0xff
+    elif op == "and":
+        s.r["a"] = a & v
+        s.set_flags(z=s.r["a"] == 0, n=False, h=True, c=False)
+    elif op == "xor":
+        s.r["a"] = a ^ v
+        s.set_flags(z=s.r["a"] == 0, n=False, h=False, c=False)
+    elif op == "or":
+        s.r["a"] = a | v
+        s.set_flags(z=s.r["a"] == 0, n=False, h=False, c=False)
+
+def cb_op(s, op, v):
+    """Returns the result of the rotate/shift `op` on `v`, setting the flags."""
+    c_in = 1 if s.flag(C) else 0
+    if op == "rlc":
+        res, c = (v << 1 | v >> 7) & 0xff, v >> 7
+    elif op == "rl":
+        res, c = (v << 1 | c_in) & 0xff, v >> 7
+    elif op == "rr":
+        res, c = (v >> 1 | c_in << 7), v & 1
+    elif op == "sra":
+        res, c = (v >> 1 | v & 0x80), v & 1
+    elif op == "swap":
+        res, c = (v << 4 | v >> 4) & 0xff, 0
+    elif op == "srl":
+        res, c = v >> 1, v & 1
+    s.set_flags(z=res == 0, n=False, h=False, c=c == 1)
+    return res
+
+def add_sp_e(s, e):
+    sp = s.sp
+    e_signed = e - 256 if e > 127 else e
+    s.set_flags(z=False, n=False, h=(sp & 0xf) + (e & 0xf) > 0xf, c=(sp & 0xff) + e > 0xff)
+    return (sp + e_signed) & 0xffff
+
+def daa(s):
+    a = s.r["a"]
+    c = s.flag(C)
+    if not s.flag(N):
+        if c or a > 0x99:
+            a += 0x60
+            c = True
+        if s.flag(H) or (a & 0xf) > 9:
+            a += 0x06
+    else:
+        if c:
+            a -= 0x60
+        if s.flag(H):
+            a -= 0x06
+    a &= 0xff
+    s.r["a"] = a
+    s.set_flags(z=a == 0, h=False, c=c)
+
+# Each opcode: (bytes before the operands, function executing the rest of the instruction).
+def op_nop(s): pass
+def op_ld_b_c(s): s.r["b"] = s.r["c"]
+def op_ld_hl_n(s):
+    v = s.imm8()
+    s.write(s.get16("hl"), v)
+def op_ld_a_bc(s): s.r["a"] = s.read(s.get16("bc"))
+def op_ldi_hl_a(s):
+    hl = s.get16("hl")
+    s.write(hl, s.r["a"])
+    s.set16("hl", hl + 1)
+def op_ld_bc_nn(s): s.set16("bc", s.imm16())
+def op_ld_nn_sp(s):
+    addr = s.imm16()
+    s.write(addr, s.sp & 0xff)
+    s.write(addr + 1, s.sp >> 8)
+def op_ld_hl_sp_e(s):
+    e = s.imm8()
+    s.internal()
+    s.set16("hl", add_sp_e(s, e))
+def op_ldh_n_a(s):
+    n = s.imm8()
+    s.write(0xff00 | n, s.r["a"])
+def op_push_bc(s):
+    s.internal()
+    s.push(s.get16("bc"))
+def op_pop_af(s): s.set16("af", s.pop())
+def op_add_a_b(s): alu(s, "add", s.r["b"])
+def op_adc_a_n(s): alu(s, "adc", s.imm8())
+def op_sbc_a_hl(s): alu(s, "sbc", s.read(s.get16("hl")))
+def op_cp_e(s): alu(s, "cp", s.r["e"])
+def op_and_n(s): alu(s, "and", s.imm8())
+def op_xor_a(s): alu(s, "xor", s.r["a"])
+def op_daa(s): daa(s)
+def op_cpl(s):
+    s.r["a"] ^= 0xff
+    s.set_flags(n=True, h=True)
+def op_ccf(s): s.set_flags(n=False, h=False, c=not s.flag(C))
+def op_inc_b(s):
+    v = s.r["b"]
+    s.r["b"] = (v + 1) & 0xff
+    s.set_flags(z=s.r["b"] == 0, n=False, h=v & 0xf == 0xf)
+def op_dec_hl_ind(s):
+    hl = s.get16("hl")
+    v = s.read(hl)
+    res = (v - 1) & 0xff
+    s.write(hl, res)
+    s.set_flags(z=res == 0, n=True, h=v & 0xf == 0)
+def op_inc_de(s):
+    s.internal()
+    s.set16("de", s.get16("de") + 1)
+def op_add_hl_sp(s):
+    hl, sp = s.get16("hl"), s.sp
+    s.internal()
+    s.set_flags(n=False, h=(hl & 0xfff) + (sp & 0xfff) > 0xfff, c=hl + sp > 0xffff)
+    s.set16("hl", hl + sp)
+def op_add_sp_e(s):
+    e = s.imm8()
+    s.internal()
+    s.internal()
+    s.sp = add_sp_e(s, e)
+def op_rlca(s):
+    a = s.r["a"]
+    s.r["a"] = (a << 1 | a >> 7) & 0xff
+    s.set_flags(z=False, n=False, h=False, c=a >> 7 == 1)
+def op_rra(s):
+    a = s.r["a"]
+    s.r["a"] = a >> 1 | (0x80 if s.flag(C) else 0)
+    s.set_flags(z=False, n=False, h=False, c=a & 1 == 1)
+def op_jr(s):
+    e = s.imm8()
+    s.internal()
+    s.pc = (s.pc + (e - 256 if e > 127 else e)) & 0xffff
+def op_jr_nz(s):
+    e = s.imm8()
+    if cond(s, "nz"):
+        s.internal()
+        s.pc = (s.pc + (e - 256 if e > 127 else e)) & 0xffff
+def op_jp(s):
+    addr = s.imm16()
+    s.internal()
+    s.pc = addr
+def op_jp_hl(s): s.pc = s.get16("hl")
+def op_call_nz(s):
+    addr = s.imm16()
+    if cond(s, "nz"):
+        s.internal()
+        s.push(s.pc)
+        s.pc = addr
+def op_ret(s):
+    addr = s.pop()
+    s.internal()
+    s.pc = addr
+def op_ret_c(s):
+    s.internal()
+    if cond(s, "c"):
+        addr = s.pop()
+        s.internal()
+        s.pc = addr
+def op_rst_38(s):
+    s.internal()
+    s.push(s.pc)
+    s.pc = 0x38
+def op_di(s): s.ime = 0
+def op_cb(name, reg):
+    def run(s):
+        s.read(s.pc)  # the second opcode byte
+        s.pc = (s.pc + 1) & 0xffff
+        if reg == "hl":
+            hl = s.get16("hl")
+            v = s.read(hl)
+        else:
+            v = s.r[reg]
+        if name.startswith("bit"):
+            bit = int(name[3])
+            s.set_flags(z=(v >> bit) & 1 == 0, n=False, h=True)
+            return
+        if name.startswith("res"):
+            res = v & ~(1 << int(name[3]))
+        elif name.startswith("set"):
+            res = v | 1 << int(name[3])
+        else:
+            res = cb_op(s, name, v)
+        if reg == "hl":
+            s.write(hl, res)
+        else:
+            s.r[reg] = res
+    return run
+
+OPCODES = {
+    "00": ([0x00], op_nop),
+    "41": ([0x41], op_ld_b_c),
+    "36": ([0x36], op_ld_hl_n),
+    "0a": ([0x0a], op_ld_a_bc),
+    "22": ([0x22], op_ldi_hl_a),
+    "01": ([0x01], op_ld_bc_nn),
+    "08": ([0x08], op_ld_nn_sp),
+    "f8": ([0xf8], op_ld_hl_sp_e),
+    "e0": ([0xe0], op_ldh_n_a),
+    "c5": ([0xc5], op_push_bc),
+    "f1": ([0xf1], op_pop_af),
+    "80": ([0x80], op_add_a_b),
+    "ce": ([0xce], op_adc_a_n),
+    "9e": ([0x9e], op_sbc_a_hl),
+    "bb": ([0xbb], op_cp_e),
+    "e6": ([0xe6], op_and_n),
+    "af": ([0xaf], op_xor_a),
+    "27": ([0x27], op_daa),
+    "2f": ([0x2f], op_cpl),
+    "3f": ([0x3f], op_ccf),
+    "04": ([0x04], op_inc_b),
+    "35": ([0x35], op_dec_hl_ind),
+    "13": ([0x13], op_inc_de),
+    "39": ([0x39], op_add_hl_sp),
+    "e8": ([0xe8], op_add_sp_e),
+    "07": ([0x07], op_rlca),
+    "1f": ([0x1f], op_rra),
+    "18": ([0x18], op_jr),
+    "20": ([0x20], op_jr_nz),
+    "c3": ([0xc3], op_jp),
+    "e9": ([0xe9], op_jp_hl),
+    "c4": ([0xc4], op_call_nz),
+    "c9": ([0xc9], op_ret),
+    "d8": ([0xd8], op_ret_c),
+    "ff": ([0xff], op_rst_38),
+    "f3": ([0xf3], op_di),
+    "cb 11": ([0xcb, 0x11], op_cb("rl", "c")),
+    "cb 1e": ([0xcb, 0x1e], op_cb("rr", "hl")),
+    "cb 2f": ([0xcb, 0x2f], op_cb("sra", "a")),
+    "cb 37": ([0xcb, 0x37], op_cb("swap", "a")),
+    "cb 7e": ([0xcb, 0x7e], op_cb("bit7", "hl")),
+    "cb 86": ([0xcb, 0x86], op_cb("res0", "hl")),
+    "cb d8": ([0xcb, 0xd8], op_cb("set3", "b")),
+}
+
+def case(rng, name, i, opcode_bytes, run):
+    # Only the opcode itself is placed before pc; a CB opcode's second byte is read as a cycle.
+    s = State(rng, opcode_bytes[:1])
+    if len(opcode_bytes) > 1:
+        s.mem[s.pc] = opcode_bytes[1]
+        s.initial_mem[s.pc] = opcode_bytes[1]
+    initial = s.snapshot()
+    run(s)
+    s.fetch_next()
+    initial["ram"] = sorted([a, v] for a, v in s.initial_mem.items())
+    final = s.snapshot()
+    final["ram"] = sorted([a, v] for a, v in s.mem.items())
+    return {"name": "%s %04x" % (name, i), "initial": initial, "final": final, "cycles": s.cycles}
+
+def main(out, count):
+    os.makedirs(out, exist_ok=True)
+    for name, (opcode_bytes, run) in OPCODES.items():
+        rng = random.Random(name)
+        cases = [case(rng, name, i, opcode_bytes, run) for i in range(count)]
+        path = os.path.join(out, name.replace(" ", "_") + ".json")
+        with open(path, "w") as f:
+            f.write("[\n" + ",\n".join(json.dumps(c, separators=(",", ":")) for c in cases) + "\n]\n")
+
+main(sys.argv[1], int(sys.argv[2]))
@@ -0,0 +1,141 @@
+//! Checks that the RGBDS source emitted by the disassembler assembles back into the same ROM.
+
+use gabbro::{assemble, Disassembly, SymbolTable};
+use std::{
+    collections::HashMap,
+    env, fs,
+    process::{Command, Stdio},
+};
+
+/// The size of a single ROM bank.
+const BANK_SIZE: usize = 0x4000;
+
+/// A ROM of four banks filled with pseudo-random bytes, so that tracing from the entry points
+/// finds most instructions, branches into every bank, and data in between.
+/// Bank 1 is also switched to bank 2 and jumped into from the entry point.
+fn test_rom() -> Vec<u8> {
+    let mut state = 0x2545f491u32;
+    let mut rom = (0..4 * BANK_SIZE)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state >> 24) as u8
+        })
+        .collect::<Vec<u8>>();
+    rom[0x100..0x108].copy_from_slice(&[
+        0x3e, 0x02, // ld a, 2
+        0xea, 0x00, 0x20, // ld [$2000], a
+        0xc3, 0x00, 0x40, // jp $4000
+    ]);
+    rom
+}
+
+/// Assembles the source files in `files` line by line, and returns the resulting ROM.
+/// Labels are resolved in a first pass that only measures the instructions.
+fn reassemble(files: &[(String, String)]) -> Vec<u8> {
+    let banks = files
+        .iter()
+        .filter(|(name, _)| name.starts_with("bank_"))
+        .map(|(_, src)| src.as_str())
+        .collect::<Vec<&str>>();
+    let mut labels = HashMap::new();
+    assemble_banks(&banks, &mut labels);
+    assemble_banks(&banks, &mut labels)
+}
+
+/// Assembles `banks` in order. Labels are defined in `labels`, and only resolved to the
+/// address of the current instruction while they are not defined yet.
+fn assemble_banks<'a>(banks: &[&'a str], labels: &mut HashMap<&'a str, u16>) -> Vec<u8> {
+    let mut rom = Vec::new();
+    for (bank, src) in banks.iter().enumerate() {
+        assert_eq!(rom.len(), bank * BANK_SIZE, "bank {} is not full", bank);
+        for line in src.lines() {
+            let line = line.trim();
+            let addr = match bank {
+                0 => rom.len() as u16,
+                _ => (BANK_SIZE + rom.len() % BANK_SIZE) as u16,
+            };
+            if line.is_empty() || line.starts_with("SECTION") {
+                continue;
+            }
+            if let Some(label) = line.strip_suffix(':') {
+                labels.insert(label, addr);
+                continue;
+            }
+            if let Some(bytes) = line.strip_prefix("db ") {
+                let bytes = bytes.split(';').next().unwrap().split(',');
+                rom.extend(bytes.map(|byte| {
+                    let byte = byte.trim().strip_prefix('$').unwrap();
+                    u8::from_str_radix(byte, 16).unwrap()
+                }));
+                continue;
+            }
+            // Only the target of a branch can be a label.
+            let (head, target) = line.rsplit_once([' ', ',']).unwrap_or((line, ""));
+            let branch = ["jr", "jp", "call"]
+                .iter()
+                .any(|name| line.starts_with(name));
+            let src = match labels.get(target) {
+                Some(addr) if branch => format!("{} ${:04x}", head, addr),
+                None if branch && target.starts_with(char::is_alphabetic) && target != "hl" => {
+                    format!("{} @", head)
+                }
+                _ => line.to_string(),
+            };
+            let bytes = assemble(&src, addr).unwrap_or_else(|e| panic!("{}: {}", line, e));
+            rom.extend(bytes);
+        }
+    }
+    rom
+}
+
+#[test]
+fn disassembly_reassembles() {
+    let rom = test_rom();
+    let files = Disassembly::new(&rom, &SymbolTable::new(), None).files();
+    let reassembled = reassemble(&files);
+    assert_eq!(reassembled.len(), rom.len());
+    if let Some(offset) = (0..rom.len()).find(|i| reassembled[*i] != rom[*i]) {
+        panic!(
+            "byte {:#07x} is {:#04x}, expected {:#04x}",
+            offset, reassembled[offset], rom[offset]
+        );
+    }
+}
+
+/// Reassembles the disassembly with RGBDS, if `rgbasm` and `rgblink` are installed.
+#[test]
+fn disassembly_reassembles_with_rgbds() {
+    let found = |tool: &str| {
+        Command::new(tool)
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok()
+    };
+    if !found("rgbasm") || !found("rgblink") {
+        println!("rgbasm or rgblink is not installed, skipping");
+        return;
+    }
+    let rom = test_rom();
+    let dir = env::temp_dir().join(format!("gabbro-disasm-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for (name, src) in Disassembly::new(&rom, &SymbolTable::new(), None).files() {
+        fs::write(dir.join(name), src).unwrap();
+    }
+    let run = |tool: &str, args: &[&str]| {
+        let status = Command::new(tool)
+            .args(args)
+            .current_dir(&dir)
+            .status()
+            .unwrap();
+        assert!(status.success(), "{} failed", tool);
+    };
+    run("rgbasm", &["-o", "game.o", "game.asm"]);
+    run("rgblink", &["-o", "game.gb", "game.o"]);
+    let reassembled = fs::read(dir.join("game.gb")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(reassembled == rom, "RGBDS did not reassemble the same ROM");
+}
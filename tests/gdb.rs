@@ -0,0 +1,120 @@
+//! Drives the GDB stub of `gabbro-db` over a localhost connection, like a GDB client would.
+
+use std::{
+    env, fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    process::{Child, Command, Stdio},
+};
+
+/// A ROM that stores `0x42` to `0xc000`, and then loops at `0x0105`.
+fn test_rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x107].copy_from_slice(&[
+        0x3e, 0x42, // ld a, $42
+        0xea, 0x00, 0xc0, // ld [$c000], a
+        0x18, 0xfe, // jr @
+    ]);
+    rom
+}
+
+/// A client of the stub, which kills the server when dropped.
+struct Client {
+    server: Child,
+    stream: TcpStream,
+}
+
+impl Client {
+    /// Starts `gabbro-db` with `rom` as a GDB server, and connects to it.
+    fn connect(rom: &[u8]) -> Self {
+        let path = env::temp_dir().join(format!("gabbro-gdb-{}.gb", std::process::id()));
+        fs::write(&path, rom).unwrap();
+        // Find a free port, and release it for the server.
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut server = Command::new(env!("CARGO_BIN_EXE_gabbro-db"))
+            .arg(&path)
+            .args(["--gdb", &port.to_string()])
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        // The server prints a line once it is listening.
+        let mut line = String::new();
+        BufReader::new(server.stdout.take().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        assert!(line.starts_with("Waiting for a GDB client"), "{}", line);
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        Self { server, stream }
+    }
+
+    /// Sends the packet `data`, and returns the data of the reply after checking its checksum.
+    fn request(&mut self, data: &str) -> String {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes()).unwrap();
+        assert_eq!(
+            self.read_byte(),
+            b'+',
+            "packet {} was not acknowledged",
+            data
+        );
+
+        assert_eq!(self.read_byte(), b'$');
+        let mut reply = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                byte => reply.push(byte),
+            }
+        }
+        let checksum = [self.read_byte(), self.read_byte()];
+        let checksum = u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap();
+        assert_eq!(
+            checksum,
+            checksum_of(&reply),
+            "bad checksum in reply to {}",
+            data
+        );
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(reply).unwrap()
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        let _ = self.server.kill();
+        let _ = self.server.wait();
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+#[test]
+fn gdb_session() {
+    let mut client = Client::connect(&test_rom());
+
+    assert_eq!(client.request("?"), "S05");
+    // Six little-endian registers, ending with `pc`.
+    let regs = client.request("g");
+    assert_eq!(regs.len(), 6 * 4);
+    assert_eq!(&regs[20..], "0001");
+    assert_eq!(client.request("m100,3"), "3e42ea");
+
+    assert_eq!(client.request("Z0,105,1"), "OK");
+    assert_eq!(client.request("c"), "S05");
+    assert_eq!(&client.request("g")[20..], "0501");
+    assert_eq!(client.request("mc000,1"), "42");
+
+    assert_eq!(client.request("D"), "OK");
+}
@@ -0,0 +1,93 @@
+//! Checks that write hooks can change or drop writes, that hooks only see their range and
+//! report the right `PC` for interrupts, and that hooks can be sent to another thread.
+
+use gabbro::{Gameboy, MemAccess, WriteAction};
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+};
+
+/// A ROM that writes `0x11` to `0xc000`, `0xc001` and `0xd000`, and then reads `0xc000`.
+fn test_rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x110].copy_from_slice(&[
+        0x3e, 0x11, // ld a, $11
+        0xea, 0x00, 0xc0, // ld [$c000], a
+        0xea, 0x01, 0xc0, // ld [$c001], a
+        0xea, 0x00, 0xd0, // ld [$d000], a
+        0xfa, 0x00, 0xc0, // ld a, [$c000]
+        0x18, 0xfe, // jr @
+    ]);
+    rom
+}
+
+#[test]
+fn write_hooks_and_ranges() {
+    let mut gameboy = Gameboy::builder(test_rom()).build();
+    let writes = Arc::new(Mutex::new(Vec::new()));
+    let reads = Arc::new(Mutex::new(Vec::new()));
+
+    gameboy.on_write(0xc000..=0xc000, |_| WriteAction::Replace(0x22));
+    gameboy.on_write(0xc001..=0xc001, |_| WriteAction::Block);
+    let log = writes.clone();
+    gameboy.on_write(0xc000..=0xc0ff, move |access: &MemAccess| {
+        log.lock().unwrap().push((access.addr, access.val));
+        WriteAction::Keep
+    });
+    let log = reads.clone();
+    gameboy.on_read(0xc000..=0xc0ff, move |access: &MemAccess| {
+        log.lock()
+            .unwrap()
+            .push((access.addr, access.val, access.pc));
+    });
+
+    let gameboy = thread::spawn(move || {
+        for _ in 0..5 {
+            gameboy.step();
+        }
+        gameboy
+    })
+    .join()
+    .unwrap();
+
+    assert_eq!(gameboy.peek(0xc000), 0x22);
+    assert_eq!(gameboy.peek(0xc001), 0x00);
+    assert_eq!(gameboy.peek(0xd000), 0x11);
+    // The blocked write is dropped before later hooks, and `0xd000` is out of range.
+    assert_eq!(*writes.lock().unwrap(), [(0xc000, 0x22)]);
+    assert_eq!(*reads.lock().unwrap(), [(0xc000, 0x22, 0x010b)]);
+}
+
+#[test]
+fn interrupt_pushes() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x40..0x42].copy_from_slice(&[0x18, 0xfe]); // jr @
+    rom[0x100..0x10b].copy_from_slice(&[
+        0xf3, // di
+        0x3e, 0x01, // ld a, 1
+        0xe0, 0xff, // ldh [$ffff], a
+        0xe0, 0x0f, // ldh [$ff0f], a
+        0xfb, // ei
+        0x00, // nop
+        0x18, 0xfe, // jr @
+    ]);
+    let mut gameboy = Gameboy::builder(rom).build();
+    let writes = Arc::new(Mutex::new(Vec::new()));
+    let log = writes.clone();
+    gameboy.on_write(0xff80..=0xfffe, move |access: &MemAccess| {
+        log.lock()
+            .unwrap()
+            .push((access.addr, access.val, access.pc));
+        WriteAction::Keep
+    });
+
+    for _ in 0..7 {
+        gameboy.step();
+    }
+
+    // The pushes of the interrupt report the address it returns to.
+    assert_eq!(
+        *writes.lock().unwrap(),
+        [(0xfffd, 0x01, 0x0109), (0xfffc, 0x09, 0x0109)]
+    );
+}
@@ -0,0 +1,220 @@
+//! Validates the CPU against SM83 single-step test vectors in the SingleStepTests JSON format.
+//!
+//! A small set of vectors is vendored in `tests/data/sm83`, with one file for a few opcodes of
+//! every group, and always runs. They were generated by `generate.py` in the same directory,
+//! from a reference model of the SM83 that is independent of this emulator.
+//!
+//! The full SingleStepTests `sm83` vectors are not part of this repository, so that test is
+//! ignored by default. Point the `GABBRO_SM83_TESTS` environment variable at a local copy of
+//! the `v1` directory to run them:
+//!
+//! ```sh
+//! GABBRO_SM83_TESTS=path/to/sm83/v1 cargo test --features debug --test sm83 -- --ignored --nocapture
+//! ```
+//!
+//! The vectors were recorded on a CPU that fetches the next opcode during the last cycle of
+//! each instruction. Their initial `pc` points one past the opcode, and their last cycle reads
+//! the next opcode. This CPU fetches the opcode at the start of an instruction instead, so each
+//! case is run from `pc - 1`, and the opcode fetch is moved from the first cycle to the end.
+
+use gabbro::{BusActivity, Cpu, TestMemory};
+use serde_json::Value;
+use std::{env, fs, path::Path};
+
+const TESTS_DIR_VAR: &str = "GABBRO_SM83_TESTS";
+
+/// Opcodes whose behaviour is not emulated yet, and whose test files are skipped.
+const SKIPPED_FILES: [&str; 1] = [
+    // STOP
+    "10.json",
+];
+
+/// The maximum number of failures printed per test file.
+const MAX_REPORTED: usize = 5;
+
+#[test]
+fn sm83_vendored_vectors() {
+    run_dir(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/sm83"));
+}
+
+#[test]
+#[ignore = "needs a local copy of the sm83 test vectors in GABBRO_SM83_TESTS"]
+fn sm83_single_step_tests() {
+    let dir = env::var(TESTS_DIR_VAR)
+        .unwrap_or_else(|_| panic!("{} must point at the sm83 test vectors", TESTS_DIR_VAR));
+    run_dir(Path::new(&dir));
+}
+
+/// Runs all test files in `dir`, and fails if any of their cases fail.
+fn run_dir(dir: &Path) {
+    let mut paths = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", dir.display(), e))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    let mut failed_files = Vec::new();
+    for path in paths {
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        if SKIPPED_FILES.contains(&name.as_str()) {
+            continue;
+        }
+        let failures = run_file(&path);
+        if !failures.is_empty() {
+            println!("{}: {} failed case(s)", name, failures.len());
+            for failure in failures.iter().take(MAX_REPORTED) {
+                println!("  {}", failure);
+            }
+            failed_files.push(name);
+        }
+    }
+    assert!(
+        failed_files.is_empty(),
+        "Failed: {}",
+        failed_files.join(", ")
+    );
+}
+
+/// Runs all test cases in the file at `path`, and returns a description of each failure.
+fn run_file(path: &Path) -> Vec<String> {
+    let json = fs::read_to_string(path).unwrap();
+    let cases: Vec<Value> = serde_json::from_str(&json)
+        .unwrap_or_else(|e| panic!("Failed to parse {}: {}", path.display(), e));
+    cases
+        .iter()
+        .filter_map(|case| run_case(case).err())
+        .collect()
+}
+
+/// Runs a single test case, returning `Err` if the final state does not match.
+fn run_case(case: &Value) -> Result<(), String> {
+    let name = case["name"].as_str().unwrap_or("unnamed");
+    let mut cpu = Cpu::new(TestMemory::new());
+    load_state(&mut cpu, &case["initial"]);
+    // Start at the opcode, which the vectors expect to be fetched already.
+    let pc = cpu.regs().pc().wrapping_sub(1);
+    cpu.regs_mut().set_pc(pc);
+    cpu.step();
+    check_state(&cpu, &case["final"]).map_err(|e| format!("{}: {}", name, e))?;
+    check_cycles(&cpu, &case["cycles"]).map_err(|e| format!("{}: {}", name, e))
+}
+
+fn load_state(cpu: &mut Cpu<TestMemory>, state: &Value) {
+    let reg = |name: &str| state[name].as_u64().unwrap();
+
+    let regs = cpu.regs_mut();
+    regs.set_af((reg("a") << 8 | reg("f")) as u16);
+    regs.set_b(reg("b") as u8);
+    regs.set_c(reg("c") as u8);
+    regs.set_d(reg("d") as u8);
+    regs.set_e(reg("e") as u8);
+    regs.set_h(reg("h") as u8);
+    regs.set_l(reg("l") as u8);
+    regs.set_sp(reg("sp") as u16);
+    regs.set_pc(reg("pc") as u16);
+    cpu.set_ime(reg("ime") != 0);
+
+    let mem = cpu.bus_mut();
+    if let Some(ie) = state["ie"].as_u64() {
+        mem.poke(0xffff, ie as u8);
+    }
+    for (addr, val) in ram(state) {
+        mem.poke(addr, val);
+    }
+}
+
+fn check_state(cpu: &Cpu<TestMemory>, state: &Value) -> Result<(), String> {
+    let regs = cpu.regs();
+    let actual = [
+        ("a", regs.a() as u64),
+        ("f", regs.af() as u64 & 0xff),
+        ("b", regs.b() as u64),
+        ("c", regs.c() as u64),
+        ("d", regs.d() as u64),
+        ("e", regs.e() as u64),
+        ("h", regs.h() as u64),
+        ("l", regs.l() as u64),
+        ("sp", regs.sp() as u64),
+        // The vectors count the fetch of the next opcode.
+        ("pc", regs.pc().wrapping_add(1) as u64),
+        ("ime", cpu.ime() as u64),
+    ];
+    for (name, val) in actual {
+        let expected = state[name].as_u64().unwrap();
+        if val != expected {
+            return Err(format!("{} is {:#x}, expected {:#x}", name, val, expected));
+        }
+    }
+    for (addr, expected) in ram(state) {
+        let val = cpu.bus().peek(addr);
+        if val != expected {
+            return Err(format!(
+                "({:#06x}) is {:#04x}, expected {:#04x}",
+                addr, val, expected
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn check_cycles(cpu: &Cpu<TestMemory>, cycles: &Value) -> Result<(), String> {
+    let expected = cycles
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(parse_cycle)
+        .collect::<Vec<BusActivity>>();
+    // The vectors fetch the next opcode in the last cycle, instead of this opcode in the first.
+    let pc = cpu.regs().pc();
+    let mut actual = cpu.bus().activity()[1..].to_vec();
+    actual.push(BusActivity::Read(pc, cpu.bus().peek(pc)));
+    if actual.len() != expected.len() {
+        return Err(format!(
+            "took {} cycles, expected {}",
+            actual.len(),
+            expected.len()
+        ));
+    }
+    for (i, (actual, expected)) in actual.iter().zip(expected.iter()).enumerate() {
+        if actual != expected {
+            return Err(format!(
+                "cycle {} was {:?}, expected {:?}",
+                i, actual, expected
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Parses a cycle entry, which is either `null` or `[addr, val, "rwm"]` where the last field
+/// contains `r` for a read, `w` for a write, and `-` for neither.
+fn parse_cycle(cycle: &Value) -> BusActivity {
+    let Some([addr, val, kind]) = cycle.as_array().map(Vec::as_slice) else {
+        return BusActivity::Idle;
+    };
+    let (Some(addr), Some(val), Some(kind)) = (addr.as_u64(), val.as_u64(), kind.as_str()) else {
+        return BusActivity::Idle;
+    };
+    if kind.contains('r') {
+        BusActivity::Read(addr as u16, val as u8)
+    } else if kind.contains('w') {
+        BusActivity::Write(addr as u16, val as u8)
+    } else {
+        BusActivity::Idle
+    }
+}
+
+fn ram(state: &Value) -> Vec<(u16, u8)> {
+    state["ram"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| {
+            (
+                entry[0].as_u64().unwrap() as u16,
+                entry[1].as_u64().unwrap() as u8,
+            )
+        })
+        .collect()
+}